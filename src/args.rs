@@ -1,17 +1,492 @@
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
-#[derive(Copy, Clone, Debug)]
+use crate::backend::{notation, Board, Difficulty, SearchLimits, Team, TimeControl, Variant};
+use crate::config::{self, Config};
+use crate::dxp;
+use crate::net;
+use crate::web;
+use crate::frontend::keys::KeyBindings;
+use crate::frontend::{self, Appearance, FrontendKind, Preferences};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mode {
+    Play,
+    Hub,
+    Dxp(DxpArgs),
+    Host(NetArgs),
+    /// Join a hosted game at this address
+    Join(String),
+    /// Watch a hosted game at this address
+    Watch(String),
+    Web(WebArgs),
+    /// Step through the game recorded in this PDN file
+    Replay(PathBuf),
+    /// Set up a position by hand, then play from it
+    Edit,
+    /// Solve the puzzles in this PDN file, or the starter set
+    Puzzles(Option<PathBuf>),
+    /// Serve the JSON API on this address
+    #[cfg(feature = "serde")]
+    Api(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DxpRole {
+    /// Wait for an initiator on this address and play whichever side it asks for
+    Listen(String),
+    /// Initiate a game with the follower at this address
+    Connect(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DxpArgs {
+    pub role: DxpRole,
+    /// Side we play when initiating
+    pub team: Team,
+    /// Time control to propose when initiating: `minutes` for every `moves` moves
+    pub minutes: u16,
+    pub moves: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetArgs {
+    pub addr: String,
+    /// Side played by the host
+    pub team: Team,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebArgs {
+    pub addr: String,
+    /// Side played by the AI, if any
+    pub cpu: Option<Team>,
+}
+
+/// How the backend sets up a game.
+#[derive(Clone, Debug, Default)]
+pub struct GameOptions {
+    pub variant: Variant,
+    /// Position to start from and the side to move; the usual opening if `None`
+    pub position: Option<(Board, Team)>,
+    /// Limits for the AI's search, on top of any clock
+    pub ai_limits: SearchLimits,
+    /// Seed for choosing between equally good AI moves; random if `None`
+    pub seed: Option<u64>,
+    /// File to write the game to in PDN once it's over
+    pub record: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
 pub struct Args {
     pub debug: bool,
+    pub mode: Mode,
+    pub frontend: FrontendKind,
+    /// Address to accept observers on during a local game
+    pub spectators: Option<String>,
+    /// Clock for both players; `None` for untimed games
+    pub time_control: Option<TimeControl>,
+    /// File to copy every log message to
+    pub log_file: Option<String>,
+    pub game: GameOptions,
+    /// Settings the menu starts with
+    pub preferences: Preferences,
+    /// Theme and glyph set the menu starts with
+    pub appearance: Appearance,
+    /// Start playing with `preferences` straight away
+    pub skip_menu: bool,
+    /// Keys for the curses frontend
+    pub bindings: KeyBindings,
+    /// Where to remember the options chosen in the menu
+    pub config_path: Option<PathBuf>,
+}
+/// A local game on the text frontend with every other setting at its default, and no config file
+impl Default for Args {
+    fn default() -> Args {
+        Args {
+            debug: false,
+            mode: Mode::Play,
+            frontend: FrontendKind::Text,
+            spectators: None,
+            time_control: None,
+            log_file: None,
+            game: GameOptions::default(),
+            preferences: Preferences::default(),
+            appearance: Appearance::default(),
+            skip_menu: false,
+            bindings: KeyBindings::default(),
+            config_path: None,
+        }
+    }
+}
+
+/// Options that apply whatever the mode
+fn options() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("debug").long("debug").help("Enable debug logging"),
+        Arg::with_name("frontend")
+            .long("frontend")
+            .takes_value(true)
+            .possible_values(&FrontendKind::variants())
+            .case_insensitive(true)
+            .default_value(frontend::DEFAULT_FRONTEND)
+            .help("User interface to play with"),
+        Arg::with_name("spectators")
+            .long("spectators")
+            .takes_value(true)
+            .value_name("ADDR")
+            .help("Let others watch a local game by connecting to this address (default port 27532)"),
+        Arg::with_name("clock")
+            .long("clock")
+            .takes_value(true)
+            .value_name("TIME")
+            .help("Play with a clock: MINUTES for sudden death, MINUTES+SECONDS for an increment or MINUTES/SECONDS for a delay"),
+        Arg::with_name("log-file")
+            .long("log-file")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Also append every log message, timestamped, to this file"),
+        Arg::with_name("light")
+            .long("light")
+            .takes_value(true)
+            .possible_values(&["human", "cpu"])
+            .case_insensitive(true)
+            .help("Who plays light [default: human]"),
+        Arg::with_name("dark")
+            .long("dark")
+            .takes_value(true)
+            .possible_values(&["human", "cpu"])
+            .case_insensitive(true)
+            .help("Who plays dark [default: cpu]"),
+        Arg::with_name("difficulty")
+            .long("difficulty")
+            .takes_value(true)
+            .possible_values(&Difficulty::variants())
+            .case_insensitive(true)
+            .conflicts_with("depth")
+            .help("How deep the AI searches"),
+        Arg::with_name("depth")
+            .long("depth")
+            .takes_value(true)
+            .value_name("PLIES")
+            .help("Exact depth for the AI to search"),
+        Arg::with_name("think-time")
+            .long("think-time")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .help("Most time the AI may think about each move"),
+        Arg::with_name("variant")
+            .long("variant")
+            .takes_value(true)
+            .possible_values(&Variant::variants())
+            .case_insensitive(true)
+            .help("Rules to play by: casual (optional captures) or american (compulsory captures) [default: casual]"),
+        Arg::with_name("theme")
+            .long("theme")
+            .alias("color-scheme")
+            .takes_value(true)
+            .value_name("NAME")
+            .help("Colours for the board: redblack, whitered, whiteblack, wood or a theme file's name [default: redblack]"),
+        Arg::with_name("glyphs")
+            .long("glyphs")
+            .takes_value(true)
+            .value_name("NAME")
+            .conflicts_with("ascii")
+            .help("Characters for the pieces: fancy, ascii, discs or a theme file's name [default: fancy]"),
+        Arg::with_name("ascii")
+            .long("ascii")
+            .help("Draw pieces with plain ASCII letters, the same as --glyphs ascii"),
+        Arg::with_name("position")
+            .long("position")
+            .takes_value(true)
+            .value_name("FEN")
+            .help("Start from this position in PDN FEN, e.g. B:W18,24,K10:B1-12 (W is light)"),
+        Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("Seed for the AI's choice between equally good moves, to replay a game exactly"),
+        Arg::with_name("record")
+            .long("record")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Write the game to this file in PDN once it's over, to watch again with the replay mode"),
+        Arg::with_name("skip-menu")
+            .long("skip-menu")
+            .help("Start playing straight away instead of showing the menu"),
+        Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Settings file to use instead of $XDG_CONFIG_HOME/checkers/config"),
+    ]
 }
 
+/// The modes besides playing a local game, each with its own arguments
+fn modes() -> Vec<App<'static, 'static>> {
+    #[allow(unused_mut)]
+    let mut modes = vec![
+        SubCommand::with_name("hub")
+            .about("Run headless as an engine speaking the Hub protocol on stdin/stdout"),
+        SubCommand::with_name("dxp")
+            .about("Play the AI against a remote engine using the DamExchange protocol")
+            .arg(Arg::with_name("listen")
+                 .long("listen")
+                 .takes_value(true)
+                 .value_name("ADDR")
+                 .conflicts_with("connect")
+                 .help("Wait for a game request on this address (the default, on port 27531)"))
+            .arg(Arg::with_name("connect")
+                 .long("connect")
+                 .takes_value(true)
+                 .value_name("ADDR")
+                 .help("Send a game request to the engine at this address"))
+            .arg(Arg::with_name("color")
+                 .long("color")
+                 .takes_value(true)
+                 .possible_values(&["light", "dark"])
+                 .case_insensitive(true)
+                 .default_value("light")
+                 .help("Side to play when connecting"))
+            .arg(Arg::with_name("time")
+                 .long("time")
+                 .takes_value(true)
+                 .default_value("10")
+                 .help("Minutes of thinking time to propose when connecting"))
+            .arg(Arg::with_name("moves")
+                 .long("moves")
+                 .takes_value(true)
+                 .default_value("50")
+                 .help("Number of moves in the time control to propose when connecting, or 0 for the whole game")),
+        SubCommand::with_name("host")
+            .about("Host a game for a player on another machine to join")
+            .arg(Arg::with_name("listen")
+                 .long("listen")
+                 .takes_value(true)
+                 .value_name("ADDR")
+                 .help("Address to wait for the other player on (default port 27532)"))
+            .arg(Arg::with_name("color")
+                 .long("color")
+                 .takes_value(true)
+                 .possible_values(&["light", "dark"])
+                 .case_insensitive(true)
+                 .default_value("light")
+                 .help("Side to play")),
+        SubCommand::with_name("join")
+            .about("Join a game hosted on another machine")
+            .arg(Arg::with_name("addr")
+                 .required(true)
+                 .value_name("ADDR")
+                 .help("Address of the host (default port 27532)")),
+        SubCommand::with_name("watch")
+            .about("Watch a game hosted on another machine")
+            .arg(Arg::with_name("addr")
+                 .required(true)
+                 .value_name("ADDR")
+                 .help("Address of the host (default port 27532)")),
+        SubCommand::with_name("replay")
+            .about("Step through a game recorded in a PDN file")
+            .arg(Arg::with_name("file")
+                 .required(true)
+                 .value_name("FILE")
+                 .help("PDN file to read the first game from")),
+        SubCommand::with_name("edit")
+            .about("Set up a position to play from, starting with --position if it's given"),
+        SubCommand::with_name("puzzles")
+            .about("Solve tactics puzzles, from a PDN file or the starter set")
+            .arg(Arg::with_name("file")
+                 .value_name("FILE")
+                 .help("PDN file of puzzles, one game each: a FEN tag for the position and the solution as its moves")),
+        SubCommand::with_name("web")
+            .about("Serve a game to play or watch from a browser")
+            .arg(Arg::with_name("listen")
+                 .long("listen")
+                 .takes_value(true)
+                 .value_name("ADDR")
+                 .default_value(web::DEFAULT_ADDR)
+                 .help("Address to serve the board page on"))
+            .arg(Arg::with_name("cpu")
+                 .long("cpu")
+                 .takes_value(true)
+                 .possible_values(&["light", "dark"])
+                 .case_insensitive(true)
+                 .help("Let the AI play this side")),
+    ];
+    #[cfg(feature = "serde")]
+    modes.push(SubCommand::with_name("api")
+               .about("Serve a JSON API for creating and playing games")
+               .arg(Arg::with_name("listen")
+                    .long("listen")
+                    .takes_value(true)
+                    .value_name("ADDR")
+                    .default_value(web::api::DEFAULT_ADDR)
+                    .help("Address to serve the API on")));
+    modes
+}
+
+/// Arguments for the `checkers` command
 pub fn get_args() -> Args {
     let args = App::new("checkers")
         .version("1.0")
-        // .arg(Arg::with_name("debug").long("debug").help("Enable debug logging"))
+        .args(&options())
+        .subcommands(modes())
         .get_matches();
+    let mode = match args.subcommand() {
+        (name, Some(mode_args)) => parse_mode(name, mode_args),
+        _ => Mode::Play,
+    };
+    make_args(&args, mode)
+}
 
+/// Arguments for `checkers-NAME`, a command of its own for the mode `name` that takes the mode's arguments alongside
+/// the usual options.
+pub fn get_mode_args(name: &str) -> Args {
+    let app = modes().into_iter().find(|mode| mode.get_name() == name).expect("No such mode");
+    let args = app
+        .name(format!("checkers-{}", name))
+        .version("1.0")
+        .args(&options())
+        .get_matches();
+    let mode = parse_mode(name, &args);
+    make_args(&args, mode)
+}
+
+fn parse_mode(name: &str, args: &ArgMatches) -> Mode {
+    match name {
+        "hub" => Mode::Hub,
+        "dxp" => {
+            let role = match args.value_of("connect") {
+                Some(addr) => DxpRole::Connect(with_default_port(addr, dxp::DEFAULT_PORT)),
+                None => DxpRole::Listen(with_default_port(args.value_of("listen").unwrap_or("0.0.0.0"), dxp::DEFAULT_PORT)),
+            };
+            Mode::Dxp(DxpArgs {
+                role,
+                team: team_arg(args),
+                minutes: value_t!(args, "time", u16).unwrap_or_else(|e| e.exit()),
+                moves: value_t!(args, "moves", u16).unwrap_or_else(|e| e.exit()),
+            })
+        },
+        "host" => Mode::Host(NetArgs {
+            addr: with_default_port(args.value_of("listen").unwrap_or("0.0.0.0"), net::DEFAULT_PORT),
+            team: team_arg(args),
+        }),
+        "join" => Mode::Join(with_default_port(args.value_of("addr").unwrap(), net::DEFAULT_PORT)),
+        "web" => Mode::Web(WebArgs {
+            addr: args.value_of("listen").unwrap().to_string(),
+            cpu: args.value_of("cpu").map(parse_team),
+        }),
+        #[cfg(feature = "serde")]
+        "api" => Mode::Api(args.value_of("listen").unwrap().to_string()),
+        "edit" => Mode::Edit,
+        "puzzles" => Mode::Puzzles(args.value_of("file").map(PathBuf::from)),
+        "replay" => Mode::Replay(PathBuf::from(args.value_of("file").unwrap())),
+        "watch" => Mode::Watch(with_default_port(args.value_of("addr").unwrap(), net::DEFAULT_PORT)),
+        _ => Mode::Play,
+    }
+}
+
+/// The rest of the arguments, given the options in `args`
+fn make_args(args: &ArgMatches, mode: Mode) -> Args {
+    let config_path = args.value_of("config").map(PathBuf::from).or_else(config::default_path);
+    let config = match &config_path {
+        Some(path) => Config::load(path).unwrap_or_else(|err| {
+            eprintln!("Ignoring settings in {}: {}", path.display(), err);
+            Config::default()
+        }),
+        None => Config::default(),
+    };
+
+    let appearance = appearance(args, &config.appearance);
     Args {
         debug: args.is_present("debug"),
+        mode,
+        frontend: value_t!(args, "frontend", FrontendKind).unwrap_or_else(|e| e.exit()),
+        spectators: args.value_of("spectators").map(|addr| with_default_port(addr, net::DEFAULT_PORT)),
+        time_control: args.value_of("clock").map(|_| value_t!(args, "clock", TimeControl).unwrap_or_else(|e| e.exit())),
+        log_file: args.value_of("log-file").map(str::to_string),
+        game: game_options(args, &config),
+        preferences: preferences(args, &config.preferences),
+        appearance,
+        skip_menu: args.is_present("skip-menu"),
+        bindings: config.bindings.clone(),
+        config_path,
+    }
+}
+
+/// Game setup from the command line, falling back on `config`
+fn game_options(args: &ArgMatches, config: &Config) -> GameOptions {
+    let position = args.value_of("position").map(|fen| notation::parse_fen(fen).unwrap_or_else(|err| {
+        clap::Error::value_validation_auto(format!("Bad position '{}': {}", fen, err)).exit()
+    }));
+    let depth = match args.value_of("difficulty") {
+        Some(_) => Some(value_t!(args, "difficulty", Difficulty).unwrap_or_else(|e| e.exit()).depth()),
+        None if args.is_present("depth") => Some(value_t!(args, "depth", usize).unwrap_or_else(|e| e.exit())),
+        None => config.ai_limits.depth,
+    };
+    let time = match args.value_of("think-time") {
+        Some(text) => {
+            let secs = value_t!(args, "think-time", f64).unwrap_or_else(|e| e.exit());
+            // Negative, infinite and overlong times can't be a Duration
+            Some(Duration::try_from_secs_f64(secs).unwrap_or_else(|_| {
+                clap::Error::value_validation_auto(format!("Bad think time '{}', expected a number of seconds", text)).exit()
+            }))
+        },
+        None => config.ai_limits.time,
+    };
+
+    GameOptions {
+        variant: match args.value_of("variant") {
+            Some(_) => value_t!(args, "variant", Variant).unwrap_or_else(|e| e.exit()),
+            None => config.variant,
+        },
+        position,
+        ai_limits: SearchLimits{ depth, time },
+        seed: args.value_of("seed").map(|_| value_t!(args, "seed", u64).unwrap_or_else(|e| e.exit())),
+        record: args.value_of("record").map(PathBuf::from),
+    }
+}
+
+/// Theme and glyphs from the command line, falling back on `defaults`.  Names are checked once the themes are
+/// loaded.
+fn appearance(args: &ArgMatches, defaults: &Appearance) -> Appearance {
+    Appearance {
+        theme: args.value_of("theme").map_or_else(|| defaults.theme.clone(), str::to_lowercase),
+        glyphs: match args.value_of("glyphs") {
+            _ if args.is_present("ascii") => "ascii".to_string(),
+            Some(glyphs) => glyphs.to_lowercase(),
+            None => defaults.glyphs.clone(),
+        },
+    }
+}
+
+/// Menu settings from the command line, falling back on `defaults`
+fn preferences(args: &ArgMatches, defaults: &Preferences) -> Preferences {
+    let player = |name, default| match args.value_of(name).map(str::to_lowercase).as_deref() {
+        Some("cpu") => "CPU",
+        Some(_) => "Human",
+        None => default,
+    };
+    Preferences {
+        players: [player("light", defaults.players[0]), player("dark", defaults.players[1])],
+    }
+}
+
+fn team_arg(args: &ArgMatches) -> Team {
+    parse_team(args.value_of("color").unwrap())
+}
+
+fn parse_team(value: &str) -> Team {
+    match value.to_lowercase().as_str() {
+        "dark" => Team::Dark,
+        _ => Team::Light,
+    }
+}
+
+fn with_default_port(addr: &str, port: u16) -> String {
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, port)
     }
 }
@@ -1,5 +1,68 @@
 use super::board::{Board, Move, PieceType, Team};
-use std::i32;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Plies searched (including our own move) when no other limit is given.
+pub const DEFAULT_DEPTH: usize = 5;
+/// Deepest the search will ever go; use as the depth limit to search until stopped.
+pub const MAX_DEPTH: usize = 64;
+
+arg_enum! {
+    /// Preset search depths.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Difficulty {
+        Easy,
+        Medium,
+        Hard,
+    }
+}
+impl Difficulty {
+    pub fn depth(self) -> usize {
+        match self {
+            Difficulty::Easy   => 2,
+            Difficulty::Medium => DEFAULT_DEPTH,
+            Difficulty::Hard   => 8,
+        }
+    }
+}
+
+/// How long `Ai::search` may run.  Whichever limit is reached first ends the search.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+/// Result of one completed iteration of the search.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub moves: Vec<Move>,
+    /// Every turn scoring the same as `moves`, including it, to pick between for variety
+    pub tied: Vec<Vec<Move>>,
+}
+
+struct SearchContext<'a> {
+    nodes: u64,
+    deadline: Option<Instant>,
+    stop: Option<&'a AtomicBool>,
+    can_abort: bool,
+    aborted: bool,
+}
+impl SearchContext<'_> {
+    fn should_abort(&mut self) -> bool {
+        if self.can_abort && !self.aborted {
+            let past_deadline = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            let stopped = self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+            self.aborted = past_deadline || stopped;
+        }
+        self.aborted
+    }
+}
 
 struct Decision {
     pub team: Team,
@@ -43,9 +106,15 @@ impl Decision {
             })
     }
 
-    pub fn score_recursive(&mut self, depth: usize, is_max_player: bool, mut alpha: i32, mut beta: i32) -> i32 {
-        if self.score.is_some() {
-            return self.score.unwrap();
+    fn score_recursive(&mut self, ctx: &mut SearchContext, depth: usize, is_max_player: bool, mut alpha: i32, mut beta: i32) -> i32 {
+        if let Some(score) = self.score {
+            return score;
+        }
+        ctx.nodes += 1;
+        if ctx.should_abort() {
+            // Result is thrown away, this just keeps the tree consistent on the way back up
+            self.score = Some(0);
+            return 0;
         }
 
         // println!("{:width$}score_recursive: depth: {}, team: {:?}, max player: {}, alpha: {}, beta: {}",
@@ -63,7 +132,7 @@ impl Decision {
         }
         for d in &mut enemy_decisions {
             // println!("{:width$}score_recursive scoring: {:?} {:?}", "", d.team, d.moves, width=5-depth);
-            d.score_recursive(depth - 1, !is_max_player, alpha, beta);
+            d.score_recursive(ctx, depth - 1, !is_max_player, alpha, beta);
         }
 
         if is_max_player {
@@ -97,28 +166,101 @@ impl Decision {
     }
 }
 
+/// Alpha-beta searcher playing for `team`.
 pub struct Ai {
     pub team: Team,
 }
 impl Ai {
+    /// Best turn at `DEFAULT_DEPTH`, hop by hop, or nothing if `team` can't move.
     pub fn get_next_moves(&self, board: Board) -> Vec<Move> {
-        // println!("----------------------------------------");
-        const MAX_DEPTH: usize = 4;
-        // const MAX_DEPTH: usize = 2;
-        let mut my_decisions = Self::_get_possible_decisions(self.team, board);
+        let limits = SearchLimits{ depth: Some(DEFAULT_DEPTH), time: None };
+        match self.search(board, limits, None, |_| ()) {
+            Some(info) => info.moves,
+            None => Vec::new(),
+        }
+    }
+
+    /// Whether to take a draw offered by the other side, who is to move: yes unless we're at least a man ahead after
+    /// a short look at the position.
+    pub fn accepts_draw(&self, board: &Board) -> bool {
+        const LOOKAHEAD: usize = 3; // Odd, so the leaves are scored from the offerer's side
+        const WINNING_MARGIN: i32 = 10; // One man
+
+        let offerer = Ai{ team: self.team.other() };
+        match offerer.search(board.clone(), SearchLimits{ depth: Some(LOOKAHEAD), time: None }, None, |_| ()) {
+            Some(info) => info.score.saturating_neg() < WINNING_MARGIN,
+            None => false, // They can't move, so they've lost anyway
+        }
+    }
+
+    /// Iterative deepening search, reporting each completed depth to `on_info`.
+    ///
+    /// Stops at the depth limit, when the time limit runs out or when `stop` is set, whichever comes first, and
+    /// returns the deepest completed result.  With no limits at all, searches to `DEFAULT_DEPTH`.
+    pub fn search<F: FnMut(&SearchInfo)>(&self, board: Board, limits: SearchLimits, stop: Option<&AtomicBool>, mut on_info: F) -> Option<SearchInfo> {
+        let start = Instant::now();
+        let max_depth = match (limits.depth, limits.time) {
+            (Some(depth), _) => depth.clamp(1, MAX_DEPTH),
+            (None, Some(_)) => MAX_DEPTH,
+            (None, None) => DEFAULT_DEPTH,
+        };
+        let mut ctx = SearchContext {
+            nodes: 0,
+            // A time too long to add is no deadline at all
+            deadline: limits.time.and_then(|time| start.checked_add(time)),
+            stop,
+            can_abort: false, // Always finish the first iteration so there is something to play
+            aborted: false,
+        };
+
+        let mut best: Option<SearchInfo> = None;
+        for depth in 1..=max_depth {
+            let (decision, tied) = match Self::search_depth(self.team, board.clone(), depth, &mut ctx) {
+                Some(result) => result,
+                None => break, // No legal moves
+            };
+            if ctx.aborted {
+                break;
+            }
+            let info = SearchInfo {
+                depth,
+                score: decision.score.unwrap(),
+                nodes: ctx.nodes,
+                elapsed: start.elapsed(),
+                moves: decision.moves,
+                tied,
+            };
+            on_info(&info);
+            let decided = info.score == i32::MAX || info.score == i32::MIN;
+            best = Some(info);
+            ctx.can_abort = true;
+            if decided || ctx.should_abort() {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Best decision at `depth`, with the moves of every decision scoring the same.
+    fn search_depth(team: Team, board: Board, depth: usize, ctx: &mut SearchContext) -> Option<(Decision, Vec<Vec<Move>>)> {
+        let mut my_decisions = Self::_get_possible_decisions(team, board);
         for d in &mut my_decisions {
             // println!("ROOT scoring: {:?} {:?}", d.team, d.moves);
-            d.score_recursive(MAX_DEPTH, true, i32::MIN, i32::MAX);
+            d.score_recursive(ctx, depth - 1, true, i32::MIN, i32::MAX);
         }
         my_decisions.sort_by_key(|d| d.score);
 
         // let dec = my_decisions.last().unwrap();
         // println!("FINAL SCORE: {} ({:?})", dec.score.unwrap(), dec.moves);
 
-        match my_decisions.last() {
-            Some(decision) => decision.moves.clone(),
-            None => Vec::new(),
-        }
+        let best = my_decisions.pop()?;
+        let mut tied: Vec<Vec<Move>> = my_decisions.into_iter()
+            .filter(|d| d.score == best.score)
+            .map(|d| d.moves)
+            .collect();
+        tied.push(best.moves.clone());
+        Some((best, tied))
     }
 
     fn _get_possible_decisions(team: Team, board: Board) -> Vec<Decision> {
@@ -127,16 +269,16 @@ impl Ai {
         // println!("VALID MOVES: {:?}\r", moves);
         for mv in moves {
             let mut new_board = board.clone();
-            new_board.apply_move(&mv);
+            let crowned = new_board.apply_move(&mv);
 
             if mv.is_jump() {
                 // Chain jumps
                 // println!("processing jump {}\r", mv);
-                result.append(&mut Self::_process_jump(team, vec![mv], new_board, &mv));
+                result.append(&mut Self::_process_jump(team, vec![mv], new_board, &mv, crowned));
             } else {
                 // println!("processing normal move {}\r", mv);
                 result.push(Decision {
-                    team: team,
+                    team,
                     moves: vec![mv],
                     board_state: new_board,
                     score: None,
@@ -147,25 +289,56 @@ impl Ai {
         result
     }
 
-    fn _process_jump(team: Team, current_path: Vec<Move>, board: Board, jump: &Move) -> Vec<Decision> {
-        let mut result = vec![Decision {
-            team: team,
-            moves: current_path.clone(),
-            board_state: board.clone(),
-            score: None,
-        }];
-        let jumps = board.get_valid_jumps_for_piece_at(&jump.to);
+    fn _process_jump(team: Team, current_path: Vec<Move>, board: Board, jump: &Move, crowned: bool) -> Vec<Decision> {
+        let mut result = Vec::new();
+        if !board.must_continue_jump(&jump.to, crowned) {
+            result.push(Decision {
+                team,
+                moves: current_path.clone(),
+                board_state: board.clone(),
+                score: None,
+            });
+        }
+        let jumps = board.get_continuing_jumps(&jump.to, crowned);
         // println!("filtering... {:?}", jumps);
         for jump in jumps {
             // println!("processing recursive jump {}\r", jump);
             let mut new_board = board.clone();
-            new_board.apply_move(&jump);
+            let crowned = new_board.apply_move(&jump);
             // Keep chaining
             let mut next_path = current_path.clone(); // TODO is there a one-liner for this?
             next_path.push(jump);
-            result.append(&mut Self::_process_jump(team, next_path, new_board, &jump));
+            result.append(&mut Self::_process_jump(team, next_path, new_board, &jump, crowned));
         }
 
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Piece, Square};
+
+    #[test]
+    fn test_accepts_draw() {
+        let dark = Ai{ team: Team::Dark };
+        assert!(dark.accepts_draw(&Board::new()));
+
+        // Two kings against one: no thanks
+        let mut board = Board::empty();
+        board.place_piece(Square{ x: 1, y: 0 }, Piece{ team: Team::Dark, piece_type: PieceType::King });
+        board.place_piece(Square{ x: 5, y: 0 }, Piece{ team: Team::Dark, piece_type: PieceType::King });
+        board.place_piece(Square{ x: 2, y: 7 }, Piece{ team: Team::Light, piece_type: PieceType::King });
+        assert!(!dark.accepts_draw(&board));
+    }
+
+    #[test]
+    fn test_tied_moves() {
+        // Every opening move is worth the same one ply deep
+        let light = Ai{ team: Team::Light };
+        let info = light.search(Board::new(), SearchLimits{ depth: Some(1), time: None }, None, |_| ()).unwrap();
+        assert_eq!(info.tied.len(), Board::new().get_all_valid_moves(Team::Light).len());
+        assert!(info.tied.contains(&info.moves));
+    }
+}
@@ -1,8 +1,14 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::notation;
+
 use std::collections::HashMap;
 use std::fmt;
 use std::ops;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Team {
     Light,
     Dark,
@@ -16,16 +22,19 @@ impl Team {
     }
 }
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PieceType {
     Man,
     King,
 }
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Piece {
     pub team: Team,
     pub piece_type: PieceType,
 }
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Square {
     pub x: i8,
     pub y: i8,
@@ -42,6 +51,7 @@ impl fmt::Display for Square {
     }
 }
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Move {
     pub from: Square,
     pub to: Square,
@@ -55,23 +65,106 @@ impl Move {
     pub fn is_jump(&self) -> bool {
         (self.from.x - self.to.x).abs() > 1
     }
+
+    pub fn jumped_square(&self) -> Option<Square> {
+        if self.is_jump() {
+            Some(Square{ x: (self.from.x + self.to.x) / 2, y: (self.from.y + self.to.y) / 2 })
+        } else {
+            None
+        }
+    }
+}
+
+arg_enum! {
+    /// Rule sets.  They differ in how strictly captures are enforced.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Variant {
+        // Captures are optional and a jump chain can stop after any hop
+        Casual,
+        // Captures are compulsory and a jump chain must be finished
+        American,
+    }
+}
+impl Variant {
+    /// Side that moves first from the starting position: Dark in American checkers, Light otherwise
+    pub fn first_team(self) -> Team {
+        match self {
+            Variant::Casual   => Team::Light,
+            Variant::American => Team::Dark,
+        }
+    }
+}
+#[allow(clippy::derivable_impls)] // arg_enum! doesn't pass #[default] through
+impl Default for Variant {
+    fn default() -> Variant {
+        Variant::Casual
+    }
 }
 
 type _Row = [Option<Piece>; 8];
 type _Board = [_Row; 8];
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "PlacedPieces", from = "PlacedPieces"))]
+/// An 8x8 position.  Dark starts on rows 0-2 and moves down the board, Light starts on rows 5-7 and moves up; only
+/// squares with `x + y` odd are played on.
 pub struct Board {
     pieces: HashMap<Square, Piece>,
+    variant: Variant,
+}
+
+/// Serialised form of a `Board`: its pieces in reading order, from the top left.  (JSON can't key a map by square.)
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct PlacedPieces {
+    pieces: Vec<PlacedPiece>,
+    #[serde(default)]
+    variant: Variant,
+}
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct PlacedPiece {
+    square: Square,
+    #[serde(flatten)]
+    piece: Piece,
+}
+#[cfg(feature = "serde")]
+impl From<Board> for PlacedPieces {
+    fn from(board: Board) -> PlacedPieces {
+        let mut pieces: Vec<PlacedPiece> = board.pieces
+            .into_iter()
+            .map(|(square, piece)| PlacedPiece{ square, piece })
+            .collect();
+        pieces.sort_by_key(|placed| (placed.square.y, placed.square.x));
+        PlacedPieces { pieces, variant: board.variant }
+    }
+}
+#[cfg(feature = "serde")]
+impl From<PlacedPieces> for Board {
+    fn from(placed: PlacedPieces) -> Board {
+        Board {
+            pieces: placed.pieces.into_iter().map(|placed| (placed.square, placed.piece)).collect(),
+            variant: placed.variant,
+        }
+    }
 }
 // pub type Board = _Board;
 
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
+    }
+}
+
 impl Board {
     pub const SIZE: i8 = 8;
+    /// The starting position.
     pub fn new() -> Board {
         use Team::*;
         let mut pieces = HashMap::new();
         let mut add_piece = |x, y, team| {
-            pieces.insert(Square{x, y}, Piece{ team: team, piece_type: PieceType::Man });
+            pieces.insert(Square{x, y}, Piece{ team, piece_type: PieceType::Man });
         };
 
         add_piece(1, 0, Dark);
@@ -100,13 +193,80 @@ impl Board {
         add_piece(4, 7, Light);
         add_piece(6, 7, Light);
 
-        Board { pieces: pieces }
+        Board { pieces, variant: Variant::default() }
+    }
+
+    /// A board with no pieces, for setting up positions with `place_piece`.
+    pub fn empty() -> Board {
+        Board { pieces: HashMap::new(), variant: Variant::default() }
+    }
+
+    /// The same position played under `variant`'s rules.
+    pub fn with_variant(mut self, variant: Variant) -> Board {
+        self.variant = variant;
+        self
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Hops the piece that just jumped to `square` can go on with.  Under `Variant::American` a man `crowned` by the
+    /// jump stops there, so it has none.
+    pub fn get_continuing_jumps(&self, square: &Square, crowned: bool) -> Vec<Move> {
+        if crowned && self.variant == Variant::American {
+            return Vec::new();
+        }
+        self.get_valid_jumps_for_piece_at(square)
+    }
+
+    /// Whether the piece that just jumped to `square`, and was `crowned` doing so or not, has to keep going.
+    pub fn must_continue_jump(&self, square: &Square, crowned: bool) -> bool {
+        self.variant == Variant::American && !self.get_continuing_jumps(square, crowned).is_empty()
+    }
+
+    pub fn place_piece(&mut self, square: Square, piece: Piece) {
+        self.pieces.insert(square, piece);
+    }
+
+    pub fn remove_piece(&mut self, square: &Square) -> Option<Piece> {
+        self.pieces.remove(square)
+    }
+
+    /// Everything stopping a game starting here with `to_move` to move, or empty if it can.  Positions set up by hand
+    /// can have too many pieces, men that should have been crowned or a side that has already lost.
+    pub fn problems(&self, to_move: Team) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut squares: Vec<(&Square, &Piece)> = self.pieces.iter().collect();
+        squares.sort_by_key(|(square, _)| (square.y, square.x));
+        for (square, piece) in squares {
+            let name = notation::square_number(square).map_or_else(|| format!("({})", square), |n| n.to_string());
+            let crowning_row = if piece.team == Team::Light { 0 } else { Self::SIZE - 1 };
+            if notation::square_number(square).is_none() {
+                problems.push(format!("{:?} piece on light square {}", piece.team, name));
+            } else if piece.piece_type == PieceType::Man && square.y == crowning_row {
+                problems.push(format!("{:?} man on {} should have been crowned", piece.team, name));
+            }
+        }
+        for team in [Team::Light, Team::Dark] {
+            match self.pieces_alive(team) {
+                0 => problems.push(format!("{:?} has no pieces", team)),
+                count if count > 12 => problems.push(format!("{:?} has {} pieces, more than the 12 it starts with", team, count)),
+                _ => (),
+            }
+        }
+        if self.pieces_alive(to_move) > 0 && self.get_all_valid_turns(to_move).is_empty() {
+            problems.push(format!("{:?} is to move but can't", to_move));
+        }
+        problems
     }
 
     pub fn pieces_alive(&self, team: Team) -> usize {
         self.pieces.values().filter(|piece| piece.team == team).count()
     }
 
+    /// True once either side has no pieces left.  (A side with pieces but no legal moves has also lost, but this
+    /// doesn't check for that.)
     pub fn game_over(&self) -> bool {
         let (light_alive, dark_alive) = self.pieces.values().fold((false, false), |result, piece| {
             match piece.team {
@@ -121,15 +281,6 @@ impl Board {
         self.pieces.get(square)
     }
 
-    fn find_piece_square(&self, piece: &Piece) -> &Square {
-        for (square, other_piece) in &self.pieces {
-            if piece == other_piece {
-                return &square;
-            }
-        }
-        panic!("Could not find square for piece {:?}", piece);
-    }
-
     fn square_occupied(&self, s: &Square) -> bool {
         self.pieces.contains_key(s)
     }
@@ -174,16 +325,6 @@ impl Board {
         self._can_step(from, to, 2) && piece.team != between_piece.team
     }
 
-    fn can_move(&self, from: &Square, to: &Square) -> bool {
-        self.can_step(from, to) || self.can_jump(from, to)
-    }
-
-    pub fn get_valid_moves_for_piece(&self, piece: &Piece) -> Vec<Move> {
-        let square = self.find_piece_square(&piece);
-
-        self.get_valid_moves_for_piece_at(square)
-    }
-
     fn _get_valid_steps_for_piece_at(&self, square: &Square, is_jump: bool) -> Vec<Move> {
         let mut steps = Vec::new();
         let delta = if is_jump { 2 } else { 1 };
@@ -193,7 +334,7 @@ impl Board {
                 let to = square + (*dx, *dy);
                 if (!is_jump && self.can_step(square, &to))
                 || ( is_jump && self.can_jump(square, &to)) {
-                    steps.push(Move{ from: *square, to: to });
+                    steps.push(Move{ from: *square, to });
                 }
             }
         }
@@ -209,25 +350,73 @@ impl Board {
         self._get_valid_steps_for_piece_at(square, true)
     }
 
+    /// Moves the piece on `square` can start a turn with.  Under `Variant::American` that's only its jumps if any
+    /// piece on its side can capture.
     pub fn get_valid_moves_for_piece_at(&self, square: &Square) -> Vec<Move> {
-        let mut moves = self.get_valid_steps_for_piece_at(&square);
-        moves.append(&mut self.get_valid_jumps_for_piece_at(&square));
+        let mut moves = self.get_valid_jumps_for_piece_at(square);
+        let must_capture = match self.pieces.get(square) {
+            Some(piece) => self.must_capture(piece.team),
+            None => false,
+        };
+        if !must_capture {
+            moves.splice(0..0, self.get_valid_steps_for_piece_at(square));
+        }
 
         moves
     }
 
+    /// Every single step or hop `team` could start a turn with.
     pub fn get_all_valid_moves(&self, team: Team) -> Vec<Move> {
-        let mut moves = Vec::new();
-        for square in self.pieces.keys() {
-            match self.pieces.get(square) {
-                Some(p) if p.team == team => {
-                    moves.append(&mut self.get_valid_moves_for_piece_at(square));
-                },
-                _ => (),
+        let mut steps = Vec::new();
+        let mut jumps = Vec::new();
+        for (square, piece) in &self.pieces {
+            if piece.team == team {
+                steps.append(&mut self.get_valid_steps_for_piece_at(square));
+                jumps.append(&mut self.get_valid_jumps_for_piece_at(square));
             }
         }
+        if self.variant == Variant::American && !jumps.is_empty() {
+            return jumps;
+        }
 
-        moves
+        steps.append(&mut jumps);
+        steps
+    }
+
+    fn must_capture(&self, team: Team) -> bool {
+        self.variant == Variant::American && self.pieces.iter()
+            .any(|(square, piece)| piece.team == team && !self.get_valid_jumps_for_piece_at(square).is_empty())
+    }
+
+    /// Every turn `team` could take, with jump chains expanded hop by hop.  A chain may stop after any hop.
+    pub fn get_all_valid_turns(&self, team: Team) -> Vec<Vec<Move>> {
+        let mut turns = Vec::new();
+        for mv in self.get_all_valid_moves(team) {
+            if mv.is_jump() {
+                self.append_jump_chains(vec![mv], &mut turns);
+            } else {
+                turns.push(vec![mv]);
+            }
+        }
+
+        turns
+    }
+
+    fn append_jump_chains(&self, path: Vec<Move>, turns: &mut Vec<Vec<Move>>) {
+        let mut board = self.clone();
+        let mut crowned = false;
+        for mv in &path {
+            crowned = board.apply_move(mv);
+        }
+        let last = *path.last().unwrap();
+        if !board.must_continue_jump(&last.to, crowned) {
+            turns.push(path.clone());
+        }
+        for jump in board.get_continuing_jumps(&last.to, crowned) {
+            let mut next_path = path.clone();
+            next_path.push(jump);
+            self.append_jump_chains(next_path, turns);
+        }
     }
 
     pub fn get_pieces(&self) -> &HashMap<Square, Piece> {
@@ -238,22 +427,25 @@ impl Board {
     //     &self.0
     // }
 
-    pub fn apply_move(&mut self, m: &Move) {
+    /// Moves a piece, removing anything it jumps and crowning it on the far row.  The move isn't checked for
+    /// legality, but there must be a piece on `m.from`.  Returns whether a man was crowned.
+    pub fn apply_move(&mut self, m: &Move) -> bool {
         let mut piece = self.pieces.remove(&m.from).unwrap();
 
         // Jump
-        if m.is_jump() {
-            let between = Square{ x: (m.from.x + m.to.x) / 2, y: (m.from.y + m.to.y) / 2 };
+        if let Some(between) = m.jumped_square() {
             self.pieces.remove(&between);
         }
 
         // Promotion
-        if (piece.team == Team::Light && m.to.y == 0)
-        || (piece.team == Team::Dark && m.to.y == Self::SIZE - 1) {
+        let crowned = piece.piece_type == PieceType::Man
+            && ((piece.team == Team::Light && m.to.y == 0) || (piece.team == Team::Dark && m.to.y == Self::SIZE - 1));
+        if crowned {
             piece.piece_type = PieceType::King;
         }
 
         self.pieces.insert(m.to, piece);
+        crowned
     }
 }
 
@@ -371,11 +563,80 @@ mod tests {
         add_piece(2, 2, Team::Light); // 4     B
         add_piece(5, 3, Team::Light); // 5   B
 
-        let board = Board {pieces: pieces};
+        let board = Board { pieces, variant: Variant::Casual };
 
         assert!(!board.can_jump(&Square{ x: 4, y: 4 }, &Square{ x: 2, y: 2 })); // Space occupied
         assert!(!board.can_jump(&Square{ x: 4, y: 4 }, &Square{ x: 2, y: 6 })); // Can't jump over own piece
         assert!( board.can_jump(&Square{ x: 4, y: 4 }, &Square{ x: 6, y: 2 }));
         assert!(!board.can_jump(&Square{ x: 4, y: 4 }, &Square{ x: 6, y: 6 })); // No piece to jump over
     }
+
+    #[test]
+    fn test_american_rules() {
+        let man = |team| Piece{ team, piece_type: PieceType::Man };
+        let mut casual = Board::empty();
+        casual.place_piece(Square{ x: 2, y: 5 }, man(Team::Light));
+        casual.place_piece(Square{ x: 6, y: 5 }, man(Team::Light));
+        casual.place_piece(Square{ x: 3, y: 4 }, man(Team::Dark));
+        casual.place_piece(Square{ x: 5, y: 2 }, man(Team::Dark));
+        let american = casual.clone().with_variant(Variant::American);
+
+        let jump = Move{ from: Square{ x: 2, y: 5 }, to: Square{ x: 4, y: 3 } };
+        let second_jump = Move{ from: jump.to, to: Square{ x: 6, y: 1 } };
+        assert_eq!(casual.get_all_valid_moves(Team::Light).len(), 4);
+        assert_eq!(american.get_all_valid_moves(Team::Light), vec![jump]);
+        assert!(american.get_valid_moves_for_piece_at(&Square{ x: 6, y: 5 }).is_empty());
+
+        assert_eq!(casual.get_all_valid_turns(Team::Light).len(), 5);
+        assert_eq!(american.get_all_valid_turns(Team::Light), vec![vec![jump, second_jump]]);
+
+        // A man crowned partway through a jump stops there under American rules, even with more to take
+        let mut crowning = Board::empty();
+        crowning.place_piece(Square{ x: 5, y: 2 }, man(Team::Light));
+        crowning.place_piece(Square{ x: 4, y: 1 }, man(Team::Dark));
+        crowning.place_piece(Square{ x: 2, y: 1 }, man(Team::Dark));
+        let american = crowning.clone().with_variant(Variant::American);
+        let jump = Move{ from: Square{ x: 5, y: 2 }, to: Square{ x: 3, y: 0 } };
+        let second_jump = Move{ from: jump.to, to: Square{ x: 1, y: 2 } };
+        assert_eq!(american.get_all_valid_turns(Team::Light), vec![vec![jump]]);
+        assert!(crowning.get_all_valid_turns(Team::Light).contains(&vec![jump, second_jump]));
+
+        let mut board = american.clone();
+        assert!(board.apply_move(&jump));
+        assert!(!board.must_continue_jump(&jump.to, true));
+        assert!(board.get_continuing_jumps(&jump.to, true).is_empty());
+        assert!(!board.apply_move(&Move{ from: jump.to, to: Square{ x: 4, y: 1 } }));
+    }
+
+    #[test]
+    fn test_problems() {
+        let man = |team| Piece{ team, piece_type: PieceType::Man };
+        assert!(Board::new().problems(Team::Light).is_empty());
+
+        let mut board = Board::empty();
+        assert_eq!(board.problems(Team::Light), ["Light has no pieces", "Dark has no pieces"]);
+        board.place_piece(Square{ x: 1, y: 0 }, man(Team::Light));
+        board.place_piece(Square{ x: 0, y: 7 }, man(Team::Dark));
+        board.place_piece(Square{ x: 3, y: 4 }, Piece{ team: Team::Dark, piece_type: PieceType::King });
+        assert_eq!(board.problems(Team::Dark), ["Light man on 1 should have been crowned",
+                                                "Dark man on 29 should have been crowned"]);
+        assert_eq!(board.problems(Team::Light), ["Light man on 1 should have been crowned",
+                                                 "Dark man on 29 should have been crowned",
+                                                 "Light is to move but can't"]);
+
+        let mut board = Board::new();
+        board.place_piece(Square{ x: 1, y: 4 }, man(Team::Dark));
+        assert_eq!(board.problems(Team::Light), ["Dark has 13 pieces, more than the 12 it starts with"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut board = Board::new();
+        board.place_piece(Square{ x: 3, y: 4 }, Piece{ team: Team::Dark, piece_type: PieceType::King });
+
+        let json = serde_json::to_value(&board).unwrap();
+        assert_eq!(json["pieces"][0], serde_json::json!({ "square": { "x": 1, "y": 0 }, "team": "Dark", "piece_type": "Man" }));
+        assert_eq!(serde_json::from_value::<Board>(json).unwrap(), board);
+    }
 }
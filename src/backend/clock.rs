@@ -0,0 +1,283 @@
+//! Chess clocks.  The backend's `Clock` is authoritative; frontends get a `ClockState` at the start of every turn and
+//! run their own copy from it just for display.
+
+use super::board::Team;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Time per player and what, if anything, they get back for each move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TimeControl {
+    SuddenDeath{ base: Duration },
+    /// `increment` is added after every move
+    Fischer{ base: Duration, increment: Duration },
+    /// The first `delay` of every move is free
+    Bronstein{ base: Duration, delay: Duration },
+}
+impl TimeControl {
+    pub fn base(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath{ base }
+            | TimeControl::Fischer{ base, .. }
+            | TimeControl::Bronstein{ base, .. } => base,
+        }
+    }
+
+    /// Time a player can count on getting back for each move
+    pub fn bonus(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath{ .. } => Duration::from_secs(0),
+            TimeControl::Fischer{ increment, .. } => increment,
+            TimeControl::Bronstein{ delay, .. } => delay,
+        }
+    }
+}
+/// `MINUTES` for sudden death, `MINUTES+SECONDS` for a Fischer increment or `MINUTES/SECONDS` for a Bronstein delay,
+/// e.g. `5`, `3+2` or `10/5`.
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TimeControl, String> {
+        let parse = |text: &str, scale: f64| match text.trim().parse::<f64>() {
+            Ok(value) if value >= 0.0 => Duration::try_from_secs_f64(value * scale)
+                .map_err(|_| format!("time '{}' is too long", text.trim())),
+            _ => Err(format!("bad time control '{}', expected e.g. 5, 3+2 or 10/5", text)),
+        };
+        let control = if let Some((base, increment)) = text.split_once('+') {
+            TimeControl::Fischer{ base: parse(base, 60.0)?, increment: parse(increment, 1.0)? }
+        } else if let Some((base, delay)) = text.split_once('/') {
+            TimeControl::Bronstein{ base: parse(base, 60.0)?, delay: parse(delay, 1.0)? }
+        } else {
+            TimeControl::SuddenDeath{ base: parse(text, 60.0)? }
+        };
+        if control.base() == Duration::from_secs(0) {
+            return Err("time control needs some time on the clock".to_string());
+        }
+        Ok(control)
+    }
+}
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.base().as_secs_f64() / 60.0;
+        match self {
+            TimeControl::SuddenDeath{ .. } => write!(f, "{} min", minutes),
+            TimeControl::Fischer{ increment, .. } => write!(f, "{} min + {} s", minutes, increment.as_secs_f64()),
+            TimeControl::Bronstein{ delay, .. } => write!(f, "{} min, {} s delay", minutes, delay.as_secs_f64()),
+        }
+    }
+}
+
+/// Snapshot of a `Clock` for sending to frontends.  `remaining` is indexed Light then Dark.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClockState {
+    pub control: TimeControl,
+    pub remaining: [Duration; 2],
+    pub running: Option<Team>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    running: Option<(Team, Instant)>,
+}
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            remaining: [control.base(); 2],
+            running: None,
+        }
+    }
+
+    /// Carries on from a snapshot, as though the running side's clock was started just now.
+    pub fn resume(state: &ClockState) -> Clock {
+        Clock {
+            control: state.control,
+            remaining: state.remaining,
+            running: state.running.map(|team| (team, Instant::now())),
+        }
+    }
+
+    pub fn state(&self) -> ClockState {
+        ClockState {
+            control: self.control,
+            remaining: [self.remaining(Team::Light), self.remaining(Team::Dark)],
+            running: self.running(),
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn running(&self) -> Option<Team> {
+        self.running.map(|(team, _)| team)
+    }
+
+    /// Starts `team`'s clock, stopping the other one first.  Does nothing if it's already running.
+    pub fn start(&mut self, team: Team) {
+        if self.running() != Some(team) {
+            self.stop();
+            self.running = Some((team, Instant::now()));
+        }
+    }
+
+    /// Stops whichever clock is running, crediting any increment.  Returns the team whose flag fell, if any.
+    pub fn stop(&mut self) -> Option<Team> {
+        let (team, started) = self.running.take()?;
+        let left = self.remaining_after(team, started.elapsed());
+        self.remaining[index(team)] = left;
+        if left == Duration::from_secs(0) {
+            return Some(team);
+        }
+        if let TimeControl::Fischer{ increment, .. } = self.control {
+            self.remaining[index(team)] = left.saturating_add(increment);
+        }
+        None
+    }
+
+    /// Time left on `team`'s clock, not counting any delay still to run out this move.
+    pub fn remaining(&self, team: Team) -> Duration {
+        match self.running {
+            Some((running, started)) if running == team => self.remaining_after(team, started.elapsed()),
+            _ => self.remaining[index(team)],
+        }
+    }
+
+    /// How long until the running side's flag falls.
+    pub fn time_to_flag(&self) -> Option<Duration> {
+        let (team, started) = self.running?;
+        let delay = match self.control {
+            TimeControl::Bronstein{ delay, .. } => delay,
+            _ => Duration::from_secs(0),
+        };
+        Some(self.remaining[index(team)].saturating_add(delay).saturating_sub(started.elapsed()))
+    }
+
+    pub fn flagged(&self) -> Option<Team> {
+        match self.time_to_flag() {
+            Some(left) if left == Duration::from_secs(0) => self.running(),
+            _ => None,
+        }
+    }
+
+    /// How long `team` should spend thinking about its next move: a share of what's left plus whatever comes back
+    /// per move, never more than half the clock.
+    pub fn move_budget(&self, team: Team) -> Duration {
+        const MOVES_TO_PLAN_FOR: u32 = 30;
+        const MIN_BUDGET: Duration = Duration::from_millis(10);
+
+        let remaining = self.remaining(team);
+        let budget = std::cmp::min((remaining / MOVES_TO_PLAN_FOR).saturating_add(self.control.bonus()), remaining / 2);
+        std::cmp::max(budget, MIN_BUDGET)
+    }
+
+    fn remaining_after(&self, team: Team, elapsed: Duration) -> Duration {
+        let charged = match self.control {
+            TimeControl::Bronstein{ delay, .. } => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        };
+        self.remaining[index(team)].saturating_sub(charged)
+    }
+}
+
+fn index(team: Team) -> usize {
+    match team {
+        Team::Light => 0,
+        Team::Dark  => 1,
+    }
+}
+
+/// Clock face for a time: `h:mm:ss`, `m:ss`, or seconds and tenths in the last ten seconds.
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 10 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("{}.{}", secs, time.subsec_millis() / 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// Real time passes while the test runs
+    fn assert_about(actual: Duration, expected: Duration) {
+        let error = actual.abs_diff(expected);
+        assert!(error < Duration::from_millis(100), "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_parse_time_control() {
+        assert_eq!("5".parse(), Ok(TimeControl::SuddenDeath{ base: secs(300) }));
+        assert_eq!("3+2".parse(), Ok(TimeControl::Fischer{ base: secs(180), increment: secs(2) }));
+        assert_eq!("0.5/5".parse(), Ok(TimeControl::Bronstein{ base: secs(30), delay: secs(5) }));
+        assert!("0".parse::<TimeControl>().is_err());
+        assert!("five".parse::<TimeControl>().is_err());
+        assert!("5+-1".parse::<TimeControl>().is_err());
+        assert!("1e300".parse::<TimeControl>().is_err());
+        assert!("5+1e300".parse::<TimeControl>().is_err());
+        assert!("inf".parse::<TimeControl>().is_err());
+
+        // Increments and delays can't overflow a clock already near the limit
+        let mut fischer = Clock::new(TimeControl::Fischer{ base: Duration::MAX, increment: secs(5) });
+        fischer.start(Team::Light);
+        assert_eq!(fischer.stop(), None);
+        let mut bronstein = Clock::new(TimeControl::Bronstein{ base: Duration::MAX, delay: secs(5) });
+        bronstein.start(Team::Light);
+        assert!(bronstein.time_to_flag().is_some());
+    }
+
+    #[test]
+    fn test_clock() {
+        // Pretend moves took a while by backdating the start
+        let play = |clock: &mut Clock, team, elapsed| {
+            clock.start(team);
+            clock.running = Some((team, Instant::now() - elapsed));
+            clock.stop()
+        };
+
+        let mut fischer = Clock::new(TimeControl::Fischer{ base: secs(60), increment: secs(5) });
+        assert_eq!(play(&mut fischer, Team::Light, secs(10)), None);
+        assert_about(fischer.remaining(Team::Light), secs(55));
+        assert_eq!(fischer.remaining(Team::Dark), secs(60));
+        assert_eq!(play(&mut fischer, Team::Dark, secs(61)), Some(Team::Dark));
+        assert_eq!(fischer.remaining(Team::Dark), secs(0));
+
+        let mut bronstein = Clock::new(TimeControl::Bronstein{ base: secs(60), delay: secs(5) });
+        assert_eq!(play(&mut bronstein, Team::Light, secs(3)), None);
+        assert_about(bronstein.remaining(Team::Light), secs(60));
+        assert_eq!(play(&mut bronstein, Team::Light, secs(15)), None);
+        assert_about(bronstein.remaining(Team::Light), secs(50));
+
+        let mut sudden_death = Clock::new(TimeControl::SuddenDeath{ base: secs(60) });
+        sudden_death.start(Team::Dark);
+        assert_eq!(sudden_death.flagged(), None);
+        assert!(sudden_death.time_to_flag().unwrap() <= secs(60));
+        assert!(sudden_death.move_budget(Team::Dark) <= secs(2));
+        let resumed = Clock::resume(&sudden_death.state());
+        assert_eq!(resumed.running(), Some(Team::Dark));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(secs(3725)), "1:02:05");
+        assert_eq!(format_time(secs(305)), "5:05");
+        assert_eq!(format_time(Duration::from_millis(9_450)), "9.4");
+    }
+}
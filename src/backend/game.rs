@@ -1,48 +1,148 @@
-use super::ai::Ai;
-use super::board::{Board, Move, Square, Team};
+use super::ai::{Ai, SearchLimits, DEFAULT_DEPTH};
+use super::board::{Board, Move, Square, Team, Variant};
+use super::clock::{self, Clock};
+use super::notation::{self, GameRecord};
 use super::player::Player;
 
 use crate::args::Args;
-use crate::channel::{BackendEndpoint, BackToFrontMessage, FrontToBackMessage};
+use crate::channel::{BackendEndpoint, BackToFrontMessage, FrontToBackMessage, LogLevel};
 
-use std::sync::mpsc::RecvError;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{RecvError, RecvTimeoutError};
 use std::thread;
 use std::time::{Duration, Instant};
 
 pub struct Game {
-    args: Args,
     frontend_channel: BackendEndpoint,
     board: Board,
+    /// Side to move in the starting position
+    first_team: Team,
+    history: Vec<Turn>,
+    clock: Option<Clock>,
+    ai_limits: SearchLimits,
+    seed: u64,
+    /// Picks between equally good AI moves
+    rng: StdRng,
+    result: Option<GameResult>,
+    /// Where to write the game once it's over
+    record_path: Option<PathBuf>,
     // score: [i8; 2],
 }
 
+/// How a finished game was decided.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EndReason {
+    NoPieces,
+    NoMoves,
+    Resigned,
+    OutOfTime,
+    DrawAgreed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameResult {
+    /// `None` for a draw
+    pub winner: Option<Team>,
+    pub reason: EndReason,
+}
+impl GameResult {
+    fn win(winner: Team, reason: EndReason) -> GameResult {
+        GameResult{ winner: Some(winner), reason }
+    }
+
+    /// Whether the winner fits the reason: only a draw has no winner
+    pub fn is_consistent(&self) -> bool {
+        self.winner.is_none() == (self.reason == EndReason::DrawAgreed)
+    }
+}
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let winner = match (self.reason, self.winner) {
+            (EndReason::DrawAgreed, _) | (_, None) => return write!(f, "Draw agreed"),
+            (_, Some(winner)) => winner,
+        };
+        let loser = winner.other();
+        match self.reason {
+            EndReason::NoPieces   => write!(f, "{:?} wins, {:?} has no pieces left", winner, loser),
+            EndReason::NoMoves    => write!(f, "{:?} wins, {:?} can't move", winner, loser),
+            EndReason::Resigned   => write!(f, "{:?} wins, {:?} resigned", winner, loser),
+            EndReason::OutOfTime  => write!(f, "{:?} wins, {:?} ran out of time", winner, loser),
+            EndReason::DrawAgreed => unreachable!(),
+        }
+    }
+}
+
+/// A completed turn, with the board as it was before the turn was taken so it can be undone.
+struct Turn {
+    team: Team,
+    moves: Vec<Move>,
+    board_before: Board,
+}
+
+enum TurnResult {
+    Moved(Vec<Move>),
+    Undo,
+    Resign,
+    NoMoves,
+    OutOfTime,
+    DrawAgreed,
+}
+
+/// `log!(self, "...")` for the player, or `log!(self, level: LogLevel::Debug, "...")` for diagnostics
 macro_rules! log {
-    ( $self:expr, $( $arg:expr ),* ) => {
+    ( $self:expr, level: $level:expr, $( $arg:expr ),* ) => {
         // TODO don't panic here
-        $self.frontend_channel.tx.send(BackToFrontMessage::Log{ msg: format!($($arg),*) }).expect("Failed to log");
+        $self.frontend_channel.tx.send(BackToFrontMessage::Log{ level: $level, msg: format!($($arg),*) }).expect("Failed to log");
+    };
+    ( $self:expr, $( $arg:expr ),* ) => {
+        log!($self, level: LogLevel::Info, $($arg),*)
     };
 }
 
 impl Game {
     pub fn new(args: Args, frontend_channel: BackendEndpoint) -> Game {
+        let options = args.game;
+        let variant = options.variant;
+        let (board, first_team) = options.position.unwrap_or_else(|| (Board::new(), variant.first_team()));
+        let seed = options.seed.unwrap_or_else(rand::random);
         Game {
-            args: args,
-            frontend_channel: frontend_channel,
-            board: Board::new(),
+            frontend_channel,
+            board: board.with_variant(variant),
+            first_team,
+            history: Vec::new(),
+            clock: args.time_control.map(Clock::new),
+            ai_limits: options.ai_limits,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            result: None,
+            record_path: options.record,
             // score: [0, 0],
         }
     }
 
     pub fn start(&mut self) {
-        let msg = self.frontend_channel.rx.recv().unwrap();
+        let msg = match self.frontend_channel.rx.recv() {
+            Ok(msg) => msg,
+            Err(_) => return, // Frontend quit before starting a game
+        };
         let prefs = match msg {
             FrontToBackMessage::StartGame(prefs) => prefs,
             msg => panic!("Unexpected message from frontend: {:?}", msg),
         };
         let make_player = |team, pref| {
             match pref {
-                "Human" => Player::Human{ team: team },
-                "CPU"   => Player::Computer{ ai: Ai{ team: team } },
+                "Human" => Player::Human{ team },
+                "CPU"   => Player::Computer{ ai: Ai{ team } },
                 _ => panic!("Bad player pref: {:?}", pref)
             }
         };
@@ -51,72 +151,295 @@ impl Game {
             make_player(Team::Dark,  prefs.players[1]),
         ];
         self.update_frontend();
-        let mut player_iter = players.iter().enumerate().cycle();
+        if let Some(clock) = &self.clock {
+            log!(self, "Time control: {}", clock.control());
+        }
+        if self.board.variant() == Variant::American {
+            log!(self, "American rules: captures are compulsory");
+        }
+        log!(self, level: LogLevel::Debug, "AI seed: {}", self.seed);
+        let mut player_idx = match self.first_team {
+            Team::Light => 0,
+            Team::Dark  => 1,
+        };
         while !self.board.game_over() {
-            let (player_idx, current_player) = player_iter.next().unwrap();
+            let current_player = &players[player_idx];
+            let opponent = &players[(player_idx + 1) % players.len()];
             log!(self, "Player {}'s turn", player_idx);
-            let result = match current_player {
-                Player::Human{team} => self.process_human(current_player, *team), // TODO relationship between player/team is awkward
-                Player::Computer{ai} => self.process_ai(ai),
+            self.start_clock(current_player.team());
+            let board_before = self.board.clone();
+            let result = if self.board.get_all_valid_moves(current_player.team()).is_empty() {
+                Ok(TurnResult::NoMoves)
+            } else {
+                match current_player {
+                    Player::Human{team} => self.process_human(*team, opponent), // TODO relationship between player/team is awkward
+                    Player::Computer{ai} => self.process_ai(ai),
+                }
             };
+            let team = current_player.team();
             match result { // TODO clean up
                 Err(_) => break, // Frontend closed, channel broken
-                Ok(false) => break,
-                _ => (),
+                Ok(TurnResult::Moved(moves)) => {
+                    if self.stop_clock().is_some() {
+                        self.result = Some(GameResult::win(team.other(), EndReason::OutOfTime));
+                        break;
+                    }
+                    self.send_msg(BackToFrontMessage::MoveMade(team, moves.clone()));
+                    self.history.push(Turn{ team, moves, board_before });
+                    player_idx = (player_idx + 1) % players.len();
+                },
+                Ok(TurnResult::Undo) => self.undo(team),
+                Ok(TurnResult::Resign) => {
+                    log!(self, "Player {} ({:?}) resigns", player_idx, team);
+                    self.result = Some(GameResult::win(team.other(), EndReason::Resigned));
+                    break;
+                },
+                Ok(TurnResult::NoMoves) => {
+                    self.result = Some(GameResult::win(team.other(), EndReason::NoMoves));
+                    break;
+                },
+                Ok(TurnResult::OutOfTime) => {
+                    self.result = Some(GameResult::win(team.other(), EndReason::OutOfTime));
+                    break;
+                },
+                Ok(TurnResult::DrawAgreed) => {
+                    self.result = Some(GameResult{ winner: None, reason: EndReason::DrawAgreed });
+                    break;
+                },
             };
         }
+        if self.board.game_over() {
+            let winner = if self.board.pieces_alive(Team::Light) > 0 { Team::Light } else { Team::Dark };
+            self.result = Some(GameResult::win(winner, EndReason::NoPieces));
+        }
+        self.stop_clock();
+        if let Some(path) = &self.record_path {
+            if let Err(err) = fs::write(path, notation::format_pdn(&self.record())) {
+                // The frontend may have gone already
+                let msg = format!("Could not write the game to {}: {}", path.display(), err);
+                self.frontend_channel.tx.send(BackToFrontMessage::Log{ level: LogLevel::Info, msg }).ok();
+            }
+        }
         // println!("Game over!");
-        log!(self, "Game over!");
+        match self.result {
+            Some(result) => {
+                log!(self, "Game over: {}", result);
+                self.send_msg(BackToFrontMessage::GameOver(result));
+            },
+            None => {
+                log!(self, "Game over!");
+            },
+        }
     }
 
-    fn process_human(&mut self, player: &Player, team: Team) -> Result<bool, RecvError> {
-        self.request_move_from_frontend(team);
+    /// How the game ended, once it has.
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
 
-        self.handle_move_msg_from_frontend(player, team)
+    /// The game so far, for writing out in PDN.  The result is Light's score first, as in FEN where Light is W.
+    pub fn record(&self) -> GameRecord {
+        let result = self.result.map(|result| match result.winner {
+            Some(Team::Light) => "2-0",
+            Some(Team::Dark)  => "0-2",
+            None              => "1-1",
+        });
+        GameRecord {
+            tags: Vec::new(),
+            start: self.history.first().map_or(&self.board, |turn| &turn.board_before).clone(),
+            first_team: self.first_team,
+            turns: self.history.iter().map(|turn| turn.moves.clone()).collect(),
+            result: result.map(str::to_string),
+        }
     }
 
-    fn handle_move_msg_from_frontend(&mut self, player: &Player, team: Team) -> Result<bool, RecvError> {
-        let msg = self.frontend_channel.rx.recv()?;
-        let mv = match msg {
-            FrontToBackMessage::Move(mv) => mv,
-            FrontToBackMessage::CancelMove => return Ok(true),
-            msg => panic!("Unexpected message from frontend: {:?}", msg),
-        };
+    fn start_clock(&mut self, team: Team) {
+        if let Some(clock) = &mut self.clock {
+            clock.start(team);
+            let state = clock.state();
+            self.send_msg(BackToFrontMessage::Clock(state));
+        }
+    }
 
-        match self.board.get_piece_at(&mv.from) {
-            Some(piece) if piece.team == team => { // TODO let board handle this logic, plus check other validity
-                log!(self, "Human ({:?}) taking move: {}", team, mv);
-                self.apply_move(&mv);
+    /// Returns the team whose flag fell, if any.
+    fn stop_clock(&mut self) -> Option<Team> {
+        let clock = self.clock.as_mut()?;
+        clock.running()?;
+        let flagged = clock.stop();
+        let state = clock.state();
+        self.send_msg(BackToFrontMessage::Clock(state));
+        flagged
+    }
+
+    /// Waits for the frontend, giving up when the running clock's flag falls.  `Ok(None)` means it fell.
+    fn recv_before_flag(&self) -> Result<Option<FrontToBackMessage>, RecvError> {
+        loop {
+            let timeout = match self.clock.as_ref().and_then(Clock::time_to_flag) {
+                Some(timeout) => timeout,
+                None => return self.frontend_channel.rx.recv().map(Some),
+            };
+            match self.frontend_channel.rx.recv_timeout(timeout) {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(RecvTimeoutError::Timeout) if self.clock.as_ref().and_then(Clock::flagged).is_some() => return Ok(None),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+            }
+        }
+    }
 
-                // TODO do I need to handle case where player has no valid moves?  (seems like I shouldn't request move in the first place)
-                let jumps = self.board.get_valid_jumps_for_piece_at(&mv.to);
-                if mv.is_jump() && !jumps.is_empty() {
-                    self.request_jump_from_frontend(team, mv.to, jumps);
-                    return self.handle_move_msg_from_frontend(player, team);
+    /// Rewinds to just before `team`'s previous turn, so it is their move again.
+    fn undo(&mut self, team: Team) {
+        match self.history.iter().rposition(|turn| turn.team == team) {
+            Some(idx) => {
+                let turn = self.history.drain(idx..).next().unwrap();
+                self.board = turn.board_before;
+                log!(self, "{:?} took back their last move", team);
+            },
+            None => {
+                log!(self, "Nothing to undo");
+            },
+        };
+        self.update_frontend();
+    }
+
+    /// Puts `team`'s draw offer to their opponent.  `Ok(None)` means `team`'s flag fell while waiting for an answer.
+    fn offer_draw(&mut self, team: Team, opponent: &Player) -> Result<Option<bool>, RecvError> {
+        log!(self, "{:?} offers a draw", team);
+        let accepted = match opponent {
+            Player::Computer{ai} => ai.accepts_draw(&self.board),
+            Player::Human{..} => {
+                self.send_msg(BackToFrontMessage::DrawOffered(team));
+                loop {
+                    match self.recv_before_flag()? {
+                        Some(FrontToBackMessage::AnswerDraw(accepted)) => break accepted,
+                        Some(_) => {
+                            log!(self, "Waiting for {:?} to answer the draw offer", team.other());
+                        },
+                        None => return Ok(None),
+                    }
                 }
             },
-            _ => panic!("Frontend sent bad move: {}", mv),
-        }
+        };
+        log!(self, "{:?} {} the draw", team.other(), if accepted { "accepts" } else { "declines" });
+        Ok(Some(accepted))
+    }
+
+    fn process_human(&mut self, team: Team, opponent: &Player) -> Result<TurnResult, RecvError> {
+        self.request_move_from_frontend(team);
 
-        Ok(true)
+        let turn_start = self.board.clone();
+        let mut path: Vec<Move> = Vec::new();
+        // Whether the last hop crowned the piece, which ends an American jump
+        let mut crowned = false;
+        loop {
+            let msg = match self.recv_before_flag()? {
+                Some(msg) => msg,
+                None => {
+                    self.board = turn_start;
+                    self.update_frontend();
+                    return Ok(TurnResult::OutOfTime);
+                },
+            };
+            let mv = match msg {
+                FrontToBackMessage::Move(mv) => mv,
+                FrontToBackMessage::CancelMove => match path.last() {
+                    Some(last) if self.board.must_continue_jump(&last.to, crowned) => {
+                        log!(self, "You have to finish the jump");
+                        let jumps = self.board.get_continuing_jumps(&last.to, crowned);
+                        self.request_jump_from_frontend(team, last.to, jumps);
+                        continue;
+                    },
+                    _ => return Ok(TurnResult::Moved(path)),
+                },
+                FrontToBackMessage::Undo => {
+                    self.board = turn_start;
+                    return Ok(TurnResult::Undo);
+                },
+                FrontToBackMessage::Resign => return Ok(TurnResult::Resign),
+                FrontToBackMessage::OfferDraw if !path.is_empty() => {
+                    log!(self, "Finish your jump before offering a draw");
+                    continue;
+                },
+                FrontToBackMessage::OfferDraw => match self.offer_draw(team, opponent)? {
+                    Some(true) => return Ok(TurnResult::DrawAgreed),
+                    Some(false) => {
+                        self.request_move_from_frontend(team);
+                        continue;
+                    },
+                    None => return Ok(TurnResult::OutOfTime),
+                },
+                msg => {
+                    log!(self, level: LogLevel::Debug, "Ignoring unexpected message from frontend: {:?}", msg);
+                    continue;
+                },
+            };
+
+            // Only moves the frontend was asked for, so a buggy or hostile one can't corrupt the game
+            let legal = match path.last() {
+                Some(last) => self.board.get_continuing_jumps(&last.to, crowned),
+                None => self.board.get_all_valid_moves(team),
+            };
+            if !legal.contains(&mv) {
+                log!(self, "Illegal move: {}", mv);
+                match path.last() {
+                    Some(last) => self.request_jump_from_frontend(team, last.to, legal),
+                    None => self.request_move_from_frontend(team),
+                }
+                continue;
+            }
+
+            log!(self, "Human ({:?}) taking move: {}", team, mv);
+            crowned = self.apply_move(&mv);
+            path.push(mv);
+
+            let jumps = self.board.get_continuing_jumps(&mv.to, crowned);
+            if mv.is_jump() && !jumps.is_empty() {
+                self.request_jump_from_frontend(team, mv.to, jumps);
+                continue;
+            }
+
+            return Ok(TurnResult::Moved(path));
+        }
     }
 
-    fn apply_move(&mut self, mv: &Move) {
-        self.board.apply_move(&mv);
+    /// Returns whether a man was crowned
+    fn apply_move(&mut self, mv: &Move) -> bool {
+        let crowned = self.board.apply_move(mv);
         self.update_frontend();
+        crowned
     }
 
-    fn process_ai(&mut self, ai: &Ai) -> Result<bool, RecvError> {
+    fn process_ai(&mut self, ai: &Ai) -> Result<TurnResult, RecvError> {
         const AUTO_PLAY: bool = true;
         const MIN_AUTO_PLAY_DELAY: Duration = Duration::from_millis(800);
 
         let now = Instant::now();
-        let next_moves = ai.get_next_moves(self.board.clone());
+        let budget = self.clock.as_ref().map(|clock| clock.move_budget(ai.team));
+        let limits = match (self.ai_limits, budget) {
+            (SearchLimits{ depth: None, time: None }, None) => SearchLimits{ depth: Some(DEFAULT_DEPTH), time: None },
+            (limits, None) => limits,
+            (limits, Some(budget)) => SearchLimits{ time: Some(limits.time.map_or(budget, |time| time.min(budget))), ..limits },
+        };
+        let next_moves = match ai.search(self.board.clone(), limits, None, |_| ()) {
+            Some(info) => {
+                log!(self, level: LogLevel::Ai, "AI ({:?}) searched depth {}, score {}, {} nodes in {:?}, {} equal choices",
+                     ai.team, info.depth, info.score, info.nodes, info.elapsed, info.tied.len());
+                info.tied.choose(&mut self.rng).cloned().unwrap_or(info.moves)
+            },
+            None => Vec::new(),
+        };
         let time_spent_in_ai = now.elapsed();
-        log!(self, "Processing AI, elapsed: {:?}", time_spent_in_ai);
+        log!(self, level: LogLevel::Debug, "Processing AI, elapsed: {:?}", time_spent_in_ai);
+        // Showing the moves is not thinking time
+        if self.stop_clock().is_some() {
+            return Ok(TurnResult::OutOfTime);
+        }
+        if let Some(clock) = &self.clock {
+            log!(self, level: LogLevel::Ai, "AI ({:?}) has {} left", ai.team, clock::format_time(clock.remaining(ai.team)));
+        }
 
         if next_moves.is_empty() {
-            return Ok(false); // TODO clean up
+            return Ok(TurnResult::NoMoves);
         }
 
         // log!(self, "next moves: {:?}", next_moves);
@@ -134,10 +457,10 @@ impl Game {
                 let _ = stdin.read(&mut [0u8]).unwrap();
             }
             log!(self, "AI ({:?}) taking move: {}", ai.team, mv);
-            self.board.apply_move(&mv);
+            self.board.apply_move(mv);
             self.update_frontend();
         }
-        Ok(true)
+        Ok(TurnResult::Moved(next_moves))
     }
 
     fn request_move_from_frontend(&self, team: Team) {
@@ -153,4 +476,8 @@ impl Game {
     fn update_frontend(&self) {
         self.frontend_channel.tx.send(BackToFrontMessage::BoardState(self.board.clone())).expect("Could not send board state"); // TODO better handling
     }
+
+    fn send_msg(&self, msg: BackToFrontMessage) {
+        self.frontend_channel.tx.send(msg).expect("Could not send message"); // TODO better handling
+    }
 }
@@ -1,13 +1,24 @@
+//! The game itself: board and rules, notation, AI and the `Game` that referees a match over a `channel`.
+
 mod ai;
 mod board;
+pub mod clock;
 mod game;
+pub mod notation;
 mod player;
 
+pub use ai::Ai;
+pub use ai::Difficulty;
+pub use ai::SearchInfo;
+pub use ai::SearchLimits;
+pub use ai::DEFAULT_DEPTH;
+pub use ai::MAX_DEPTH;
 pub use board::Board;
 pub use board::Move;
 pub use board::PieceType;
 pub use board::Piece;
 pub use board::Square;
 pub use board::Team;
-pub use game::Game;
-pub use player::Player;
+pub use board::Variant;
+pub use clock::{Clock, ClockState, TimeControl};
+pub use game::{EndReason, Game, GameResult};
@@ -0,0 +1,489 @@
+//! Standard checkers notation: playable squares are numbered 1-32 starting from Dark's side of the board, quiet moves
+//! are written `11-15` and jumps list every landing square, e.g. `15x24x31`.  Positions are read and written as PDN
+//! FEN, and whole recorded games as PDN.
+
+use super::board::{Board, Move, Piece, PieceType, Square, Team};
+
+use std::fmt;
+
+pub const NUM_SQUARES: u8 = 32;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    message: String,
+}
+impl ParseError {
+    pub fn new(message: String) -> ParseError {
+        ParseError { message }
+    }
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Number (1-32) of a playable square, or `None` for light squares and squares off the board.
+pub fn square_number(square: &Square) -> Option<u8> {
+    let on_board = 0 <= square.x && square.x < Board::SIZE && 0 <= square.y && square.y < Board::SIZE;
+    if !on_board || (square.x + square.y) % 2 == 0 {
+        return None;
+    }
+    Some((square.y * Board::SIZE / 2 + square.x / 2 + 1) as u8)
+}
+
+pub fn number_square(number: u8) -> Option<Square> {
+    if !(1..=NUM_SQUARES).contains(&number) {
+        return None;
+    }
+    let idx = (number - 1) as i8;
+    let y = idx / (Board::SIZE / 2);
+    let x = (idx % (Board::SIZE / 2)) * 2 + (y + 1) % 2;
+    Some(Square{ x, y })
+}
+
+/// Writes a full turn (a single step, or every hop of a jump sequence) in standard notation.
+pub fn format_path(path: &[Move]) -> String {
+    let separator = if path.iter().any(|mv| mv.is_jump()) { "x" } else { "-" };
+    let mut squares = Vec::new();
+    if let Some(first) = path.first() {
+        squares.push(first.from);
+    }
+    squares.extend(path.iter().map(|mv| mv.to));
+
+    squares
+        .iter()
+        .map(|square| square_number(square).map_or_else(|| format!("({})", square), |n| n.to_string()))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Parses `11-15` or `15x24x31` into the squares visited.  Either separator is accepted between any two squares.
+pub fn parse_path(text: &str) -> Result<Vec<Square>, ParseError> {
+    let squares = text
+        .trim()
+        .split(['-', 'x', 'X'])
+        .map(|part| {
+            let number = part.trim().parse::<u8>()
+                .map_err(|_| ParseError::new(format!("'{}' is not a square number", part.trim())))?;
+            number_square(number)
+                .ok_or_else(|| ParseError::new(format!("Square {} is not between 1 and {}", number, NUM_SQUARES)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if squares.len() < 2 {
+        return Err(ParseError::new(format!("'{}' needs at least a start and end square", text.trim())));
+    }
+
+    Ok(squares)
+}
+
+/// Start square, end square and captured squares (ascending) of a turn, by number.
+pub fn turn_summary(turn: &[Move]) -> (u8, u8, Vec<u8>) {
+    let number = |square: &Square| square_number(square).unwrap_or(0);
+    let mut captured: Vec<u8> = turn.iter()
+        .filter_map(|mv| mv.jumped_square())
+        .map(|square| number(&square))
+        .collect();
+    captured.sort_unstable();
+
+    (number(&turn[0].from), number(&turn[turn.len() - 1].to), captured)
+}
+
+/// Finds the legal turn for `team` going from `from` to `to`.  If `captured` is empty any capture path will do,
+/// otherwise the captured squares must match exactly (in any order).
+pub fn find_turn(board: &Board, team: Team, from: u8, to: u8, captured: &[u8]) -> Option<Vec<Move>> {
+    let mut captured = captured.to_vec();
+    captured.sort_unstable();
+
+    board.get_all_valid_turns(team)
+        .into_iter()
+        .find(|turn| {
+            let (turn_from, turn_to, turn_captured) = turn_summary(turn);
+            turn_from == from && turn_to == to && (captured.is_empty() || captured == turn_captured)
+        })
+}
+
+fn fen_team(team: Team) -> char {
+    match team {
+        Team::Light => 'W',
+        Team::Dark  => 'B',
+    }
+}
+
+fn parse_fen_team(text: &str) -> Result<Team, ParseError> {
+    match text.trim() {
+        "W" | "w" => Ok(Team::Light),
+        "B" | "b" => Ok(Team::Dark),
+        text => Err(ParseError::new(format!("'{}' is not W or B", text))),
+    }
+}
+
+/// Parses a position in PDN's FEN form, e.g. `B:W18,24,K10:B1-12`: the side to move, then each side's squares with
+/// `K` marking kings and `a-b` for runs of men.  `W` is Light and `B` is Dark.
+pub fn parse_fen(text: &str) -> Result<(Board, Team), ParseError> {
+    let mut fields = text.trim().trim_end_matches('.').split(':');
+    let to_move = parse_fen_team(fields.next().unwrap_or(""))?;
+
+    let mut board = Board::empty();
+    for field in fields {
+        let field = field.trim();
+        let team = parse_fen_team(field.get(..1).unwrap_or(""))?;
+        for entry in field[1..].split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (piece_type, squares) = match entry.strip_prefix(['K', 'k']) {
+                Some(squares) => (PieceType::King, squares),
+                None => (PieceType::Man, entry),
+            };
+            let number = |text: &str| text.trim().parse::<u8>().ok().filter(|n| number_square(*n).is_some())
+                .ok_or_else(|| ParseError::new(format!("'{}' is not a square number", text.trim())));
+            let (first, last) = match squares.split_once('-') {
+                Some((first, last)) => (number(first)?, number(last)?),
+                None => (number(squares)?, number(squares)?),
+            };
+            for square in (first..=last).filter_map(number_square) {
+                board.place_piece(square, Piece{ team, piece_type });
+            }
+        }
+    }
+
+    Ok((board, to_move))
+}
+
+/// Writes a position the way `parse_fen` reads it, without runs.
+pub fn format_fen(board: &Board, to_move: Team) -> String {
+    let side = |team: Team| {
+        let squares: Vec<String> = (1..=NUM_SQUARES)
+            .filter_map(|number| {
+                let piece = board.get_piece_at(&number_square(number)?)?;
+                match piece.piece_type {
+                    _ if piece.team != team => None,
+                    PieceType::Man  => Some(number.to_string()),
+                    PieceType::King => Some(format!("K{}", number)),
+                }
+            })
+            .collect();
+        format!("{}{}", fen_team(team), squares.join(","))
+    };
+    format!("{}:{}:{}", fen_team(to_move), side(Team::Light), side(Team::Dark))
+}
+
+/// A game read from PDN: its tags, where it started and every turn played.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    /// `[Name "Value"]` pairs from the header, in order
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub first_team: Team,
+    pub turns: Vec<Vec<Move>>,
+    /// The result as written, e.g. `2-0` or `1-1`
+    pub result: Option<String>,
+}
+impl GameRecord {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Side that played turn `idx` (counting from 0)
+    pub fn team(&self, idx: usize) -> Team {
+        if idx.is_multiple_of(2) { self.first_team } else { self.first_team.other() }
+    }
+
+    /// The board after the first `turns` turns
+    pub fn position(&self, turns: usize) -> Board {
+        let mut board = self.start.clone();
+        for mv in self.turns.iter().take(turns).flatten() {
+            board.apply_move(mv);
+        }
+        board
+    }
+}
+
+const RESULTS: &[&str] = &["2-0", "0-2", "1-1", "1-0", "0-1", "1/2-1/2", "0-0", "*"];
+/// Lines of PDN move text are kept to this many characters
+const PDN_LINE_WIDTH: usize = 80;
+
+/// Writes `record` in PDN, as `parse_pdn` reads it: the tags, a `FEN` tag for the start if there isn't one, the turns
+/// numbered in pairs and the result, `*` if there isn't one.
+pub fn format_pdn(record: &GameRecord) -> String {
+    let mut text = String::new();
+    for (name, value) in &record.tags {
+        text += &format!("[{} \"{}\"]\n", name, value);
+    }
+    if record.tag("FEN").is_none() {
+        text += &format!("[FEN \"{}\"]\n", format_fen(&record.start, record.first_team));
+    }
+    text.push('\n');
+
+    let turns = record.turns.iter().enumerate().map(|(idx, turn)| match idx % 2 {
+        0 => format!("{}. {}", idx / 2 + 1, format_path(turn)),
+        _ => format_path(turn),
+    });
+    let mut line = String::new();
+    for token in turns.chain(std::iter::once(record.result.clone().unwrap_or_else(|| "*".to_string()))) {
+        if !line.is_empty() && line.len() + 1 + token.len() > PDN_LINE_WIDTH {
+            text += &line;
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &token;
+    }
+    text += &line;
+    text.push('\n');
+    text
+}
+
+/// Reads the first game in PDN `text`.  Move numbers, comments (`{...}` and `;` to the end of the line), variations
+/// and `!`/`?` annotations are skipped.  Without a `FEN` tag the game starts from the usual opening, with whichever
+/// side owns the piece the first move starts from.
+pub fn parse_pdn(text: &str) -> Result<GameRecord, ParseError> {
+    pdn_games(text)?.into_iter().next().unwrap_or_default().record()
+}
+
+/// Reads every game in PDN `text`, as `parse_pdn` reads one.  Each game ends with its result, or where the next
+/// game's tags start.
+pub fn parse_pdn_games(text: &str) -> Result<Vec<GameRecord>, ParseError> {
+    pdn_games(text)?.into_iter().enumerate()
+        .map(|(idx, game)| game.record().map_err(|err| ParseError::new(format!("Game {}: {}", idx + 1, err))))
+        .collect()
+}
+
+/// One game's worth of PDN, split up but not yet checked
+#[derive(Default)]
+struct PdnGame {
+    tags: Vec<(String, String)>,
+    moves: Vec<String>,
+    result: Option<String>,
+}
+
+fn pdn_games(text: &str) -> Result<Vec<PdnGame>, ParseError> {
+    let mut games = Vec::new();
+    let mut game = PdnGame::default();
+    let mut chars = text.chars().peekable();
+    let mut token = String::new();
+    let mut tokens = Vec::new();
+    while let Some(c) = chars.next() {
+        let closing = match c {
+            '[' => Some(']'),
+            '{' => Some('}'),
+            ';' => Some('\n'),
+            '(' => Some(')'),
+            _ => None,
+        };
+        if closing.is_some() || c.is_whitespace() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        } else {
+            token.push(c);
+        }
+        let closing = match closing {
+            Some(closing) => closing,
+            None => continue,
+        };
+        let mut depth = 1;
+        let mut inside = String::new();
+        for c in chars.by_ref() {
+            match c {
+                _ if c == closing => depth -= 1,
+                '(' if closing == ')' => depth += 1,
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+            inside.push(c);
+        }
+        if c == '[' {
+            // Tags after moves start the next game
+            add_pdn_moves(&mut games, &mut game, tokens.drain(..));
+            if !game.moves.is_empty() {
+                games.push(std::mem::take(&mut game));
+            }
+            let (name, value) = inside.trim().split_once(char::is_whitespace)
+                .ok_or_else(|| ParseError::new(format!("Bad tag '[{}]'", inside)))?;
+            game.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    add_pdn_moves(&mut games, &mut game, tokens.into_iter());
+    if !game.tags.is_empty() || !game.moves.is_empty() {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+/// Adds move text to `game`, moving it to `games` at each result
+fn add_pdn_moves<I: Iterator<Item = String>>(games: &mut Vec<PdnGame>, game: &mut PdnGame, tokens: I) {
+    for token in tokens {
+        if RESULTS.contains(&token.as_str()) {
+            game.result = Some(token);
+            games.push(std::mem::take(game));
+            continue;
+        }
+        // Move numbers are `12.` or `12...`, sometimes run into the move
+        let mv = token.trim_start_matches(|c: char| c.is_ascii_digit() && token.contains('.'))
+            .trim_start_matches('.')
+            .trim_end_matches(['!', '?']);
+        if !mv.is_empty() {
+            game.moves.push(mv.to_string());
+        }
+    }
+}
+
+impl PdnGame {
+    fn record(self) -> Result<GameRecord, ParseError> {
+        let PdnGame{ tags, moves, result } = self;
+        let (start, first_team) = match tags.iter().find(|(name, _)| name.eq_ignore_ascii_case("FEN")) {
+            Some((_, fen)) => parse_fen(fen)?,
+            None => {
+                let board = Board::new();
+                let first_team = moves.first()
+                    .and_then(|mv| parse_path(mv).ok())
+                    .and_then(|path| board.get_piece_at(&path[0]).map(|piece| piece.team))
+                    .unwrap_or(Team::Light);
+                (board, first_team)
+            },
+        };
+
+        let mut board = start.clone();
+        let mut team = first_team;
+        let mut turns = Vec::new();
+        for (idx, mv) in moves.iter().enumerate() {
+            let path = parse_path(mv).map_err(|err| ParseError::new(format!("Move {} ({}): {}", idx + 1, mv, err)))?;
+            let turn = board.get_all_valid_turns(team).into_iter()
+                .find(|turn| {
+                    let landings: Vec<Square> = turn.iter().map(|mv| mv.to).collect();
+                    turn[0].from == path[0]
+                        && (landings[..] == path[1..] || (path.len() == 2 && landings.last() == Some(&path[1])))
+                })
+                .ok_or_else(|| ParseError::new(format!("Move {} ({}) is not legal for {:?}", idx + 1, mv, team)))?;
+            for mv in &turn {
+                board.apply_move(mv);
+            }
+            turns.push(turn);
+            team = team.other();
+        }
+
+        Ok(GameRecord{ tags, start, first_team, turns, result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_numbers() {
+        assert_eq!(square_number(&Square{ x: 1, y: 0 }), Some(1));
+        assert_eq!(square_number(&Square{ x: 7, y: 0 }), Some(4));
+        assert_eq!(square_number(&Square{ x: 0, y: 1 }), Some(5));
+        assert_eq!(square_number(&Square{ x: 7, y: 6 }), Some(28));
+        assert_eq!(square_number(&Square{ x: 0, y: 7 }), Some(29));
+        assert_eq!(square_number(&Square{ x: 6, y: 7 }), Some(32));
+
+        assert_eq!(square_number(&Square{ x: 0, y: 0 }), None);
+        assert_eq!(square_number(&Square{ x: 8, y: 1 }), None);
+        assert_eq!(square_number(&Square{ x: -1, y: 0 }), None);
+
+        for n in 1..=NUM_SQUARES {
+            assert_eq!(square_number(&number_square(n).unwrap()), Some(n));
+        }
+        assert_eq!(number_square(0), None);
+        assert_eq!(number_square(33), None);
+    }
+
+    #[test]
+    fn test_format_path() {
+        let step = Move{ from: number_square(22).unwrap(), to: number_square(18).unwrap() };
+        assert_eq!(format_path(&[step]), "22-18");
+
+        let jumps = [
+            Move{ from: number_square(15).unwrap(), to: number_square(24).unwrap() },
+            Move{ from: number_square(24).unwrap(), to: number_square(31).unwrap() },
+        ];
+        assert_eq!(format_path(&jumps), "15x24x31");
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("11-15"), Ok(vec![number_square(11).unwrap(), number_square(15).unwrap()]));
+        assert_eq!(parse_path(" 15x24X31 ").unwrap().len(), 3);
+
+        assert!(parse_path("11").is_err());
+        assert!(parse_path("11-").is_err());
+        assert!(parse_path("0-4").is_err());
+        assert!(parse_path("a-b").is_err());
+    }
+
+    #[test]
+    fn test_fen() {
+        let (board, to_move) = parse_fen("W:W21-32:B1-12").unwrap();
+        assert!(board == Board::new());
+        assert_eq!(to_move, Team::Light);
+
+        let (board, to_move) = parse_fen("B:W18,K10:BK1, 5.").unwrap();
+        assert_eq!(to_move, Team::Dark);
+        assert_eq!(board.get_piece_at(&number_square(10).unwrap()), Some(&Piece{ team: Team::Light, piece_type: PieceType::King }));
+        assert_eq!(board.pieces_alive(Team::Dark), 2);
+        assert_eq!(format_fen(&board, to_move), "B:WK10,18:BK1,5");
+
+        assert!(parse_fen("X:W1").is_err());
+        assert!(parse_fen("W:W33").is_err());
+        assert!(parse_fen("W:Q1").is_err());
+    }
+
+    #[test]
+    fn test_pdn() {
+        let text = "[Event \"Club night\"]\n[White \"Ann\"] [Black \"Bo\"]\n\n\
+                    1. 22-18 {Light opens} 9-14 2. 18x9 (2. 24-19 5-9) 5x14!? 3. 23-19 ; a comment\n2-0 26-22";
+        let record = parse_pdn(text).unwrap();
+        assert_eq!(record.tag("event"), Some("Club night"));
+        assert_eq!(record.tags.len(), 3);
+        assert_eq!(record.first_team, Team::Light);
+        assert_eq!(record.turns.len(), 5);
+        assert_eq!(record.team(3), Team::Dark);
+        assert_eq!(format_path(&record.turns[2]), "18x9");
+        assert_eq!(record.result.as_deref(), Some("2-0"));
+        assert_eq!(record.position(5).pieces_alive(Team::Dark), 11);
+        assert!(record.position(0) == Board::new());
+
+        // Dark first, from a set position
+        let record = parse_pdn("[FEN \"B:W18:B14\"]\n1... 14x23 *").unwrap();
+        assert_eq!(record.first_team, Team::Dark);
+        assert_eq!(record.position(1).pieces_alive(Team::Light), 0);
+        let record = parse_pdn("11-15 22-18 15x22").unwrap();
+        assert_eq!(record.first_team, Team::Dark);
+        assert_eq!(record.turns.len(), 3);
+
+        assert!(parse_pdn("1. 22-17 2. 17-13").is_err());
+        assert!(parse_pdn("1. 22-18 9-14 3. 18x27").is_err());
+        assert!(parse_pdn("[Event]").is_err());
+
+        // Collections, with and without results between games
+        let games = parse_pdn_games("[Event \"One\"] 22-18 1-0\n[Event \"Two\"]\n[FEN \"B:W18:B14\"] 14x23\n[Event \"Three\"]").unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[1].tag("Event"), Some("Two"));
+        assert_eq!(games[1].turns.len(), 1);
+        assert!(games[2].turns.is_empty());
+        assert!(parse_pdn_games("").unwrap().is_empty());
+
+        // What's written reads back the same
+        let record = parse_pdn(text).unwrap();
+        let written = format_pdn(&record);
+        let tags = format!("[Event \"Club night\"]\n[White \"Ann\"]\n[Black \"Bo\"]\n[FEN \"{}\"]\n\n",
+                           format_fen(&Board::new(), Team::Light));
+        assert!(written.starts_with(&tags), "{}", written);
+        assert!(written.ends_with("\n1. 22-18 9-14 2. 18x9 5x14 3. 23-19 2-0\n"));
+        let read = parse_pdn(&written).unwrap();
+        assert_eq!((&read.turns, &read.result, read.first_team), (&record.turns, &record.result, record.first_team));
+        // Not a legal game, but long enough to wrap
+        let long = GameRecord{ turns: record.turns.iter().cycle().take(50).cloned().collect(), ..record };
+        assert!(format_pdn(&long).lines().all(|line| line.len() <= PDN_LINE_WIDTH));
+        let err = parse_pdn_games("22-18 *\n22-17 17-13").unwrap_err();
+        assert!(err.to_string().starts_with("Game 2: "), "{}", err);
+    }
+}
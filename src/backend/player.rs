@@ -5,3 +5,11 @@ pub enum Player {
     Human{ team: Team },
     Computer{ ai: Ai },
 }
+impl Player {
+    pub fn team(&self) -> Team {
+        match self {
+            Player::Human{team} => *team,
+            Player::Computer{ai} => ai.team,
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Serves the JSON API for creating and playing games, the same as `checkers api`.
+
+fn main() {
+    checkers::run_mode_command("api");
+}
@@ -0,0 +1,5 @@
+//! Plays the AI against a remote engine over the DamExchange protocol, the same as `checkers dxp`.
+
+fn main() {
+    checkers::run_mode_command("dxp");
+}
@@ -0,0 +1,5 @@
+//! Hosts a game for a player on another machine to join, the same as `checkers host`.
+
+fn main() {
+    checkers::run_mode_command("host");
+}
@@ -0,0 +1,8 @@
+//! Headless engine speaking the Hub protocol on stdin/stdout, for GUIs that don't want the rest of the game.
+
+fn main() {
+    if let Err(err) = checkers::hub::HubEngine::new().run() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,5 @@
+//! Joins a game hosted on another machine, the same as `checkers join`.
+
+fn main() {
+    checkers::run_mode_command("join");
+}
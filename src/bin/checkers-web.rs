@@ -0,0 +1,5 @@
+//! Serves a game to play or watch from a browser, the same as `checkers web`.
+
+fn main() {
+    checkers::run_mode_command("web");
+}
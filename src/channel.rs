@@ -1,7 +1,10 @@
 use std::sync::mpsc;
-use super::backend::{Board, Move, Square, Team};
+use super::backend::{Board, ClockState, GameResult, Move, Square, Team};
 use super::frontend::Preferences;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 pub struct Endpoint<TxMsg, RxMsg> {
     pub tx: mpsc::Sender<TxMsg>,
     pub rx: mpsc::Receiver<RxMsg>,
@@ -16,23 +19,102 @@ pub fn make_two_way_channel() -> (BackendEndpoint, FrontendEndpoint) {
     (BackendEndpoint{ tx: tx1, rx: rx2 }, FrontendEndpoint{ tx: tx2, rx: rx1 })
 }
 
+/// How important a log message is.  Frontends show `Info` and can hide the rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LogLevel {
+    #[default]
+    Info,
+    /// What the AI is thinking
+    Ai,
+    Debug,
+}
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            LogLevel::Info  => "info",
+            LogLevel::Ai    => "ai",
+            LogLevel::Debug => "debug",
+        })
+    }
+}
+
+/// With the `serde` feature, messages serialise externally tagged: `{"RequestMove": "Light"}`,
+/// `{"Log": {"level": "Info", "msg": "..."}}`, `"Undo"`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BackToFrontMessage {
-    Log{ msg: String },
+    Log{
+        #[cfg_attr(feature = "serde", serde(default))]
+        level: LogLevel,
+        msg: String,
+    },
     BoardState(Board),
     RequestMove(Team),
     RequestJump(Team, Square, Vec<Move>),
+    /// Sent once a turn is complete, with every hop of a multi-jump.
+    MoveMade(Team, Vec<Move>),
+    /// Sent whenever a clock starts or stops, for games with a time control.
+    Clock(ClockState),
+    /// The given team offers a draw; their opponent should answer with `AnswerDraw`.
+    DrawOffered(Team),
+    GameOver(GameResult),
 }
 impl std::fmt::Debug for BackToFrontMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            BackToFrontMessage::BoardState(_) => write!(f, "BackToFrontMessage::BoardState(...)"),
-            _                                 => write!(f, "{:?}", self),
+            BackToFrontMessage::Log{ level, msg }                 => write!(f, "BackToFrontMessage::Log{{ level: {:?}, msg: {:?} }}", level, msg),
+            BackToFrontMessage::BoardState(_)                     => write!(f, "BackToFrontMessage::BoardState(...)"),
+            BackToFrontMessage::RequestMove(team)                 => write!(f, "BackToFrontMessage::RequestMove({:?})", team),
+            BackToFrontMessage::RequestJump(team, square, moves)  => write!(f, "BackToFrontMessage::RequestJump({:?}, {:?}, {:?})", team, square, moves),
+            BackToFrontMessage::MoveMade(team, moves)             => write!(f, "BackToFrontMessage::MoveMade({:?}, {:?})", team, moves),
+            BackToFrontMessage::Clock(state)                      => write!(f, "BackToFrontMessage::Clock({:?})", state),
+            BackToFrontMessage::DrawOffered(team)                 => write!(f, "BackToFrontMessage::DrawOffered({:?})", team),
+            BackToFrontMessage::GameOver(result)                  => write!(f, "BackToFrontMessage::GameOver({:?})", result),
         }
     }
 }
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum FrontToBackMessage {
     StartGame(Preferences),
     Move(Move),
     CancelMove,
+    /// Take back the requesting player's previous turn.
+    Undo,
+    Resign,
+    /// Offer a draw instead of moving.  The turn carries on if it's declined.
+    OfferDraw,
+    /// Accept (`true`) or decline the opponent's draw offer.
+    AnswerDraw(bool),
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_message_json() {
+        let mv = Move{ from: Square{ x: 2, y: 5 }, to: Square{ x: 3, y: 4 } };
+        let msg = BackToFrontMessage::MoveMade(Team::Light, vec![mv]);
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json, json!({ "MoveMade": ["Light", [{ "from": { "x": 2, "y": 5 }, "to": { "x": 3, "y": 4 } }]] }));
+        assert_eq!(format!("{:?}", serde_json::from_value::<BackToFrontMessage>(json).unwrap()), format!("{:?}", msg));
+
+        let start = json!({ "StartGame": { "players": ["Human", "CPU"] } });
+        match serde_json::from_value::<FrontToBackMessage>(start.clone()).unwrap() {
+            FrontToBackMessage::StartGame(prefs) => {
+                assert_eq!(prefs.players, ["Human", "CPU"]);
+                assert_eq!(serde_json::to_value(FrontToBackMessage::StartGame(prefs)).unwrap(), start);
+            },
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        assert_eq!(serde_json::to_value(FrontToBackMessage::Undo).unwrap(), json!("Undo"));
+        match serde_json::from_value::<BackToFrontMessage>(json!({ "Log": { "msg": "Player 0's turn" } })).unwrap() {
+            BackToFrontMessage::Log{ level, .. } => assert_eq!(level, LogLevel::Info),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        assert!(serde_json::from_value::<FrontToBackMessage>(json!({ "StartGame": { "players": ["Human", "Robot"] } })).is_err());
+    }
 }
@@ -0,0 +1,129 @@
+//! The `checkers` command: play locally, or run one of the engine and server modes.  Each of those modes is also a
+//! command of its own, `checkers-dxp` for `checkers dxp` and so on.
+
+use crate::args::{self, Args, Mode};
+use crate::channel::{self, FrontendEndpoint};
+use crate::frontend::Frontend;
+use crate::{backend, dxp, frontend, hub, net, web};
+
+use std::thread;
+
+/// Runs the `checkers` command with the process's arguments.
+pub fn run_command() {
+    run(args::get_args());
+}
+
+/// Runs the `checkers-NAME` command for mode `name`, with the process's arguments.
+pub fn run_mode_command(name: &str) {
+    run(args::get_mode_args(name));
+}
+
+/// Runs whichever mode `args` asks for, exiting the process on failure.
+fn run(args: Args) {
+    match args.mode.clone() {
+        Mode::Play => {
+            let frontend = frontend::make_frontend(&args);
+            play(args, frontend);
+        },
+        Mode::Edit => {
+            let mut args = args;
+            match frontend::edit_position(&mut args) {
+                Ok(Some(frontend)) => play(args, frontend),
+                Ok(None) => (),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                },
+            }
+        },
+        Mode::Hub => {
+            if let Err(err) = hub::HubEngine::new().run() {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        Mode::Dxp(dxp_args) => {
+            if let Err(err) = dxp::run(args, dxp_args) {
+                eprintln!("DXP session ended: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Mode::Host(net_args) => match net::host(args.clone(), net_args) {
+            Ok(endpoint) => run_frontend(frontend::make_frontend(&args), endpoint),
+            Err(err) => {
+                eprintln!("Could not host game: {}", err);
+                std::process::exit(1);
+            },
+        },
+        Mode::Web(web_args) => {
+            if let Err(err) = web::run(args, web_args) {
+                eprintln!("Web server stopped: {}", err);
+                std::process::exit(1);
+            }
+        },
+        #[cfg(feature = "serde")]
+        Mode::Api(addr) => {
+            if let Err(err) = web::api::run(&addr) {
+                eprintln!("API server stopped: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Mode::Puzzles(path) => {
+            if let Err(err) = frontend::puzzles(&args, path.as_deref()) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        Mode::Replay(path) => {
+            if let Err(err) = frontend::replay(&args, &path) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        Mode::Join(addr) | Mode::Watch(addr) => {
+            let hello = if let Mode::Watch(_) = args.mode { net::Hello::Watch } else { net::Hello::Play(None) };
+            match net::join(&addr, hello) {
+                Ok(endpoint) => run_frontend(frontend::make_frontend(&args), endpoint),
+                Err(err) => {
+                    eprintln!("Could not join game at {}: {}", addr, err);
+                    std::process::exit(1);
+                },
+            }
+        },
+    }
+}
+
+/// Plays a game against the local backend on `frontend`
+fn play(args: Args, frontend: Box<dyn Frontend>) {
+    if let Some(addr) = &args.spectators {
+        match net::host_spectated(args.clone(), addr) {
+            Ok(endpoint) => run_frontend(frontend, endpoint),
+            Err(err) => {
+                drop(frontend);
+                eprintln!("Could not accept spectators on {}: {}", addr, err);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let (backend_endpoint, frontend_endpoint) = channel::make_two_way_channel();
+
+    // The frontend owns the main thread; once it exits, the game goes with it
+    let game_args = args.clone();
+    thread::spawn(move || {
+        let mut game = backend::Game::new(game_args, backend_endpoint);
+        game.start();
+    });
+
+    run_frontend(frontend, frontend_endpoint);
+}
+
+fn run_frontend(mut frontend: Box<dyn Frontend>, endpoint: FrontendEndpoint) {
+    let result = frontend::run(frontend.as_mut(), endpoint);
+    drop(frontend);
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
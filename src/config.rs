@@ -0,0 +1,177 @@
+//! Settings remembered between runs, in `$XDG_CONFIG_HOME/checkers/config` (usually `~/.config/checkers/config`).
+//!
+//! The file is `key = value` lines, with `#` starting a comment:
+//!
+//! ```text
+//! light = human
+//! dark = cpu
+//! theme = redblack
+//! glyphs = fancy
+//! variant = casual
+//! depth = 5
+//! think-time = 2.5
+//! bind.offer-draw = o, F2
+//! ```
+//!
+//! Themes and glyph sets other than the built-in ones come from `*.theme` files in the `themes` directory beside this
+//! file, in the same format (see `frontend::theme`), and puzzle mode keeps the puzzles solved in `puzzles`.  `bind.`
+//! lines give the keys for one of the curses frontend's commands, see `frontend::keys`.  Every setting is optional.
+//! Command line options override the file, and changing options in the menu writes them back.
+
+use crate::backend::{SearchLimits, Variant};
+use crate::frontend::keys::{self, KeyBindings};
+use crate::frontend::{Appearance, Preferences};
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub preferences: Preferences,
+    pub appearance: Appearance,
+    pub variant: Variant,
+    pub ai_limits: SearchLimits,
+    pub bindings: KeyBindings,
+}
+impl Config {
+    /// Reads the file at `path`; a missing file is just the defaults.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the whole file, creating its directory if need be.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        parse_lines(text, |key, value| config.set(key, value))?;
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let player = |value: &str| match value.to_lowercase().as_str() {
+            "human" => Ok("Human"),
+            "cpu"   => Ok("CPU"),
+            _ => Err(format!("'{}' is not human or cpu", value)),
+        };
+        match key {
+            "light"        => self.preferences.players[0] = player(value)?,
+            "dark"         => self.preferences.players[1] = player(value)?,
+            // Themes used to be called colour schemes
+            "theme" | "color-scheme" => self.appearance.theme = value.to_lowercase(),
+            "glyphs"       => self.appearance.glyphs = value.to_lowercase(),
+            "variant"      => self.variant = value.parse::<Variant>()?,
+            "depth"        => self.ai_limits.depth = Some(value.parse().map_err(|_| format!("'{}' is not a depth", value))?),
+            "think-time"   => self.ai_limits.time = Some(value.parse().ok().filter(|secs: &f64| secs.is_finite())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(|| format!("'{}' is not a number of seconds", value))?),
+            _ => match key.strip_prefix("bind.") {
+                Some(command) => self.bindings.set(command.parse()?, keys::parse_keys(value)?),
+                None => return Err(format!("unknown setting '{}'", key)),
+            },
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefs = &self.preferences;
+        writeln!(f, "# Checkers settings, rewritten when options are changed in the menu")?;
+        writeln!(f, "light = {}", prefs.players[0].to_lowercase())?;
+        writeln!(f, "dark = {}", prefs.players[1].to_lowercase())?;
+        writeln!(f, "theme = {}", self.appearance.theme)?;
+        writeln!(f, "glyphs = {}", self.appearance.glyphs)?;
+        writeln!(f, "variant = {}", self.variant.to_string().to_lowercase())?;
+        if let Some(depth) = self.ai_limits.depth {
+            writeln!(f, "depth = {}", depth)?;
+        }
+        if let Some(time) = self.ai_limits.time {
+            writeln!(f, "think-time = {}", time.as_secs_f64())?;
+        }
+        for (command, keys) in self.bindings.changed() {
+            writeln!(f, "bind.{} = {}", command.name(), keys::format_keys(keys))?;
+        }
+        Ok(())
+    }
+}
+
+/// Feeds each `key = value` line of `text` to `set`, skipping blank lines and `#` comments.  Errors say which line
+/// they came from.
+pub(crate) fn parse_lines<F: FnMut(&str, &str) -> Result<(), String>>(text: &str, mut set: F) -> Result<(), String> {
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected 'key = value'", idx + 1))?;
+        set(key.trim(), value.trim()).map_err(|err| format!("line {}: {}", idx + 1, err))?;
+    }
+    Ok(())
+}
+
+/// Where the config file lives, if there is anywhere to put it.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("checkers").join("config"))
+}
+
+/// Directory of theme files for the config file at `path`
+pub fn themes_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or_else(|| Path::new(".")).join("themes")
+}
+
+/// Progress file for puzzle mode, beside the config file at `path`
+pub fn puzzle_progress_path(path: &Path) -> PathBuf {
+    path.with_file_name("puzzles")
+}
+
+/// Stores the options chosen in a menu, keeping the rest of the file.  Leaves a file it can't read alone.
+pub fn save_menu_choices(path: &Path, preferences: Preferences, appearance: &Appearance) -> io::Result<()> {
+    let mut config = Config::load(path)?;
+    config.preferences = preferences;
+    config.appearance = appearance.clone();
+    config.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::keys::{Command, Key};
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse("# mine\ndark = Human\n\ncolor-scheme = WhiteRed  # red pieces\nglyphs = ascii\n\
+                                    variant = american\ndepth = 7\nthink-time = 1.5\nbind.hint = Hash\n").unwrap();
+        assert_eq!(config.preferences.players, ["Human", "Human"]);
+        assert_eq!(config.appearance, Appearance{ theme: "whitered".to_string(), glyphs: "ascii".to_string() });
+        assert_eq!(config.variant, Variant::American);
+        assert_eq!(config.ai_limits, SearchLimits{ depth: Some(7), time: Some(Duration::from_millis(1500)) });
+        assert_eq!(config.bindings.keys(Command::Hint), [Key::Char('#')]);
+        assert_eq!(Config::parse(&config.to_string()), Ok(config));
+
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+        assert_eq!(Config::parse("light = robot"), Err("line 1: 'robot' is not human or cpu".to_string()));
+        assert!(Config::parse("\nspeed = 11").unwrap_err().starts_with("line 2"));
+        assert!(Config::parse("depth").is_err());
+        for time in ["-1", "inf", "1e30"] {
+            assert!(Config::parse(&format!("think-time = {}", time)).is_err());
+        }
+        assert!(Config::parse("bind.hint = Hyper").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Encoding of DamExchange messages.  Every message is plain ASCII, starts with a one letter type and is terminated by
+//! a NUL byte on the wire (framing is handled by `Connection`).
+//!
+//! DXP was written for 10x10 boards; on our 8x8 board square numbers run 1-32 and custom positions have 32 squares.
+
+use crate::backend::{notation, Board, Piece, PieceType, Team};
+
+use std::fmt;
+
+pub const VERSION: u8 = 1;
+const NAME_WIDTH: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    message: String,
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+fn error<T>(message: String) -> Result<T, DecodeError> {
+    Err(DecodeError { message })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Acceptance {
+    Accepted,
+    WrongColor,
+    WrongTime,
+    WrongMoves,
+    WrongPosition,
+    Declined,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackAcceptance {
+    Accepted,
+    NotSupported,
+    Declined,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEndReason {
+    Unknown,
+    ILose,
+    Draw,
+    IWin,
+}
+impl GameEndReason {
+    /// The same result from the other side's point of view
+    pub fn reversed(self) -> GameEndReason {
+        match self {
+            GameEndReason::ILose => GameEndReason::IWin,
+            GameEndReason::IWin  => GameEndReason::ILose,
+            reason => reason,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    GameRequest {
+        version: u8,
+        name: String,
+        /// Team the follower (the side receiving the request) will play.
+        follower_team: Team,
+        minutes: u16,
+        moves: u16,
+        /// Custom starting position and side to move, or `None` for the normal start.
+        position: Option<(Board, Team)>,
+    },
+    GameAccept {
+        name: String,
+        acceptance: Acceptance,
+    },
+    Move {
+        seconds: u16,
+        from: u8,
+        to: u8,
+        captured: Vec<u8>,
+    },
+    GameEnd {
+        reason: GameEndReason,
+        /// Whether the sender is up for another game.
+        next_game: bool,
+    },
+    Chat(String),
+    BackRequest {
+        move_number: u16,
+        to_move: Team,
+    },
+    BackAccept(BackAcceptance),
+}
+
+fn team_char(team: Team) -> char {
+    match team {
+        Team::Light => 'W',
+        Team::Dark  => 'Z',
+    }
+}
+
+fn parse_team(c: char) -> Result<Team, DecodeError> {
+    match c {
+        'W' | 'w' => Ok(Team::Light),
+        'Z' | 'z' | 'B' | 'b' => Ok(Team::Dark),
+        _ => error(format!("bad color '{}'", c)),
+    }
+}
+
+fn acceptance_char(acceptance: Acceptance) -> char {
+    match acceptance {
+        Acceptance::Accepted      => '0',
+        Acceptance::WrongColor    => '1',
+        Acceptance::WrongTime     => '2',
+        Acceptance::WrongMoves    => '3',
+        Acceptance::WrongPosition => '4',
+        Acceptance::Declined      => '9',
+    }
+}
+
+fn parse_acceptance(c: char) -> Acceptance {
+    match c {
+        '0' => Acceptance::Accepted,
+        '1' => Acceptance::WrongColor,
+        '2' => Acceptance::WrongTime,
+        '3' => Acceptance::WrongMoves,
+        '4' => Acceptance::WrongPosition,
+        _   => Acceptance::Declined,
+    }
+}
+
+impl Message {
+    pub fn encode(&self) -> String {
+        match self {
+            Message::GameRequest{ version, name, follower_team, minutes, moves, position } => {
+                let mut result = format!("R{:02}{:<width$.width$}{}{:03}{:03}",
+                                         version, name, team_char(*follower_team), minutes, moves, width=NAME_WIDTH);
+                match position {
+                    None => result.push('A'),
+                    Some((board, to_move)) => {
+                        result.push('B');
+                        result.push(team_char(*to_move));
+                        for number in 1..=notation::NUM_SQUARES {
+                            let square = notation::number_square(number).unwrap();
+                            result.push(match board.get_piece_at(&square) {
+                                Some(Piece{ team: Team::Light, piece_type: PieceType::Man })  => 'w',
+                                Some(Piece{ team: Team::Light, piece_type: PieceType::King }) => 'W',
+                                Some(Piece{ team: Team::Dark,  piece_type: PieceType::Man })  => 'z',
+                                Some(Piece{ team: Team::Dark,  piece_type: PieceType::King }) => 'Z',
+                                None => 'e',
+                            });
+                        }
+                    },
+                }
+                result
+            },
+            Message::GameAccept{ name, acceptance } => {
+                format!("A{:<width$.width$}{}", name, acceptance_char(*acceptance), width=NAME_WIDTH)
+            },
+            Message::Move{ seconds, from, to, captured } => {
+                let captured: String = captured.iter().map(|square| format!("{:02}", square)).collect();
+                format!("M{:04}{:02}{:02}{:02}{}", seconds, from, to, captured.len() / 2, captured)
+            },
+            Message::GameEnd{ reason, next_game } => {
+                let reason = match reason {
+                    GameEndReason::Unknown => '0',
+                    GameEndReason::ILose   => '1',
+                    GameEndReason::Draw    => '2',
+                    GameEndReason::IWin    => '3',
+                };
+                format!("E{}{}", reason, if *next_game { '0' } else { '1' })
+            },
+            Message::Chat(text) => format!("C{}", text),
+            Message::BackRequest{ move_number, to_move } => format!("B{:03}{}", move_number, team_char(*to_move)),
+            Message::BackAccept(acceptance) => format!("K{}", match acceptance {
+                BackAcceptance::Accepted     => '0',
+                BackAcceptance::NotSupported => '1',
+                BackAcceptance::Declined     => '2',
+            }),
+        }
+    }
+
+    pub fn decode(text: &str) -> Result<Message, DecodeError> {
+        if !text.is_ascii() {
+            return error(format!("message is not ASCII: {:?}", text));
+        }
+        let field = |start: usize, len: usize| -> Result<&str, DecodeError> {
+            text.get(start..start + len).map_or_else(|| error(format!("message too short: {:?}", text)), Ok)
+        };
+        let number = |start: usize, len: usize| -> Result<u16, DecodeError> {
+            let value = field(start, len)?;
+            value.trim().parse::<u16>().or_else(|_| error(format!("bad number '{}' in {:?}", value, text)))
+        };
+        let char_at = |idx: usize| -> Result<char, DecodeError> {
+            field(idx, 1).map(|s| s.chars().next().unwrap())
+        };
+
+        match char_at(0)? {
+            'R' => {
+                let position = match char_at(42)? {
+                    'A' => None,
+                    'B' => {
+                        let to_move = parse_team(char_at(43)?)?;
+                        let squares = field(44, notation::NUM_SQUARES as usize)?;
+                        let mut board = Board::empty();
+                        for (idx, c) in squares.chars().enumerate() {
+                            let piece_type = match c {
+                                'w' | 'z' => PieceType::Man,
+                                'W' | 'Z' => PieceType::King,
+                                'e' => continue,
+                                _ => return error(format!("bad square '{}' in {:?}", c, text)),
+                            };
+                            let piece = Piece{ team: parse_team(c)?, piece_type };
+                            board.place_piece(notation::number_square(idx as u8 + 1).unwrap(), piece);
+                        }
+                        Some((board, to_move))
+                    },
+                    c => return error(format!("bad starting position type '{}'", c)),
+                };
+                Ok(Message::GameRequest {
+                    version: number(1, 2)? as u8,
+                    name: field(3, NAME_WIDTH)?.trim_end().to_string(),
+                    follower_team: parse_team(char_at(35)?)?,
+                    minutes: number(36, 3)?,
+                    moves: number(39, 3)?,
+                    position,
+                })
+            },
+            'A' => Ok(Message::GameAccept {
+                name: field(1, NAME_WIDTH)?.trim_end().to_string(),
+                acceptance: parse_acceptance(char_at(1 + NAME_WIDTH)?),
+            }),
+            'M' => {
+                let num_captured = number(9, 2)? as usize;
+                let captured = (0..num_captured)
+                    .map(|idx| number(11 + idx * 2, 2).map(|n| n as u8))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Message::Move {
+                    seconds: number(1, 4)?,
+                    from: number(5, 2)? as u8,
+                    to: number(7, 2)? as u8,
+                    captured,
+                })
+            },
+            'E' => Ok(Message::GameEnd {
+                reason: match char_at(1)? {
+                    '1' => GameEndReason::ILose,
+                    '2' => GameEndReason::Draw,
+                    '3' => GameEndReason::IWin,
+                    _   => GameEndReason::Unknown,
+                },
+                next_game: char_at(2)? == '0',
+            }),
+            'C' => Ok(Message::Chat(text[1..].to_string())),
+            'B' => Ok(Message::BackRequest {
+                move_number: number(1, 3)?,
+                to_move: parse_team(char_at(4)?)?,
+            }),
+            'K' => Ok(Message::BackAccept(match char_at(1)? {
+                '0' => BackAcceptance::Accepted,
+                '1' => BackAcceptance::NotSupported,
+                _   => BackAcceptance::Declined,
+            })),
+            c => error(format!("unknown message type '{}'", c)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(msg: Message) -> String {
+        let encoded = msg.encode();
+        assert_eq!(Message::decode(&encoded), Ok(msg));
+        encoded
+    }
+
+    #[test]
+    fn test_game_request() {
+        let encoded = round_trip(Message::GameRequest {
+            version: VERSION,
+            name: "checkers".to_string(),
+            follower_team: Team::Dark,
+            minutes: 10,
+            moves: 50,
+            position: None,
+        });
+        assert_eq!(encoded.len(), 43);
+        assert!(encoded.starts_with("R01checkers "));
+        assert!(encoded.ends_with("Z010050A"));
+
+        let squares = "z".repeat(12) + &"e".repeat(8) + &"w".repeat(12);
+        match Message::decode(&format!("R01{:<32}W005075BZ{}", "peer", squares)) {
+            Ok(Message::GameRequest{ position: Some((board, to_move)), .. }) => {
+                assert_eq!(to_move, Team::Dark);
+                assert_eq!(board.pieces_alive(Team::Light), 12);
+                assert_eq!(board.pieces_alive(Team::Dark), 12);
+            },
+            msg => panic!("Unexpected decode result: {:?}", msg.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_other_messages() {
+        assert_eq!(round_trip(Message::GameAccept{ name: "peer".to_string(), acceptance: Acceptance::Accepted }).len(), 34);
+        assert_eq!(round_trip(Message::Move{ seconds: 12, from: 22, to: 18, captured: vec![] }), "M0012221800");
+        assert_eq!(round_trip(Message::Move{ seconds: 3, from: 15, to: 31, captured: vec![19, 26] }), "M00031531021926");
+        assert_eq!(round_trip(Message::GameEnd{ reason: GameEndReason::IWin, next_game: false }), "E31");
+        assert_eq!(round_trip(Message::Chat("good luck".to_string())), "Cgood luck");
+        assert_eq!(round_trip(Message::BackRequest{ move_number: 7, to_move: Team::Light }), "B007W");
+        assert_eq!(round_trip(Message::BackAccept(BackAcceptance::NotSupported)), "K1");
+
+        assert!(Message::decode("M00").is_err());
+        assert!(Message::decode("Q").is_err());
+        assert!(Message::decode("").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! DamExchange Protocol (DXP) play over TCP.  The remote peer takes one side of a `Game` and our AI plays the other.
+//!
+//! The session sits on the frontend end of the `channel` protocol: it starts the game, answers the backend's move
+//! requests for the remote team with moves received from the peer, and forwards our AI's moves to the peer.  Games are
+//! played under American rules, with the clock and starting position the game request asks for.
+
+mod message;
+
+pub use message::{Acceptance, BackAcceptance, GameEndReason, Message};
+
+use crate::args::{Args, DxpArgs, DxpRole};
+use crate::backend::{notation, Board, Game, GameResult, Move, Team, TimeControl, Variant};
+use crate::channel::{self, BackToFrontMessage, FrontendEndpoint, FrontToBackMessage, LogLevel};
+use crate::frontend::Preferences;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 27531;
+const NAME: &str = "checkers";
+
+/// A TCP stream framed into NUL-terminated DXP messages.
+pub struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        Ok(Connection {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    pub fn send(&mut self, msg: &Message) -> io::Result<()> {
+        self.writer.write_all(msg.encode().as_bytes())?;
+        self.writer.write_all(&[0])?;
+        self.writer.flush()
+    }
+
+    /// Blocks until the next message arrives.  A closed connection is reported as `UnexpectedEof`.
+    pub fn recv(&mut self) -> io::Result<Message> {
+        let mut buf = Vec::new();
+        if self.reader.read_until(0, &mut buf)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "DXP peer disconnected"));
+        }
+        if buf.last() == Some(&0) {
+            buf.pop();
+        }
+        let text = String::from_utf8_lossy(&buf);
+        Message::decode(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    /// Splits into a sending half and a receiving half that keeps anything already buffered.
+    fn split(self) -> io::Result<(Connection, Connection)> {
+        let sender = Connection::new(self.writer.try_clone()?)?;
+        let receiver = Connection {
+            writer: self.writer,
+            reader: self.reader,
+        };
+        Ok((sender, receiver))
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A game request we accepted
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameTerms {
+    /// The initiator's name
+    pub name: String,
+    /// The side we play
+    pub team: Team,
+    pub minutes: u16,
+    pub moves: u16,
+    /// Position to start from and the side to move; the usual opening if `None`
+    pub position: Option<(Board, Team)>,
+}
+
+/// The clock for `minutes` per player for every `moves` moves, or for the whole game if `moves` is 0.  Our clocks don't
+/// start a new period, so a move count becomes a Fischer increment giving the same time per move on average.  No
+/// minutes means no clock.
+pub fn time_control(minutes: u16, moves: u16) -> Option<TimeControl> {
+    let base = Duration::from_secs(u64::from(minutes) * 60);
+    match (minutes, moves) {
+        (0, _) => None,
+        (_, 0) => Some(TimeControl::SuddenDeath{ base }),
+        (_, moves) => Some(TimeControl::Fischer{ base, increment: base / u32::from(moves) }),
+    }
+}
+
+/// Initiates a game, asking the follower to play `follower_team` from `position` (or the usual opening).  Returns the
+/// follower's name.
+pub fn request_game(conn: &mut Connection, follower_team: Team, minutes: u16, moves: u16,
+                    position: Option<(Board, Team)>) -> io::Result<String> {
+    conn.send(&Message::GameRequest {
+        version: message::VERSION,
+        name: NAME.to_string(),
+        follower_team,
+        minutes,
+        moves,
+        position,
+    })?;
+    match conn.recv()? {
+        Message::GameAccept{ name, acceptance: Acceptance::Accepted } => Ok(name),
+        Message::GameAccept{ name, acceptance } => Err(invalid_data(format!("{} refused the game: {:?}", name, acceptance))),
+        msg => Err(invalid_data(format!("Expected GAMEACC, got {:?}", msg))),
+    }
+}
+
+/// Waits for a game request and accepts it if we can play it, refusing starting positions a game can't be played from.
+pub fn accept_game(conn: &mut Connection) -> io::Result<GameTerms> {
+    loop {
+        match conn.recv()? {
+            Message::GameRequest{ name, follower_team, minutes, moves, position, .. } => {
+                let playable = position.as_ref().is_none_or(|(board, to_move)| board.problems(*to_move).is_empty());
+                let acceptance = if playable { Acceptance::Accepted } else { Acceptance::WrongPosition };
+                conn.send(&Message::GameAccept{ name: NAME.to_string(), acceptance })?;
+                if acceptance == Acceptance::Accepted {
+                    return Ok(GameTerms{ name, team: follower_team, minutes, moves, position });
+                }
+            },
+            Message::Chat(text) => println!("Peer says: {}", text),
+            msg => return Err(invalid_data(format!("Expected GAMEREQ, got {:?}", msg))),
+        }
+    }
+}
+
+pub fn run(args: Args, dxp_args: DxpArgs) -> io::Result<()> {
+    let (conn, terms) = match &dxp_args.role {
+        DxpRole::Listen(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            println!("Waiting for a DXP connection on {}", listener.local_addr()?);
+            let (stream, peer) = listener.accept()?;
+            println!("Connection from {}", peer);
+            let mut conn = Connection::new(stream)?;
+            let terms = accept_game(&mut conn)?;
+            println!("Accepted game from {}, we play {:?}", terms.name, terms.team);
+            (conn, terms)
+        },
+        DxpRole::Connect(addr) => {
+            let mut conn = Connection::new(TcpStream::connect(addr)?)?;
+            let position = args.game.position.clone();
+            let name = request_game(&mut conn, dxp_args.team.other(), dxp_args.minutes, dxp_args.moves, position.clone())?;
+            println!("{} accepted the game, we play {:?}", name, dxp_args.team);
+            (conn, GameTerms{ name, team: dxp_args.team, minutes: dxp_args.minutes, moves: dxp_args.moves, position })
+        },
+    };
+
+    let (backend_endpoint, frontend_endpoint) = channel::make_two_way_channel();
+    let mut game_args = args.clone();
+    game_args.game.variant = Variant::American;
+    game_args.time_control = time_control(terms.minutes, terms.moves);
+    game_args.game.position = terms.position.clone();
+    let first_team = terms.position.as_ref().map_or_else(|| Variant::American.first_team(), |(_, to_move)| *to_move);
+    thread::spawn(move || {
+        let mut game = Game::new(game_args, backend_endpoint);
+        game.start();
+    });
+
+    let verbosity = if args.debug { LogLevel::Debug } else { LogLevel::Ai };
+    Session::new(terms.team, first_team, frontend_endpoint, conn, verbosity)?.play()
+}
+
+enum Event {
+    Backend(BackToFrontMessage),
+    BackendClosed,
+    Peer(io::Result<Message>),
+}
+
+struct Session {
+    local_team: Team,
+    backend_tx: mpsc::Sender<FrontToBackMessage>,
+    events: mpsc::Receiver<Event>,
+    peer: Connection,
+    board: Board,
+    to_move: Team,
+    turn_start: Instant,
+    peer_moves: VecDeque<Message>,
+    remote_path: VecDeque<Move>,
+    awaiting_remote: bool,
+    game_result: Option<GameResult>,
+    /// Most detailed log level printed
+    verbosity: LogLevel,
+}
+impl Session {
+    fn new(local_team: Team, first_team: Team, endpoint: FrontendEndpoint, peer: Connection, verbosity: LogLevel)
+           -> io::Result<Session> {
+        let (tx, rx) = mpsc::channel();
+        let FrontendEndpoint{ tx: backend_tx, rx: backend_rx } = endpoint;
+
+        let backend_events = tx.clone();
+        thread::spawn(move || {
+            for msg in backend_rx {
+                if backend_events.send(Event::Backend(msg)).is_err() {
+                    return;
+                }
+            }
+            backend_events.send(Event::BackendClosed).ok();
+        });
+
+        let (peer, mut reader) = peer.split()?;
+        thread::spawn(move || loop {
+            let msg = reader.recv();
+            let closed = msg.is_err();
+            if tx.send(Event::Peer(msg)).is_err() || closed {
+                return;
+            }
+        });
+
+        Ok(Session {
+            local_team,
+            backend_tx,
+            events: rx,
+            peer,
+            board: Board::new().with_variant(Variant::American),
+            to_move: first_team,
+            turn_start: Instant::now(),
+            peer_moves: VecDeque::new(),
+            remote_path: VecDeque::new(),
+            awaiting_remote: false,
+            game_result: None,
+            verbosity,
+        })
+    }
+
+    fn play(mut self) -> io::Result<()> {
+        let mut players = ["Human", "Human"];
+        players[self.local_team as usize] = "CPU";
+        self.send_backend(FrontToBackMessage::StartGame(Preferences{ players }));
+
+        while let Ok(event) = self.events.recv() {
+            match event {
+                Event::Backend(msg) => self.handle_backend(msg)?,
+                Event::BackendClosed => {
+                    let reason = self.result();
+                    println!("Game over ({:?})", reason);
+                    self.peer.send(&Message::GameEnd{ reason, next_game: false })?;
+                    return Ok(());
+                },
+                Event::Peer(Ok(Message::GameEnd{ reason, .. })) => {
+                    println!("Peer ended the game ({:?})", reason);
+                    // Unless our game has finished, the peer knows better than a guess from the position
+                    let reply = match self.game_result {
+                        Some(_) => self.result(),
+                        None => reason.reversed(),
+                    };
+                    self.peer.send(&Message::GameEnd{ reason: reply, next_game: false })?;
+                    return Ok(());
+                },
+                Event::Peer(Ok(msg)) => self.handle_peer(msg)?,
+                Event::Peer(Err(err)) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_backend(&mut self, msg: BackToFrontMessage) -> io::Result<()> {
+        match msg {
+            BackToFrontMessage::Log{ level: LogLevel::Info, msg } => println!("{}", msg),
+            BackToFrontMessage::Log{ level, msg } => if level <= self.verbosity {
+                println!("[{}] {}", level, msg);
+            },
+            BackToFrontMessage::BoardState(board) => self.board = board,
+            BackToFrontMessage::RequestMove(_) => {
+                self.awaiting_remote = true;
+                self.play_peer_move()?;
+            },
+            BackToFrontMessage::RequestJump(..) => match self.remote_path.pop_front() {
+                Some(mv) => self.send_backend(FrontToBackMessage::Move(mv)),
+                None => self.send_backend(FrontToBackMessage::CancelMove),
+            },
+            BackToFrontMessage::MoveMade(team, moves) => {
+                if team == self.local_team {
+                    let (from, to, captured) = notation::turn_summary(&moves);
+                    let seconds = std::cmp::min(self.turn_start.elapsed().as_secs(), 9999) as u16;
+                    self.peer.send(&Message::Move{ seconds, from, to, captured })?;
+                }
+                self.to_move = team.other();
+                self.turn_start = Instant::now();
+            },
+            BackToFrontMessage::GameOver(result) => self.game_result = Some(result),
+            // DXP games keep their own time, and the peer is never asked about draws
+            BackToFrontMessage::Clock(_) | BackToFrontMessage::DrawOffered(_) => (),
+        };
+
+        Ok(())
+    }
+
+    fn handle_peer(&mut self, msg: Message) -> io::Result<()> {
+        match msg {
+            Message::Move{ .. } => {
+                self.peer_moves.push_back(msg);
+                self.play_peer_move()?;
+            },
+            Message::Chat(text) => println!("Peer says: {}", text),
+            Message::BackRequest{ .. } => self.peer.send(&Message::BackAccept(BackAcceptance::NotSupported))?,
+            msg => println!("Ignoring unexpected DXP message {:?}", msg),
+        };
+
+        Ok(())
+    }
+
+    /// Hands the peer's next move to the backend, once the backend has asked for it.
+    fn play_peer_move(&mut self) -> io::Result<()> {
+        if !self.awaiting_remote {
+            return Ok(());
+        }
+        let (from, to, captured) = match self.peer_moves.pop_front() {
+            Some(Message::Move{ from, to, captured, .. }) => (from, to, captured),
+            _ => return Ok(()),
+        };
+        let turn = notation::find_turn(&self.board, self.local_team.other(), from, to, &captured)
+            .ok_or_else(|| invalid_data(format!("Peer sent illegal move {}-{} capturing {:?}", from, to, captured)))?;
+
+        self.awaiting_remote = false;
+        self.remote_path = turn.into_iter().collect();
+        let first = self.remote_path.pop_front().unwrap();
+        self.send_backend(FrontToBackMessage::Move(first));
+
+        Ok(())
+    }
+
+    /// Game result from our point of view, as the game decided it or else judged from the final position.
+    fn result(&self) -> GameEndReason {
+        if let Some(result) = self.game_result {
+            return match result.winner {
+                Some(team) if team == self.local_team => GameEndReason::IWin,
+                Some(_) => GameEndReason::ILose,
+                None => GameEndReason::Draw,
+            };
+        }
+        if self.board.pieces_alive(self.local_team) == 0 {
+            GameEndReason::ILose
+        } else if self.board.pieces_alive(self.local_team.other()) == 0 {
+            GameEndReason::IWin
+        } else if self.to_move == self.local_team {
+            GameEndReason::ILose // Out of moves
+        } else {
+            GameEndReason::IWin
+        }
+    }
+
+    fn send_backend(&self, msg: FrontToBackMessage) {
+        self.backend_tx.send(msg).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a session where we play Light from `fen`, returning the peer's end of the connection.
+    fn start_session(fen: &str) -> (Connection, thread::JoinHandle<io::Result<()>>) {
+        let mut args = Args::default();
+        let (board, to_move) = notation::parse_fen(fen).unwrap();
+        args.game.variant = Variant::American;
+        args.game.position = Some((board, to_move));
+        let (backend_endpoint, frontend_endpoint) = channel::make_two_way_channel();
+        thread::spawn(move || Game::new(args, backend_endpoint).start());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap()).unwrap();
+        let conn = Connection::new(listener.accept().unwrap().0).unwrap();
+        let session = thread::spawn(move || Session::new(Team::Light, to_move, frontend_endpoint, conn, LogLevel::Info)?.play());
+        (peer, session)
+    }
+
+    #[test]
+    fn test_session_plays_peer_jump() {
+        // The peer's double jump reaches the backend one hop at a time and leaves us with nothing
+        let (mut peer, session) = start_session("B:W14,23:B9");
+        peer.send(&Message::Move{ seconds: 3, from: 9, to: 27, captured: vec![14, 23] }).unwrap();
+        assert_eq!(peer.recv().unwrap(), Message::GameEnd{ reason: GameEndReason::ILose, next_game: false });
+        session.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_session_answers_peer_game_end() {
+        let (mut peer, session) = start_session("B:W14,23:B9");
+        peer.send(&Message::GameEnd{ reason: GameEndReason::ILose, next_game: false }).unwrap();
+        assert_eq!(peer.recv().unwrap(), Message::GameEnd{ reason: GameEndReason::IWin, next_game: false });
+        session.join().unwrap().unwrap();
+
+        let (mut peer, session) = start_session("B:W14,23:B9");
+        peer.send(&Message::GameEnd{ reason: GameEndReason::Unknown, next_game: false }).unwrap();
+        assert_eq!(peer.recv().unwrap(), Message::GameEnd{ reason: GameEndReason::Unknown, next_game: false });
+        session.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_loopback_handshake_and_move() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let follower = thread::spawn(move || {
+            let mut conn = Connection::new(listener.accept().unwrap().0).unwrap();
+            let terms = accept_game(&mut conn).unwrap();
+            let mv = conn.recv().unwrap();
+            conn.send(&Message::Move{ seconds: 1, from: 9, to: 14, captured: vec![] }).unwrap();
+            (terms, mv)
+        });
+
+        let mut conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        assert_eq!(request_game(&mut conn, Team::Dark, 5, 40, None).unwrap(), NAME);
+        conn.send(&Message::Move{ seconds: 0, from: 22, to: 18, captured: vec![] }).unwrap();
+        assert_eq!(conn.recv().unwrap(), Message::Move{ seconds: 1, from: 9, to: 14, captured: vec![] });
+
+        let (terms, mv) = follower.join().unwrap();
+        assert_eq!(terms, GameTerms{ name: NAME.to_string(), team: Team::Dark, minutes: 5, moves: 40, position: None });
+        assert_eq!(mv, Message::Move{ seconds: 0, from: 22, to: 18, captured: vec![] });
+    }
+
+    #[test]
+    fn test_time_control() {
+        assert_eq!(time_control(0, 40), None);
+        assert_eq!(time_control(5, 0), Some(TimeControl::SuddenDeath{ base: Duration::from_secs(300) }));
+        assert_eq!(time_control(5, 40),
+                   Some(TimeControl::Fischer{ base: Duration::from_secs(300), increment: Duration::from_millis(7500) }));
+    }
+
+    #[test]
+    fn test_custom_position() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let follower = thread::spawn(move || {
+            let mut conn = Connection::new(listener.accept().unwrap().0).unwrap();
+            accept_game(&mut conn)
+        });
+
+        // A position without Light pieces is refused, and the follower waits for another request
+        let mut conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        let empty = notation::parse_fen("B:W:B9").unwrap();
+        assert!(request_game(&mut conn, Team::Light, 5, 0, Some(empty)).is_err());
+        let position = notation::parse_fen("B:W14,23:B9").unwrap();
+        assert_eq!(request_game(&mut conn, Team::Light, 5, 0, Some(position.clone())).unwrap(), NAME);
+        assert_eq!(follower.join().unwrap().unwrap().position, Some(position));
+    }
+}
@@ -1,138 +1,137 @@
+use super::controller::{MoveController, Selection};
 use super::cursor_input::CursorInput;
-use super::log::LogView;
-use super::menu::{Color, ColorScheme, Preferences};
+use super::glyphs::GlyphSet;
+use super::theme::{self, Ink, Paper, Theme};
 
 use crate::backend;
-use backend::{Board, Move, Piece, PieceType, Square, Team};
-use crate::channel::FrontendEndpoint;
+use backend::{notation, Ai, Board, Move, SearchLimits, Square, Team, DEFAULT_DEPTH};
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::time::Duration;
 
 use pancurses::{
-    A_BLINK, A_NORMAL,
+    A_BOLD, A_DIM, A_NORMAL, A_REVERSE, A_UNDERLINE, chtype,
     ACS_HLINE, ACS_VLINE,
     Input,
 };
 
-enum State {
-    Waiting,
-    ChoosingPiece(Team),
-    ChoosingMove(Team, Square, Vec<Move>, bool),
+/// Squares are drawn this wide normally, and wider when the terminal has room
+pub const SQUARE_WIDTHS: [i32; 2] = [3, 5];
+
+/// Which hints the board draws.  Each can be toggled while playing.
+#[derive(Clone, Copy, Debug)]
+pub struct Highlights {
+    /// Mark where the selected piece can move to
+    pub destinations: bool,
+    /// Underline pieces that can capture
+    pub captures: bool,
+    /// Show the squares the last move passed through
+    pub last_move: bool,
+    /// Keep the cursor on legal destinations while choosing a move
+    pub restrict_cursor: bool,
+}
+impl Default for Highlights {
+    fn default() -> Highlights {
+        Highlights {
+            destinations: true,
+            captures: true,
+            last_move: true,
+            restrict_cursor: true,
+        }
+    }
 }
 
-pub const SQUARE_WIDTH: usize = 3;
+/// What's written around (or on) the board to name squares
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Labels {
+    /// Files a-h along the bottom and ranks 1-8 up the side, from Light's point of view
+    Coordinates,
+    /// The 1-32 numbers used by standard notation, on empty playable squares
+    Numbers,
+    Off,
+}
+impl Labels {
+    pub fn next(self) -> Labels {
+        match self {
+            Labels::Coordinates => Labels::Numbers,
+            Labels::Numbers     => Labels::Off,
+            Labels::Off         => Labels::Coordinates,
+        }
+    }
+}
 
 pub struct BoardView {
-    preferences: Preferences,
+    theme: Theme,
+    glyphs: GlyphSet,
     board: Board,
     window: pancurses::Window,
-
-    log: Rc<RefCell<LogView>>, // TODO not sure if this is the best way to do this
-    backend_channel: Rc<RefCell<FrontendEndpoint>>,
+    square_width: i32,
 
     cursor: Square,
-    state: State,
+    pub controller: MoveController,
+    pub highlights: Highlights,
+    pub labels: Labels,
+    /// Draw Dark at the bottom
+    pub flipped: bool,
+    /// Every hop of the last turn taken
+    last_move: Vec<Move>,
 }
 impl BoardView {
-    pub fn new(
-        preferences: Preferences,
-        window: pancurses::Window,
-        log: Rc<RefCell<LogView>>,
-        backend_channel: Rc<RefCell<FrontendEndpoint>>,
-    ) -> BoardView {
+    pub fn new(theme: Theme, glyphs: GlyphSet, window: pancurses::Window, square_width: i32) -> BoardView {
         let result = BoardView {
-            preferences: preferences,
+            theme,
+            glyphs,
             board: Board::new(),
-            window: window,
-            log: log,
-            backend_channel: backend_channel,
+            window,
+            square_width,
             cursor: Square{ x: 0, y: 7 },
-            state: State::Waiting,
+            controller: MoveController::new(),
+            highlights: Highlights::default(),
+            labels: Labels::Coordinates,
+            flipped: false,
+            last_move: Vec::new(),
         };
         result.window.draw_box(ACS_VLINE(), ACS_HLINE());
 
         result
     }
 
-    pub fn start_selecting_piece(&mut self, team: Team) {
-        self.state = State::ChoosingPiece(team);
+    /// Lines and columns needed to draw the board, border included
+    pub fn size(square_width: i32) -> (i32, i32) {
+        (2 + Board::SIZE as i32, 2 + Board::SIZE as i32 * square_width)
     }
 
-    pub fn continue_jumping(&mut self, team: Team, square: Square, valid_moves: Vec<Move>) {
-        self.state = State::ChoosingMove(team, square, valid_moves, true);
+    /// Moves the board to a new window after the layout changes
+    pub fn set_window(&mut self, window: pancurses::Window, square_width: i32) {
+        self.window = window;
+        self.square_width = square_width;
     }
 
-    fn process_state(&self) -> Option<State> {
-        match &self.state {
-            State::Waiting => None,
-            State::ChoosingPiece(team) => {
-                // log!(self.log, "choosing piece.. {:?}", team);
-                match self.board.get_piece_at(&self.cursor) {
-                    Some(piece) if piece.team == *team => {
-                        let valid_moves = self.board.get_valid_moves_for_piece_at(&self.cursor);
-                        if valid_moves.is_empty() {
-                            // log!(self.log, "Piece at {} has no valid moves", self.cursor);
-                            return None;
-                        }
-                        // log!(self.log, "valid moves: {:?}", valid_moves);
-                        return Some(State::ChoosingMove(*team, self.cursor, valid_moves, false));
-                    },
-                    _ => return None,
-                };
-            },
-            State::ChoosingMove(team, piece_pos, valid_moves, only_jumps) => {
-                // log!(self.log, "choosing move.. {:?} {:?}", valid_moves, only_jumps);
-                if self.cursor == *piece_pos {
-                    // Cancel move
-                    if *only_jumps {
-                        log!(self.log, "Jump canceled");
-                        self.send_cancel_move_to_backend();
-                        return Some(State::Waiting);
-                    } else {
-                        log!(self.log, "Move canceled");
-                        return Some(State::ChoosingPiece(*team));
-                    }
-                }
-                let mv = Move{ from: *piece_pos, to: self.cursor };
-                if valid_moves.contains(&mv) {
-                    // log!(self.log, "sending move {:?}", mv);
-                    self.send_move_to_backend(mv);
-                    return Some(State::Waiting);
-                } else {
-                    log!(self.log, "Illegal move {}", mv);
-                    return None;
-                }
-            },
+    /// Maps a square on the screen to the board square drawn there, or back again.
+    fn screen_square(&self, square: Square) -> Square {
+        if self.flipped {
+            Square{ x: Board::SIZE - 1 - square.x, y: Board::SIZE - 1 - square.y }
+        } else {
+            square
         }
     }
 
-    fn send_cancel_move_to_backend(&self) {
-        self.send_msg(crate::channel::FrontToBackMessage::CancelMove);
+    /// The board square drawn at screen position `y`, `x`, if any
+    pub fn square_at(&self, y: i32, x: i32) -> Option<Square> {
+        let (top, left) = self.window.get_beg_yx();
+        let (row, col) = (y - top - 1, x - left - 1);
+        let size = Board::SIZE as i32;
+        if row < 0 || row >= size || col < 0 || col >= size * self.square_width {
+            return None;
+        }
+        Some(self.screen_square(Square{ x: (col / self.square_width) as i8, y: row as i8 }))
     }
 
-    fn send_move_to_backend(&self, mv: Move) {
-        self.send_msg(crate::channel::FrontToBackMessage::Move(mv));
+    pub fn cursor(&self) -> Square {
+        self.cursor
     }
 
-    fn send_msg(&self, msg: crate::channel::FrontToBackMessage) {
-        self.backend_channel.borrow_mut().tx.send(msg).expect("Could not send message"); // TODO better error handling
-    }
-
-    fn get_piece_glyph(piece: Option<&Piece>, ascii: bool) -> char {
-        match piece {
-            Some(piece) => match (piece.team, piece.piece_type, ascii) {
-                (Team::Light, PieceType::Man,  true)  => 'O', // TODO better chars
-                (Team::Dark,  PieceType::Man,  true)  => '=',
-                (Team::Light, PieceType::King, true)  => '@',
-                (Team::Dark,  PieceType::King, true)  => '#',
-                (Team::Light, PieceType::Man,  false) => '⛂',
-                (Team::Dark,  PieceType::Man,  false) => '⛀',
-                (Team::Light, PieceType::King, false) => '⛃',
-                (Team::Dark,  PieceType::King, false) => '⛁',
-            },
-            None => ' ',
-        }
+    pub fn set_cursor(&mut self, square: Square) {
+        self.cursor = square;
     }
 
     pub fn set_board_state(&mut self, board: Board) {
@@ -142,56 +141,222 @@ impl BoardView {
         self.window.clearok(true);
     }
 
+    pub fn set_last_move(&mut self, moves: Vec<Move>) {
+        self.last_move = moves;
+    }
+
+    /// Puts the selected piece back, or ends a jump early, as selecting the piece again would.
+    pub fn cancel(&mut self) -> Option<Selection> {
+        let piece_pos = self.controller.selected_piece()?;
+        self.controller.select(&self.board, piece_pos)
+    }
+
+    /// A good turn for the team to move, with the cursor moved to its piece.  Mid-jump, just the next hop.
+    pub fn hint(&mut self) -> Option<Vec<Move>> {
+        const THINK_TIME: Duration = Duration::from_secs(1);
+
+        let team = self.controller.active_team()?;
+        let turn = if self.controller.is_jumping() {
+            vec![*self.controller.valid_moves().first()?]
+        } else {
+            let limits = SearchLimits{ depth: Some(DEFAULT_DEPTH), time: Some(THINK_TIME) };
+            Ai{ team }.search(self.board.clone(), limits, None, |_| ())?.moves
+        };
+        if self.controller.selected_piece().is_none() {
+            self.cursor = turn.first()?.from;
+        }
+        Some(turn)
+    }
+
+    /// Squares the selected piece can move to
+    fn destinations(&self) -> Vec<Square> {
+        self.controller.valid_moves().iter().map(|mv| mv.to).collect()
+    }
+
+    /// The start and every landing square of the last turn
+    fn last_move_path(&self) -> Vec<Square> {
+        self.last_move.first().map(|mv| mv.from).into_iter()
+            .chain(self.last_move.iter().map(|mv| mv.to))
+            .collect()
+    }
+
+    /// Pieces of the team to move that have a capture available
+    fn capturing_pieces(&self) -> Vec<Square> {
+        match self.controller.active_team() {
+            Some(_) if self.controller.is_jumping() => Vec::new(),
+            Some(team) => self.board.get_pieces().iter()
+                .filter(|(square, piece)| piece.team == team && !self.board.get_valid_jumps_for_piece_at(square).is_empty())
+                .map(|(square, _)| *square)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
     pub fn draw(&mut self) {
         let pieces = self.board.get_pieces();
-        for y in 0..Board::SIZE {
-            for x in 0..Board::SIZE {
-                // TODO blink cursor when piece selected, highlight valid moves?
-                let (left, right) = match self.state {
-                    State::ChoosingMove(_, piece_pos, _, _) if piece_pos == (Square{x, y}) => ("(", ")"),
-                    _ if self.cursor == (Square{x, y})                                     => ("[", "]"),
-                    _                                                                      => (" ", " "),
+        let destinations = if self.highlights.destinations { self.destinations() } else { Vec::new() };
+        let captures = if self.highlights.captures { self.capturing_pieces() } else { Vec::new() };
+        let last_move = if self.highlights.last_move { self.last_move_path() } else { Vec::new() };
+        self.window.draw_box(ACS_VLINE(), ACS_HLINE());
+        self.draw_coordinates();
+        for screen_y in 0..Board::SIZE {
+            for screen_x in 0..Board::SIZE {
+                let square = self.screen_square(Square{ x: screen_x, y: screen_y });
+                let (x, y) = (square.x, square.y);
+                let (left, right) = match self.controller.selected_piece() {
+                    Some(piece_pos) if piece_pos == square => ('(', ')'),
+                    _ if self.cursor == square             => ('[', ']'),
+                    _                                      => (' ', ' '),
                 };
-                let center = Self::get_piece_glyph(pieces.get(&Square{x, y}), self.preferences.ascii);
-                let ch = format!("{left}{center}{right}", left=left, center=center, right=right);
-                let colors = match self.preferences.color_scheme {
-                    ColorScheme::WhiteRed   => [Color::WhiteOnRed as i16,   Color::RedOnWhite as i16],
-                    ColorScheme::RedBlack   => [Color::RedOnBlack as i16,   Color::BlackOnRed as i16],
-                    ColorScheme::WhiteBlack => [Color::WhiteOnBlack as i16, Color::BlackOnWhite as i16],
+                let piece = pieces.get(&square);
+                let (center, ink) = match piece {
+                    None if destinations.contains(&square) => (self.glyphs.destination, Ink::Plain),
+                    None => (' ', Ink::Plain),
+                    Some(piece) if piece.team == Team::Light => (self.glyphs.glyph(Some(piece)), Ink::LightPiece),
+                    Some(piece) => (self.glyphs.glyph(Some(piece)), Ink::DarkPiece),
                 };
-                let real_x = (x * SQUARE_WIDTH as i8 + 1) as i32;
-                let real_y = (y + 1) as i32;
-                let color_pair = colors[((x + y + 1) % 2) as usize];
-                // let attrs = if self.cursor == (Square{x, y}) { A_BLINK } else { A_NORMAL };
-                self.window.color_set(color_pair);
-                self.window.mvaddstr(real_y, real_x, format!("{char:^width$}", char=ch, width=SQUARE_WIDTH));
+                let number = match notation::square_number(&square) {
+                    Some(number) if self.labels == Labels::Numbers && left == ' ' && center == ' ' => Some(number),
+                    _ => None,
+                };
+
+                let square_paper = if (x + y) % 2 == 1 { Paper::DarkSquare } else { Paper::LightSquare };
+                let highlighted = [destinations.contains(&square), captures.contains(&square), last_move.contains(&square)];
+                let (paper, attrs) = square_style(&self.theme, square_paper, highlighted);
+                let real_x = screen_x as i32 * self.square_width + 1;
+                let real_y = (screen_y + 1) as i32;
+                match number {
+                    Some(number) => {
+                        self.window.attrset(attrs | A_DIM);
+                        self.window.color_set(theme::pair(Ink::Plain, paper));
+                        self.window.mvaddstr(real_y, real_x, format!("{:^width$}", number, width=self.square_width as usize));
+                    },
+                    None => draw_square(&self.window, (real_y, real_x), self.square_width, [left, center, right], ink, paper, attrs),
+                }
             }
         }
+        self.window.attrset(A_NORMAL);
         self.window.refresh();
     }
+
+    /// Writes files and ranks over the bottom and left edges of the border
+    fn draw_coordinates(&self) {
+        if self.labels != Labels::Coordinates {
+            return;
+        }
+        let bottom = Board::SIZE as i32 + 1;
+        for screen in 0..Board::SIZE {
+            let square = self.screen_square(Square{ x: screen, y: screen });
+            let file = (b'a' + square.x as u8) as char;
+            let rank = Board::SIZE - square.y;
+            self.window.mvaddstr(bottom, screen as i32 * self.square_width + 1 + self.square_width / 2, file.to_string());
+            self.window.mvaddstr(screen as i32 + 1, 0, rank.to_string());
+        }
+    }
+}
+
+/// Background and attributes for a square, given whether it is a destination, has a capture and was part of the
+/// last move.  Highlights are colours if the theme has them, or text attributes if not.
+pub fn square_style(theme: &Theme, square_paper: Paper, [destination, capture, last_move]: [bool; 3]) -> (Paper, chtype) {
+    let mut paper = square_paper;
+    let mut attrs = A_NORMAL;
+    for (highlighted, highlight_paper, color, attr) in [
+        (destination, Paper::Destination, theme.destination, A_BOLD),
+        (capture,     Paper::Capture,     theme.capture,     A_UNDERLINE),
+        (last_move,   Paper::LastMove,    theme.last_move,   A_REVERSE),
+    ] {
+        match color {
+            _ if !highlighted => (),
+            Some(_) => paper = highlight_paper,
+            None => attrs |= attr,
+        }
+    }
+    (paper, attrs)
+}
+
+/// Draws one square at `(y, x)`: the cursor brackets either side of the glyph, which is in `ink`.
+pub fn draw_square(window: &pancurses::Window, (y, x): (i32, i32), width: i32, [left, center, right]: [char; 3], ink: Ink,
+                   paper: Paper, attrs: chtype) {
+    let padding = " ".repeat((width as usize).saturating_sub(3) / 2);
+    window.attrset(attrs);
+    window.color_set(theme::pair(Ink::Cursor, paper));
+    window.mvaddstr(y, x, format!("{}{}", padding, left));
+    window.color_set(theme::pair(ink, paper));
+    window.addstr(center.to_string());
+    window.color_set(theme::pair(Ink::Cursor, paper));
+    window.addstr(format!("{}{}", right, padding));
+}
+
+/// The closest of `candidates` that lies in direction `(dx, dy)` from `from`, preferring squares nearest the line
+/// straight out from it.
+fn nearest_in_direction(from: Square, (dx, dy): (i8, i8), candidates: &[Square]) -> Option<Square> {
+    candidates.iter()
+        .filter(|square| {
+            let (along, _) = offset(from, **square, (dx, dy));
+            along > 0
+        })
+        .min_by_key(|square| {
+            let (along, across) = offset(from, **square, (dx, dy));
+            (across.abs(), along)
+        })
+        .copied()
+}
+
+/// How far `to` is from `from` along direction `(dx, dy)`, and how far off to the side
+fn offset(from: Square, to: Square, (dx, dy): (i8, i8)) -> (i8, i8) {
+    let (x, y) = (to.x - from.x, to.y - from.y);
+    (x * dx + y * dy, x * dy - y * dx)
 }
 impl CursorInput for BoardView {
-    type Action = ();
+    type Action = Selection;
 
-    // TODO if selecting move, limit to valid moves?
     fn move_cursor(&mut self, dir: Input) {
-        match dir {
-            Input::KeyLeft => self.cursor.x -= 1,
-            Input::KeyRight => self.cursor.x += 1,
-            Input::KeyUp => self.cursor.y -= 1,
-            Input::KeyDown => self.cursor.y += 1,
+        let (dx, dy) = match dir {
+            Input::KeyLeft => (-1, 0),
+            Input::KeyRight => (1, 0),
+            Input::KeyUp => (0, -1),
+            Input::KeyDown => (0, 1),
             _ => panic!("Bad dir passed to move_cursor: {:?}", dir),
+        };
+        let delta = if self.flipped { (-dx, -dy) } else { (dx, dy) };
+
+        let piece_pos = self.controller.selected_piece().filter(|_| self.highlights.restrict_cursor);
+        if let Some(piece_pos) = piece_pos {
+            // The piece itself stays reachable so the move can be cancelled
+            let mut candidates = self.destinations();
+            candidates.push(piece_pos);
+            if let Some(square) = nearest_in_direction(self.cursor, delta, &candidates) {
+                self.cursor = square;
+            }
+            return;
         }
-        self.cursor.x = (self.cursor.x + Board::SIZE) % Board::SIZE;
-        self.cursor.y = (self.cursor.y + Board::SIZE) % Board::SIZE;
+
+        self.cursor.x = (self.cursor.x + delta.0 + Board::SIZE) % Board::SIZE;
+        self.cursor.y = (self.cursor.y + delta.1 + Board::SIZE) % Board::SIZE;
     }
 
     fn do_action(&mut self) -> Option<Self::Action> {
-        match self.process_state() {
-            Some(new_state) => self.state = new_state,
-            None => (),
-        };
+        self.controller.select(&self.board, self.cursor)
+    }
+
+    fn click(&mut self, y: i32, x: i32) -> Option<Self::Action> {
+        self.cursor = self.square_at(y, x)?;
+        self.do_action()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        None
+    #[test]
+    fn test_nearest_in_direction() {
+        let from = Square{ x: 2, y: 5 };
+        let candidates = [Square{ x: 1, y: 4 }, Square{ x: 3, y: 4 }, Square{ x: 6, y: 1 }, from];
+        assert_eq!(nearest_in_direction(from, (1, 0), &candidates), Some(Square{ x: 3, y: 4 }));
+        assert_eq!(nearest_in_direction(from, (-1, 0), &candidates), Some(Square{ x: 1, y: 4 }));
+        assert_eq!(nearest_in_direction(from, (0, 1), &candidates), None);
+        assert_eq!(nearest_in_direction(Square{ x: 3, y: 4 }, (1, 0), &candidates), Some(Square{ x: 6, y: 1 }));
+        assert_eq!(nearest_in_direction(Square{ x: 3, y: 4 }, (0, 1), &candidates), Some(from));
     }
 }
@@ -0,0 +1,32 @@
+use crate::backend::{clock, Clock, Team};
+
+use pancurses::A_REVERSE;
+
+pub const CLOCK_WIDTH: i32 = 16;
+
+/// Both players' clocks beside the board, Dark's at the top to match the board.
+pub struct ClockView {
+    pub window: pancurses::Window,
+    pub clock: Option<Clock>,
+}
+impl ClockView {
+    pub fn draw(&self) {
+        let clock = match &self.clock {
+            Some(clock) => clock,
+            None => return,
+        };
+        let bottom = self.window.get_max_y() - 1;
+        for (team, y) in [(Team::Dark, 1), (Team::Light, bottom - 1)].iter() {
+            let running = clock.running() == Some(*team);
+            if running {
+                self.window.attron(A_REVERSE);
+            }
+            let face = format!("{:<6}{:>8}", format!("{:?}", team), clock::format_time(clock.remaining(*team)));
+            self.window.mvaddstr(*y, 1, format!("{:width$}", face, width=(CLOCK_WIDTH - 2) as usize));
+            if running {
+                self.window.attroff(A_REVERSE);
+            }
+        }
+        self.window.refresh();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::backend::{Board, Move, Square, Team};
+use crate::channel::FrontToBackMessage;
+
+enum State {
+    Waiting,
+    ChoosingPiece(Team),
+    ChoosingMove(Team, Square, Vec<Move>, bool),
+}
+
+/// Result of selecting a square while the controller is active.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    PieceSelected(Square),
+    MoveCanceled,
+    JumpCanceled,
+    MoveChosen(Move),
+    IllegalMove(Move),
+}
+impl Selection {
+    /// The message (if any) that should be forwarded to the backend for this selection.
+    pub fn message(&self) -> Option<FrontToBackMessage> {
+        match self {
+            Selection::JumpCanceled    => Some(FrontToBackMessage::CancelMove),
+            Selection::MoveChosen(mv)  => Some(FrontToBackMessage::Move(*mv)),
+            _                          => None,
+        }
+    }
+}
+
+/// UI-agnostic state machine for picking a piece and then a destination.
+///
+/// Frontends translate their own input (cursor, mouse, typed notation) into squares and feed them to `select`.
+pub struct MoveController {
+    state: State,
+}
+impl MoveController {
+    pub fn new() -> MoveController {
+        MoveController {
+            state: State::Waiting,
+        }
+    }
+
+    pub fn start_selecting_piece(&mut self, team: Team) {
+        self.state = State::ChoosingPiece(team);
+    }
+
+    pub fn continue_jumping(&mut self, team: Team, square: Square, valid_moves: Vec<Move>) {
+        self.state = State::ChoosingMove(team, square, valid_moves, true);
+    }
+
+    pub fn stop(&mut self) {
+        self.state = State::Waiting;
+    }
+
+    /// Team currently being asked for input, if any.
+    pub fn active_team(&self) -> Option<Team> {
+        match self.state {
+            State::Waiting                      => None,
+            State::ChoosingPiece(team)          => Some(team),
+            State::ChoosingMove(team, _, _, _)  => Some(team),
+        }
+    }
+
+    pub fn valid_moves(&self) -> &[Move] {
+        match &self.state {
+            State::ChoosingMove(_, _, valid_moves, _) => valid_moves,
+            _ => &[],
+        }
+    }
+
+    pub fn is_jumping(&self) -> bool {
+        matches!(self.state, State::ChoosingMove(_, _, _, true))
+    }
+
+    pub fn selected_piece(&self) -> Option<Square> {
+        match self.state {
+            State::ChoosingMove(_, square, _, _) => Some(square),
+            _ => None,
+        }
+    }
+
+    pub fn select(&mut self, board: &Board, square: Square) -> Option<Selection> {
+        let (new_state, selection) = match &self.state {
+            State::Waiting => return None,
+            State::ChoosingPiece(team) => {
+                match board.get_piece_at(&square) {
+                    Some(piece) if piece.team == *team => {
+                        let valid_moves = board.get_valid_moves_for_piece_at(&square);
+                        if valid_moves.is_empty() {
+                            return None;
+                        }
+                        (State::ChoosingMove(*team, square, valid_moves, false), Selection::PieceSelected(square))
+                    },
+                    _ => return None,
+                }
+            },
+            State::ChoosingMove(team, piece_pos, valid_moves, only_jumps) => {
+                if square == *piece_pos {
+                    // Cancel move
+                    if *only_jumps {
+                        (State::Waiting, Selection::JumpCanceled)
+                    } else {
+                        (State::ChoosingPiece(*team), Selection::MoveCanceled)
+                    }
+                } else {
+                    let mv = Move{ from: *piece_pos, to: square };
+                    if valid_moves.contains(&mv) {
+                        (State::Waiting, Selection::MoveChosen(mv))
+                    } else {
+                        return Some(Selection::IllegalMove(mv));
+                    }
+                }
+            },
+        };
+        self.state = new_state;
+
+        Some(selection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_piece_then_move() {
+        let board = Board::new();
+        let mut controller = MoveController::new();
+        assert_eq!(controller.select(&board, Square{ x: 0, y: 5 }), None); // Not our turn yet
+
+        controller.start_selecting_piece(Team::Light);
+        assert_eq!(controller.select(&board, Square{ x: 1, y: 2 }), None); // Enemy piece
+        assert_eq!(controller.select(&board, Square{ x: 0, y: 7 }), None); // No valid moves
+        assert_eq!(controller.select(&board, Square{ x: 0, y: 5 }), Some(Selection::PieceSelected(Square{ x: 0, y: 5 })));
+        assert_eq!(controller.valid_moves().len(), 1);
+
+        let illegal = Move{ from: Square{ x: 0, y: 5 }, to: Square{ x: 0, y: 4 } };
+        assert_eq!(controller.select(&board, illegal.to), Some(Selection::IllegalMove(illegal)));
+        assert_eq!(controller.selected_piece(), Some(Square{ x: 0, y: 5 }));
+
+        let mv = Move{ from: Square{ x: 0, y: 5 }, to: Square{ x: 1, y: 4 } };
+        let selection = controller.select(&board, mv.to).unwrap();
+        assert_eq!(selection, Selection::MoveChosen(mv));
+        assert!(selection.message().is_some());
+        assert_eq!(controller.active_team(), None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let board = Board::new();
+        let mut controller = MoveController::new();
+
+        controller.start_selecting_piece(Team::Light);
+        controller.select(&board, Square{ x: 0, y: 5 });
+        assert_eq!(controller.select(&board, Square{ x: 0, y: 5 }), Some(Selection::MoveCanceled));
+        assert!(controller.select(&board, Square{ x: 0, y: 5 }).is_some()); // Still choosing
+
+        let jump = Move{ from: Square{ x: 0, y: 5 }, to: Square{ x: 2, y: 3 } };
+        controller.continue_jumping(Team::Light, jump.from, vec![jump]);
+        assert!(controller.is_jumping());
+        let selection = controller.select(&board, jump.from).unwrap();
+        assert_eq!(selection, Selection::JumpCanceled);
+        assert!(selection.message().is_some());
+        assert_eq!(controller.select(&board, jump.from), None);
+    }
+}
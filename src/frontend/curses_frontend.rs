@@ -1,167 +1,920 @@
 extern crate pancurses;
 
-use super::board::{BoardView, SQUARE_WIDTH};
+use super::{Frontend, FrontendError, UserAction};
+use super::board::{BoardView, SQUARE_WIDTHS};
+use super::clock::{ClockView, CLOCK_WIDTH};
+use super::controller::Selection;
 use super::cursor_input::CursorInput;
+use super::editor::{self, Editor, EditorInput};
+use super::help::HelpView;
+use super::keys::{Command, Key, KeyBindings};
 use super::log::LogView;
-use super::menu::{Color, Menu, Preferences};
+use super::log_file::LogFile;
+use super::menu::Menu;
+use super::moves::{MovesView, MOVES_WIDTH};
+use super::preferences::Preferences;
+use super::puzzle::{Attempt, Progress, Puzzle, Step};
+use super::replay::Replay;
+use super::theme::{self, Themes};
 
-use crate::args::Args;
-use crate::backend;
-use backend::{Board, Player};
-use crate::channel::FrontendEndpoint;
+use crate::args::{Args, Mode};
+use crate::backend::notation::{self, GameRecord};
+use crate::backend::{Board, Clock, Team};
+use crate::channel::{BackToFrontMessage, FrontToBackMessage, LogLevel};
+use crate::config;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::path::Path;
+use std::time::Instant;
 
 use pancurses::{
-    ACS_HLINE, ACS_VLINE,
-    COLOR_BLACK, COLOR_RED, COLOR_WHITE,
-    curs_set, endwin, initscr, init_pair, Input, noecho, start_color,
+    BUTTON1_PRESSED,
+    curs_set, endwin, getmouse, initscr, Input, mouseinterval, mousemask, noecho, start_color, use_default_colors,
 };
 
-#[derive(Debug)]
-pub struct WindowError {
-    code: i32,
-    message: String,
+/// Lines needed for the log under the board, border included
+const MIN_LOG_HEIGHT: i32 = 5;
+
+enum InputEvent<Action> {
+    Action(Action),
+    /// A command the cursor doesn't use
+    Command(Command),
+    /// Scroll the log back (positive) or forward by pages
+    ScrollLog(i32),
+    Resize,
+    Quit,
 }
-impl From<i32> for WindowError {
-    fn from(code: i32) -> Self {
-        WindowError {
-            code: code,
-            message: format!("Error code {}", code), // TODO prettify common error codes... actually I think pancurses may only have a single -1 code for everything
-        }
-    }
+
+/// The game screen's windows, laid out for the current terminal size
+struct GameWindows {
+    board: pancurses::Window,
+    square_width: i32,
+    clock: pancurses::Window,
+    moves: pancurses::Window,
+    log: pancurses::Window,
 }
 
 pub struct CursesFrontend {
-    // args: Args,
-    backend_channel: Rc<RefCell<FrontendEndpoint>>,
+    args: Args,
     window: pancurses::Window,
+    menu: Menu,
+    board: Option<BoardView>,
+    clock: Option<ClockView>,
+    moves: Option<MovesView>,
+    log: Option<LogView>,
+    /// Team whose draw offer we owe an answer to
+    draw_offer: Option<Team>,
+    /// Lines and columns needed, if the terminal is too small for the game
+    too_small: Option<(i32, i32)>,
+    /// Why the menu's options couldn't be saved, to report once there is a log
+    save_error: Option<String>,
+    /// Key list shown over the game
+    help: Option<HelpView>,
+    /// Only watching someone else's game, so there is nothing to ask the user
+    watching: bool,
 }
 impl CursesFrontend {
-    pub fn new(args: Args, backend_channel: FrontendEndpoint) -> CursesFrontend {
-        let mut window = initscr();
+    pub fn new(args: &Args) -> CursesFrontend {
+        let window = initscr();
         window.keypad(true); // Allow control characters
         window.nodelay(true); // Input is non-blocking
         curs_set(0); // Hide cursor
         start_color(); // Enable colors
+        use_default_colors(); // Let themes use the terminal's own colours
         noecho(); // Don't echo typed characters
+        mousemask(BUTTON1_PRESSED, std::ptr::null_mut()); // Only left clicks
+        mouseinterval(0); // Report presses straight away instead of waiting to see if they're clicks
 
-        init_pair(Color::RedOnWhite as i16,   COLOR_RED,   COLOR_WHITE);
-        init_pair(Color::WhiteOnRed as i16,   COLOR_WHITE, COLOR_RED);
-        init_pair(Color::RedOnBlack as i16,   COLOR_RED,   COLOR_BLACK);
-        init_pair(Color::BlackOnRed as i16,   COLOR_BLACK, COLOR_RED);
-        init_pair(Color::WhiteOnBlack as i16, COLOR_WHITE, COLOR_BLACK);
-        init_pair(Color::BlackOnWhite as i16, COLOR_BLACK, COLOR_WHITE);
+        let themes = Themes::load(args.config_path.as_deref().map(config::themes_dir).as_deref());
 
         CursesFrontend {
-            // args: args.clone(),
-            window: window,
-            backend_channel: Rc::new(RefCell::new(backend_channel)),
+            args: args.clone(),
+            window,
+            menu: Menu::new(&args.preferences, &args.appearance, themes),
+            board: None,
+            clock: None,
+            moves: None,
+            log: None,
+            draw_offer: None,
+            too_small: None,
+            save_error: None,
+            help: None,
+            watching: matches!(args.mode, Mode::Watch(_)),
         }
     }
 
-    fn process_input<Actor: CursorInput>(&self, actor: &mut Actor) -> Option<Actor::Action> {
-        let key = self.window.getch();
-        const ESC: char = 27 as char;
-        match key {
-            None => (),
-            Some(key) => match key {
-                Input::KeyLeft | Input::KeyRight | Input::KeyUp | Input::KeyDown => actor.move_cursor(key),
-                Input::KeyEnter | Input::Character('\n') | Input::Character(' ') => return actor.do_action(),
-                Input::Character('q') | Input::KeyDC | Input::Character(ESC) => {
-                    endwin();
-                    std::process::exit(0);
-                }
-                // i => log!(self.window, "unknown... {:?}", i),
-                _ => (),
+    /// Widest squares the game fits with, if it fits at all
+    fn fitting_square_width(&self) -> Option<i32> {
+        let (lines, cols) = self.window.get_max_yx();
+        SQUARE_WIDTHS.iter().rev().copied().find(|&square_width| {
+            let (needed_lines, needed_cols) = Self::game_size(square_width);
+            lines >= needed_lines && cols >= needed_cols
+        })
+    }
+
+    fn game_size(square_width: i32) -> (i32, i32) {
+        let (board_lines, board_cols) = BoardView::size(square_width);
+        (board_lines + MIN_LOG_HEIGHT, board_cols + CLOCK_WIDTH + MOVES_WIDTH)
+    }
+
+    /// Makes windows for the game to fit the terminal: the board at the top left, the clocks and move list beside it
+    /// and the log underneath.  If the terminal is too small the windows are placeholders that are never drawn.
+    fn layout(&mut self) -> GameWindows {
+        self.window.clear();
+        let windows = self.fitting_square_width().and_then(|square_width| {
+            let (board_lines, board_cols) = BoardView::size(square_width);
+            let (lines, cols) = self.window.get_max_yx();
+            Some(GameWindows {
+                board: self.window.subwin(board_lines, board_cols, 0, 0).ok()?,
+                square_width,
+                clock: self.window.subwin(board_lines, CLOCK_WIDTH, 0, board_cols + 1).ok()?,
+                moves: self.window.subwin(board_lines, MOVES_WIDTH, 0, board_cols + 1 + CLOCK_WIDTH).ok()?,
+                log: self.window.subwin(lines - board_lines, cols, board_lines, 0).ok()?,
+            })
+        });
+        self.too_small = match windows {
+            Some(_) => None,
+            None => Some(Self::game_size(SQUARE_WIDTHS[0])),
+        };
+        windows.unwrap_or_else(|| {
+            let placeholder = || self.window.subwin(1, 1, 0, 0).expect("Could not make a placeholder window");
+            GameWindows {
+                board: placeholder(),
+                square_width: SQUARE_WIDTHS[0],
+                clock: placeholder(),
+                moves: placeholder(),
+                log: placeholder(),
+            }
+        })
+    }
+
+    /// Lays the game out again after the terminal changes size
+    fn resize(&mut self) {
+        self.help = None;
+        let windows = self.layout();
+        if let Some(board) = self.board.as_mut() {
+            board.set_window(windows.board, windows.square_width);
+        }
+        if let Some(clock) = self.clock.as_mut() {
+            clock.window = windows.clock;
+        }
+        if let Some(moves) = self.moves.as_mut() {
+            moves.window = windows.moves;
+        }
+        if let Some(log) = self.log.as_mut() {
+            log.window = windows.log;
+        }
+    }
+
+    /// Says how big the terminal needs to be instead of drawing something that doesn't fit
+    fn draw_too_small(&self, (lines, cols): (i32, i32)) {
+        self.window.erase();
+        self.window.mvaddstr(0, 0, format!("Terminal too small, make it at least {}x{}", cols, lines));
+        self.window.refresh();
+    }
+
+    fn process_input<Actor: CursorInput>(window: &pancurses::Window, bindings: &KeyBindings, actor: &mut Actor)
+                                         -> Option<InputEvent<Actor::Action>> {
+        let key = match window.getch()? {
+            Input::KeyResize => return Some(InputEvent::Resize),
+            Input::KeyMouse => return match getmouse() {
+                Ok(event) if event.bstate & BUTTON1_PRESSED != 0 => actor.click(event.y, event.x).map(InputEvent::Action),
+                _ => None,
             },
+            input => key_of(input)?,
+        };
+        match bindings.command(key)? {
+            Command::Up    => actor.move_cursor(Input::KeyUp),
+            Command::Down  => actor.move_cursor(Input::KeyDown),
+            Command::Left  => actor.move_cursor(Input::KeyLeft),
+            Command::Right => actor.move_cursor(Input::KeyRight),
+            Command::Select => return actor.do_action().map(InputEvent::Action),
+            Command::Quit => return Some(InputEvent::Quit),
+            Command::LogUp => return Some(InputEvent::ScrollLog(1)),
+            Command::LogDown => return Some(InputEvent::ScrollLog(-1)),
+            command => return Some(InputEvent::Command(command)),
         };
 
         None
     }
 
-    fn send_msg(&self, msg: crate::channel::FrontToBackMessage) {
-        self.backend_channel.borrow_mut().tx.send(msg).expect("Could not send message"); // TODO better error handling
+    /// Lays out the game screen and makes its views, starting the log with `intro` and anything that went wrong
+    /// setting up.
+    fn open_views(&mut self, intro: &[String]) {
+        let windows = self.layout();
+
+        let filter = if self.args.debug { LogLevel::Debug } else { LogLevel::Info };
+        let (file, file_error) = match self.args.log_file.as_deref().map(LogFile::create) {
+            Some(Ok(file)) => (Some(file), None),
+            Some(Err(err)) => (None, Some(err)),
+            None => (None, None),
+        };
+        let mut log = LogView::new(windows.log, filter, file);
+        for line in intro {
+            log!(log, "{}", line);
+        }
+        if let Some(err) = file_error {
+            log!(log, "Could not open the log file: {}", err);
+        }
+        for err in self.menu.theme_errors().iter().chain(self.save_error.take().as_ref()) {
+            log!(log, "{}", err);
+        }
+        self.log = Some(log);
+        theme::apply(self.menu.theme());
+        self.board = Some(BoardView::new(self.menu.theme().clone(), self.menu.glyphs().clone(), windows.board,
+                                         windows.square_width));
+        self.clock = Some(ClockView{ window: windows.clock, clock: None });
+        self.moves = Some(MovesView::new(windows.moves));
     }
 
-    pub fn run(&mut self) -> Result<(), WindowError> {
-        let preferences = self.handle_menu();
+    /// Drops the game screen's views, so the menu has the screen to itself again
+    fn close_views(&mut self) {
+        self.board = None;
+        self.clock = None;
+        self.moves = None;
+        self.log = None;
+        self.help = None;
         self.window.clear();
-        self.send_msg(crate::channel::FrontToBackMessage::StartGame(preferences.clone()));
-
-        self.main_loop(preferences)
     }
 
-    fn handle_menu(&mut self) -> Preferences {
-        let mut menu = Menu::new();
-        return loop {
-            match self.process_input(&mut menu) {
-                Some(prefs) => break prefs,
-                None => (),
+    /// Lets the user set up a position, starting from `--position` or the opening.  Returns the position and side to
+    /// move once they ask to play from it, or `None` if they quit instead.
+    pub fn edit_position(&mut self) -> Result<Option<(Board, Team)>, FrontendError> {
+        let bindings = &self.args.bindings;
+        let intro = [
+            format!("{} or click cycles a square through light and dark men and kings, {} empties it",
+                    bindings.primary(Command::Select), bindings.primary(Command::Cancel)),
+            format!("{} changes the side to move, {} empties the board and {} takes back a change",
+                    bindings.primary(Command::SideToMove), bindings.primary(Command::ClearBoard),
+                    bindings.primary(Command::Undo)),
+            format!("{} analyses, {} writes out the position and {} plays from it",
+                    bindings.primary(Command::Hint), bindings.primary(Command::Export), bindings.primary(Command::Play)),
+        ];
+        self.open_views(&intro);
+
+        let variant = self.args.game.variant;
+        let (board, to_move) = self.args.game.position.clone().unwrap_or_else(|| (Board::new(), variant.first_team()));
+        let mut editor = Editor::new(board.with_variant(self.args.game.variant), to_move);
+        let mut messages = vec![format!("{:?} to move", editor.to_move)];
+        let mut changed = true;
+        let position = loop {
+            if let Some(log) = self.log.as_mut() {
+                for msg in messages.drain(..) {
+                    log!(log, "{}", msg);
+                }
             }
+            if changed {
+                changed = false;
+                if let Some(board) = self.board.as_mut() {
+                    board.set_board_state(editor.board.clone());
+                }
+            }
+            self.draw();
+
+            if self.help.is_some() {
+                self.close_help_on_key();
+            } else {
+                let view = self.board.as_mut().expect("Editor not started");
+                let cursor = view.cursor();
+                let mut input = EditorInput{ editor: &mut editor, view };
+                match Self::process_input(&self.window, &self.args.bindings, &mut input) {
+                    Some(InputEvent::Quit) => break None,
+                    Some(InputEvent::Action(())) => changed = true,
+                    Some(InputEvent::Command(Command::Cancel)) => changed = editor.remove(cursor),
+                    Some(InputEvent::Command(Command::Undo)) => match editor.undo() {
+                        true => {
+                            changed = true;
+                            messages.push(format!("Change taken back, {:?} to move", editor.to_move));
+                        },
+                        false => messages.push("Nothing to take back".to_string()),
+                    },
+                    Some(InputEvent::Command(Command::SideToMove)) => {
+                        editor.switch_side();
+                        messages.push(format!("{:?} to move", editor.to_move));
+                    },
+                    Some(InputEvent::Command(Command::ClearBoard)) => {
+                        editor.clear();
+                        changed = true;
+                        messages.push("Board emptied".to_string());
+                    },
+                    Some(InputEvent::Command(Command::Export)) => {
+                        messages.push(format!("Position: {}", editor.fen()));
+                        messages.extend(editor.problems().into_iter().map(|problem| format!("Can't play this yet: {}", problem)));
+                    },
+                    Some(InputEvent::Command(Command::Hint)) => match editor.problems() {
+                        problems if !problems.is_empty() => messages.extend(problems),
+                        _ => match editor.analyse(self.args.game.ai_limits) {
+                            Some(info) => messages.push(format!("{:?} to move: {} looks best, {} at depth {}", editor.to_move,
+                                                                notation::format_path(&info.moves),
+                                                                editor::describe_score(info.score), info.depth)),
+                            None => messages.push("No moves to analyse".to_string()),
+                        },
+                    },
+                    Some(InputEvent::Command(Command::Play)) => match editor.problems() {
+                        problems if problems.is_empty() => break Some((editor.board, editor.to_move)),
+                        problems => messages.extend(problems),
+                    },
+                    Some(InputEvent::Command(command @ (Command::Help | Command::Flip | Command::Labels
+                        | Command::LogFilter))) => {
+                        self.handle_command(command);
+                    },
+                    Some(InputEvent::ScrollLog(pages)) => {
+                        if let Some(log) = self.log.as_mut() {
+                            log.scroll_pages(pages);
+                        }
+                    },
+                    Some(InputEvent::Resize) => self.resize(),
+                    // Game commands have nothing to act on yet
+                    Some(InputEvent::Command(_)) | None => (),
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10)); // Throttle to keep my laptop from melting
+        };
+        self.close_views();
+        Ok(position)
+    }
+
+    /// Sets the puzzles one after another, starting with the first not yet solved, until the user quits.
+    pub fn puzzles(&mut self, puzzles: Vec<Puzzle>) -> Result<(), FrontendError> {
+        let bindings = &self.args.bindings;
+        let intro = [
+            format!("Find the winning line, moving as in a game: {} shows the next move and {} starts again",
+                    bindings.primary(Command::Hint), bindings.primary(Command::Undo)),
+            format!("{} and {} go to the next and previous puzzles, {} quits",
+                    bindings.primary(Command::NextPuzzle), bindings.primary(Command::PreviousPuzzle),
+                    bindings.primary(Command::Quit)),
+        ];
+        self.open_views(&intro);
 
-            menu.draw(&mut self.window); // TODO window doesn't need to be mut everywhere
+        let progress_path = self.args.config_path.as_deref().map(config::puzzle_progress_path);
+        let mut progress = match progress_path.as_deref().map(Progress::load) {
+            Some(Ok(progress)) => progress,
+            Some(Err(err)) => {
+                if let Some(log) = self.log.as_mut() {
+                    log!(log, "Could not read the puzzles solved so far: {}", err);
+                }
+                Progress::default()
+            },
+            None => Progress::default(),
+        };
+        let mut current = puzzles.iter().position(|puzzle| !progress.is_solved(puzzle)).unwrap_or(0);
+        let mut attempt = self.start_puzzle(&puzzles, current, &progress);
+        loop {
+            self.draw();
+
+            if self.help.is_some() {
+                self.close_help_on_key();
+            } else {
+                let board = self.board.as_mut().expect("Puzzles not started");
+                let selection = match Self::process_input(&self.window, &self.args.bindings, board) {
+                    Some(InputEvent::Quit) => break,
+                    Some(InputEvent::Action(selection)) => Some(selection),
+                    Some(InputEvent::Command(Command::Cancel)) => board.cancel(),
+                    // Starting again doesn't forget a hint
+                    Some(InputEvent::Command(Command::Undo)) => {
+                        let hinted = attempt.hinted;
+                        attempt = self.start_puzzle(&puzzles, current, &progress);
+                        attempt.hinted = hinted;
+                        None
+                    },
+                    Some(InputEvent::Command(command @ (Command::NextPuzzle | Command::PreviousPuzzle))) => {
+                        let step = if command == Command::NextPuzzle { 1 } else { puzzles.len() - 1 };
+                        current = (current + step) % puzzles.len();
+                        attempt = self.start_puzzle(&puzzles, current, &progress);
+                        None
+                    },
+                    Some(InputEvent::Command(Command::Hint)) => {
+                        let log = self.log.as_mut().expect("Puzzles not started");
+                        match attempt.hint() {
+                            Some(turn) => {
+                                board.set_cursor(turn[0].from);
+                                log!(log, "Hint: {}, so this one won't count", notation::format_path(&turn));
+                            },
+                            None => log!(log, "Press {} to try again", self.args.bindings.primary(Command::Undo)),
+                        }
+                        None
+                    },
+                    Some(InputEvent::Command(command @ (Command::Help | Command::Flip | Command::Labels
+                        | Command::LogFilter | Command::ToggleMoves | Command::ToggleCaptures
+                        | Command::ToggleLastMove | Command::ToggleRestrict))) => {
+                        self.handle_command(command);
+                        None
+                    },
+                    Some(InputEvent::ScrollLog(pages)) => {
+                        if let Some(log) = self.log.as_mut() {
+                            log.scroll_pages(pages);
+                        }
+                        None
+                    },
+                    Some(InputEvent::Resize) => {
+                        self.resize();
+                        None
+                    },
+                    // Nothing to offer or resign to in a puzzle
+                    Some(InputEvent::Command(_)) | None => None,
+                };
+                match selection {
+                    Some(Selection::MoveChosen(mv)) => {
+                        let step = attempt.play(mv);
+                        self.puzzle_step(&attempt, step, &mut progress, progress_path.as_deref());
+                    },
+                    // Captures are compulsory, so a jump can't stop partway
+                    Some(Selection::JumpCanceled) => {
+                        let board = self.board.as_mut().expect("Puzzles not started");
+                        if let Some(hop) = attempt.jumping_from() {
+                            board.controller.continue_jumping(attempt.puzzle.solver, hop.to,
+                                                              attempt.board.get_valid_jumps_for_piece_at(&hop.to));
+                        }
+                    },
+                    Some(selection) => {
+                        self.handle_selection(selection);
+                    },
+                    None => (),
+                }
+            }
 
             std::thread::sleep(std::time::Duration::from_millis(10)); // Throttle to keep my laptop from melting
         }
+        Ok(())
+    }
+
+    /// Sets up puzzle `idx` from the start, for the user to solve
+    fn start_puzzle(&mut self, puzzles: &[Puzzle], idx: usize, progress: &Progress) -> Attempt {
+        let puzzle = &puzzles[idx];
+        let attempt = Attempt::new(puzzle.clone());
+        self.show_attempt(&attempt);
+        if let Some(board) = self.board.as_mut() {
+            board.flipped = puzzle.solver == Team::Dark;
+            board.controller.start_selecting_piece(puzzle.solver);
+        }
+        if let Some(log) = self.log.as_mut() {
+            let solved = if progress.is_solved(puzzle) { " (solved before)" } else { "" };
+            log!(log, "Puzzle {} of {}: {}, {:?} to play and win{}", idx + 1, puzzles.len(), puzzle.title,
+                 puzzle.solver, solved);
+        }
+        attempt
+    }
+
+    /// Shows the position an attempt has reached, and the solution played so far
+    fn show_attempt(&mut self, attempt: &Attempt) {
+        let played = attempt.played();
+        if let Some(board) = self.board.as_mut() {
+            board.set_board_state(attempt.board.clone());
+            board.set_last_move(played.last().cloned().unwrap_or_default());
+        }
+        if let Some(moves) = self.moves.as_mut() {
+            moves.clear();
+            let mut team = attempt.puzzle.solver;
+            for turn in played {
+                moves.add_turn(team, turn.clone());
+                team = team.other();
+            }
+        }
+    }
+
+    /// Shows what came of the user's move, keeping score once the attempt is over
+    fn puzzle_step(&mut self, attempt: &Attempt, step: Step, progress: &mut Progress, progress_path: Option<&Path>) {
+        self.show_attempt(attempt);
+        let solver = attempt.puzzle.solver;
+        let bindings = &self.args.bindings;
+        let board = self.board.as_mut().expect("Puzzles not started");
+        let log = self.log.as_mut().expect("Puzzles not started");
+        let solved = match step {
+            Step::Jumping(jumps) => {
+                let hop = attempt.jumping_from().expect("Jumping without a jump");
+                board.controller.continue_jumping(solver, hop.to, jumps);
+                return;
+            },
+            Step::Reply(reply) => {
+                log!(log, "{:?} replies {}", solver.other(), notation::format_path(&reply));
+                if !attempt.finished {
+                    board.controller.start_selecting_piece(solver);
+                    return;
+                }
+                true
+            },
+            Step::Solved => true,
+            Step::Wrong(_) => {
+                log!(log, "That's not it: press {} to try again or {} for a hint", bindings.primary(Command::Undo),
+                     bindings.primary(Command::Hint));
+                false
+            },
+            Step::Over => {
+                log!(log, "Press {} to try again or {} for the next puzzle", bindings.primary(Command::Undo),
+                     bindings.primary(Command::NextPuzzle));
+                return;
+            },
+        };
+
+        match (solved, attempt.hinted) {
+            (true, false) => log!(log, "Solved!"),
+            (true, true) => log!(log, "Solved, with a hint"),
+            (false, _) => (),
+        }
+        progress.record(&attempt.puzzle, solved && !attempt.hinted);
+        if solved {
+            log!(log, "{} solved, a streak of {} (best {}); press {} for the next puzzle", progress.solved.len(),
+                 progress.streak, progress.best_streak, bindings.primary(Command::NextPuzzle));
+        }
+        if let Some(path) = progress_path {
+            if let Err(err) = progress.save(path) {
+                log!(log, "Could not save the puzzles solved to {}: {}", path.display(), err);
+            }
+        }
     }
 
-    fn main_loop(&mut self, preferences: Preferences) -> Result<(), WindowError> {
-        let board_window = self.window.subwin(
-            2 + Board::SIZE as i32,
-            2 + Board::SIZE as i32 * SQUARE_WIDTH as i32,
-            0,
-            0,
-        )?;
-        let log_window = self.window.subwin(
-            self.window.get_max_y() - board_window.get_max_y(),
-            self.window.get_max_x(),
-            board_window.get_max_y(),
-            0,
-        )?;
-        let log = Rc::new(RefCell::new(LogView {
-            window: log_window,
-        }));
-        let mut board = BoardView::new(preferences, board_window, log.clone(), self.backend_channel.clone());
+    /// Steps through a recorded game instead of playing one, until the user quits.
+    pub fn replay(&mut self, record: GameRecord) -> Result<(), FrontendError> {
+        let bindings = &self.args.bindings;
+        let mut intro: Vec<String> = ["Event", "Date", "White", "Black", "Result"].iter()
+            .filter_map(|name| Some(format!("{}: {}", name, record.tag(name)?)))
+            .collect();
+        intro.push(format!("Left and right step through the game, {} and {} jump to the start and end",
+                           bindings.primary(Command::First), bindings.primary(Command::Last)));
+        intro.push(format!("{} plays or pauses, up and down change the speed, {} quits",
+                           bindings.primary(Command::Select), bindings.primary(Command::Quit)));
+        self.open_views(&intro);
+        if let Some(moves) = self.moves.as_mut() {
+            for (idx, turn) in record.turns.iter().enumerate() {
+                moves.add_turn(record.team(idx), turn.clone());
+            }
+        }
 
+        let mut replay = Replay::new(record);
+        let mut shown = None;
         loop {
-            let msg = self.backend_channel.borrow_mut().rx.try_recv();
-            match msg {
-                Ok(msg) => {
-                    use crate::channel::BackToFrontMessage as Msg;
-                    match msg {
-                        Msg::Log{ msg: s } => log!(log, "{}", s),
-                        Msg::BoardState(state) => board.set_board_state(state),
-                        Msg::RequestMove(team) => board.start_selecting_piece(team),
-                        Msg::RequestJump(team, square, valid_moves) => board.continue_jumping(team, square, valid_moves),
-                    };
+            if shown != Some(replay.position) {
+                shown = Some(replay.position);
+                self.show_replay_position(&replay);
+            }
+            self.draw();
+
+            if self.help.is_some() {
+                self.close_help_on_key();
+            } else {
+                let before = (replay.is_playing(), replay.move_delay());
+                match Self::process_input(&self.window, &self.args.bindings, &mut replay) {
+                    Some(InputEvent::Quit) => break,
+                    Some(InputEvent::Command(Command::First)) => replay.go_to(0),
+                    Some(InputEvent::Command(Command::Last)) => replay.go_to(usize::MAX),
+                    Some(InputEvent::Command(command @ (Command::Help | Command::Flip | Command::Labels
+                        | Command::LogFilter | Command::ToggleCaptures | Command::ToggleLastMove))) => {
+                        self.handle_command(command);
+                    },
+                    Some(InputEvent::ScrollLog(pages)) => {
+                        if let Some(log) = self.log.as_mut() {
+                            log.scroll_pages(pages);
+                        }
+                    },
+                    Some(InputEvent::Resize) => self.resize(),
+                    // Nothing to move or offer in a replay
+                    Some(InputEvent::Command(_)) | Some(InputEvent::Action(())) | None => (),
+                }
+                if (replay.is_playing(), replay.move_delay()) != before {
+                    if let Some(log) = self.log.as_mut() {
+                        log!(log, "{}", replay.status());
+                    }
+                }
+            }
+
+            let was_playing = replay.is_playing();
+            if replay.tick(Instant::now()) && was_playing && !replay.is_playing() {
+                if let Some(log) = self.log.as_mut() {
+                    log!(log, "End of the game{}", replay.record.result.as_ref().map_or(String::new(), |result| format!(", {}", result)));
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10)); // Throttle to keep my laptop from melting
+        }
+        Ok(())
+    }
+
+    /// Shows the board after the replay's current move, with that move highlighted
+    fn show_replay_position(&mut self, replay: &Replay) {
+        let last_turn = replay.position.checked_sub(1);
+        if let Some(board) = self.board.as_mut() {
+            board.set_board_state(replay.record.position(replay.position));
+            board.set_last_move(last_turn.map_or_else(Vec::new, |idx| replay.record.turns[idx].clone()));
+        }
+        if let Some(moves) = self.moves.as_mut() {
+            moves.current = last_turn;
+        }
+    }
+
+    /// Closes the help on any key
+    fn close_help_on_key(&mut self) {
+        match self.window.getch() {
+            Some(Input::KeyResize) => self.resize(),
+            Some(_) => {
+                self.help = None;
+                self.window.touch();
+            },
+            None => (),
+        }
+    }
+
+    fn handle_selection(&mut self, selection: Selection) -> Option<UserAction> {
+        let log = self.log.as_mut().expect("Game not started");
+        match &selection {
+            Selection::MoveCanceled    => log!(log, "Move canceled"),
+            Selection::JumpCanceled    => log!(log, "Jump canceled"),
+            Selection::IllegalMove(mv) => log!(log, "Illegal move {}", mv),
+            _ => (),
+        };
+
+        selection.message().map(UserAction::Send)
+    }
+
+    fn handle_command(&mut self, command: Command) -> Option<UserAction> {
+        let bindings = &self.args.bindings;
+        let board = self.board.as_mut().expect("Game not started");
+        let log = self.log.as_mut().expect("Game not started");
+        match command {
+            Command::AcceptDraw | Command::DeclineDraw | Command::Undo | Command::Resign | Command::OfferDraw
+                | Command::Hint | Command::Cancel if self.watching => {
+                log!(log, "You are only watching");
+                None
+            },
+            Command::AcceptDraw | Command::DeclineDraw => match self.draw_offer.take() {
+                Some(_) => Some(UserAction::Send(FrontToBackMessage::AnswerDraw(command == Command::AcceptDraw))),
+                None => {
+                    log!(log, "No one has offered a draw");
+                    None
                 },
-                Err(err) => match err {
-                    std::sync::mpsc::TryRecvError::Disconnected => { println!("Disconnected"); break; },
-                    _ => (),
+            },
+            Command::Undo | Command::Resign | Command::OfferDraw | Command::Hint if board.controller.active_team().is_none() => {
+                log!(log, "Wait for your turn");
+                None
+            },
+            Command::Undo => {
+                board.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::Undo))
+            },
+            Command::Resign => {
+                board.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::Resign))
+            },
+            Command::OfferDraw if board.controller.is_jumping() => {
+                log!(log, "Finish your jump first");
+                None
+            },
+            Command::OfferDraw => {
+                board.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::OfferDraw))
+            },
+            Command::Cancel => {
+                let selection = board.cancel()?;
+                self.handle_selection(selection)
+            },
+            Command::Hint => {
+                match board.hint() {
+                    Some(turn) => log!(log, "Hint: {}", notation::format_path(&turn)),
+                    None => log!(log, "No moves to suggest"),
+                }
+                None
+            },
+            Command::Help => {
+                self.help = HelpView::new(&self.window, bindings);
+                if self.help.is_none() {
+                    for (command, _) in bindings.all() {
+                        log!(log, "{}: {}", command.description(), bindings.describe(command));
+                    }
+                }
+                None
+            },
+            Command::Flip => {
+                board.flipped = !board.flipped;
+                None
+            },
+            Command::Labels => {
+                board.labels = board.labels.next();
+                None
+            },
+            Command::LogFilter => {
+                log.cycle_filter();
+                None
+            },
+            Command::ToggleMoves | Command::ToggleCaptures | Command::ToggleLastMove | Command::ToggleRestrict => {
+                let highlights = &mut board.highlights;
+                let (name, setting) = match command {
+                    Command::ToggleMoves    => ("Move highlights", &mut highlights.destinations),
+                    Command::ToggleCaptures => ("Capture highlights", &mut highlights.captures),
+                    Command::ToggleLastMove => ("Last move highlights", &mut highlights.last_move),
+                    _                       => ("Cursor restriction", &mut highlights.restrict_cursor),
+                };
+                *setting = !*setting;
+                log!(log, "{} {}", name, if *setting { "on" } else { "off" });
+                None
+            },
+            // Handled with the cursor
+            Command::Up | Command::Down | Command::Left | Command::Right | Command::Select | Command::Quit
+                | Command::LogUp | Command::LogDown => None,
+            // Only for replays, the position editor and puzzles
+            Command::First | Command::Last | Command::SideToMove | Command::ClearBoard | Command::Export
+                | Command::Play | Command::NextPuzzle | Command::PreviousPuzzle => None,
+        }
+    }
+}
+impl Frontend for CursesFrontend {
+    fn get_preferences(&mut self) -> Result<Option<Preferences>, FrontendError> {
+        // Watchers have no players to choose, only the look of the board
+        if self.args.skip_menu || self.watching {
+            return Ok(Some(self.menu.preferences()));
+        }
+        loop {
+            // Typed text goes to the menu, not the key bindings
+            let event = match self.menu.editing() {
+                true => match self.window.getch() {
+                    Some(Input::KeyResize) => Some(InputEvent::Resize),
+                    Some(input) => {
+                        self.menu.type_input(input);
+                        None
+                    },
+                    None => None,
                 },
+                false => Self::process_input(&self.window, &self.args.bindings, &mut self.menu),
             };
+            match event {
+                Some(InputEvent::Action(prefs)) => {
+                    if let (true, Some(path)) = (self.menu.changed(), &self.args.config_path) {
+                        if let Err(err) = config::save_menu_choices(path, prefs, &self.menu.appearance()) {
+                            self.save_error = Some(format!("Could not save settings to {}: {}", path.display(), err));
+                        }
+                    }
+                    break Ok(Some(prefs));
+                },
+                // Quitting from a submenu only leaves it
+                Some(InputEvent::Quit) if self.menu.back() => (),
+                Some(InputEvent::Quit) => break Ok(None),
+                Some(InputEvent::Command(Command::Cancel)) => {
+                    self.menu.back();
+                },
+                Some(InputEvent::Resize) => {
+                    self.window.clear();
+                },
+                Some(InputEvent::Command(_)) | Some(InputEvent::ScrollLog(_)) | None => (),
+            }
 
-            // self.window.addstr("○●◯◖◗⬤⭗⭕⭘🔴🔵🞉🞊♛♕♔♚👑⛀⛂⛁⛃");
-            board.draw();
-            log.borrow_mut().window.draw_box(ACS_VLINE(), ACS_HLINE()); // TODO temp?
-            log.borrow_mut().window.refresh();
-            self.window.refresh();
-
-            self.process_input(&mut board);
-            // match self.process_input(&mut board) {
-            //     Some(_) => break,
-            //     None => (),
-            // }
+            let (lines, cols) = self.window.get_max_yx();
+            let (menu_lines, menu_cols) = self.menu.size();
+            if lines < menu_lines || cols < menu_cols {
+                self.draw_too_small((menu_lines, menu_cols));
+            } else {
+                self.menu.draw(&self.window);
+            }
 
-            // TODO I think I can turn this up if I rearrange some things in here
             std::thread::sleep(std::time::Duration::from_millis(10)); // Throttle to keep my laptop from melting
         }
-        // println!("DONE");
-        endwin();
+    }
+
+    fn start_game(&mut self, preferences: Preferences) -> Result<(), FrontendError> {
+        let bindings = &self.args.bindings;
+        let intro = match self.watching {
+            true => vec![
+                format!("Watching the game, {} quits", bindings.primary(Command::Quit)),
+                format!("Press {} to see all the keys", bindings.primary(Command::Help)),
+            ],
+            false => vec![
+                format!("Arrows, hjkl or WASD move, {} or click selects, {} quits",
+                        bindings.primary(Command::Select), bindings.primary(Command::Quit)),
+                format!("Press {} to see all the keys", bindings.primary(Command::Help)),
+            ],
+        };
+        self.open_views(&intro);
+        // Play from the bottom when Dark is the only human
+        if let Some(board) = self.board.as_mut() {
+            board.flipped = !self.watching && preferences.players == ["CPU", "Human"];
+        }
 
         Ok(())
     }
+
+    fn handle_message(&mut self, msg: BackToFrontMessage) {
+        let board = self.board.as_mut().expect("Game not started");
+        let log = self.log.as_mut().expect("Game not started");
+        match msg {
+            BackToFrontMessage::Log{ level, msg } => log.add(level, msg),
+            BackToFrontMessage::BoardState(state) => board.set_board_state(state),
+            // Watchers are never asked to move
+            BackToFrontMessage::RequestMove(_) | BackToFrontMessage::RequestJump(..) if self.watching => (),
+            BackToFrontMessage::RequestMove(team) => board.controller.start_selecting_piece(team),
+            BackToFrontMessage::RequestJump(team, square, valid_moves) => board.controller.continue_jumping(team, square, valid_moves),
+            BackToFrontMessage::MoveMade(team, moves) => {
+                if let Some(view) = self.moves.as_mut() {
+                    view.add_turn(team, moves.clone());
+                }
+                board.set_last_move(moves);
+            },
+            BackToFrontMessage::DrawOffered(team) => {
+                self.draw_offer = Some(team);
+                log!(log, "{:?} offers a draw, press {} to accept or {} to decline", team,
+                     self.args.bindings.primary(Command::AcceptDraw), self.args.bindings.primary(Command::DeclineDraw));
+            },
+            BackToFrontMessage::GameOver(_) => {
+                board.controller.stop();
+                self.draw_offer = None;
+            },
+            BackToFrontMessage::Clock(state) => {
+                if let Some(view) = self.clock.as_mut() {
+                    view.clock = Some(Clock::resume(&state));
+                }
+            },
+        };
+    }
+
+    fn poll_input(&mut self) -> Option<UserAction> {
+        if self.help.is_some() {
+            self.close_help_on_key();
+            return None;
+        }
+
+        let board = self.board.as_mut().expect("Game not started");
+        match Self::process_input(&self.window, &self.args.bindings, board)? {
+            InputEvent::Action(_) if self.watching => None,
+            InputEvent::Action(selection) => self.handle_selection(selection),
+            InputEvent::Command(command) => self.handle_command(command),
+            InputEvent::ScrollLog(pages) => {
+                if let Some(log) = self.log.as_mut() {
+                    log.scroll_pages(pages);
+                }
+                None
+            },
+            InputEvent::Resize => {
+                self.resize();
+                None
+            },
+            InputEvent::Quit => Some(UserAction::Quit),
+        }
+    }
+
+    fn draw(&mut self) {
+        // self.window.addstr("○●◯◖◗⬤⭗⭕⭘🔴🔵🞉🞊♛♕♔♚👑⛀⛂⛁⛃");
+        if let Some(size) = self.too_small {
+            self.draw_too_small(size);
+            return;
+        }
+        if let Some(board) = &mut self.board {
+            board.draw();
+        }
+        if let Some(clock) = &self.clock {
+            clock.draw();
+        }
+        if let Some(moves) = &self.moves {
+            moves.draw();
+        }
+        if let Some(log) = &self.log {
+            log.draw();
+        }
+        if let Some(help) = &self.help {
+            help.draw();
+        }
+        self.window.refresh();
+    }
+
+    fn backend_disconnected(&mut self) -> bool {
+        if let Some(log) = self.log.as_mut() {
+            log!(log, "Disconnected from game, press {} to exit", self.args.bindings.primary(Command::Quit));
+        }
+        true
+    }
+}
+/// The key in the config file's terms, or `None` for keys that can't be bound
+fn key_of(input: Input) -> Option<Key> {
+    const ESC: char = 27 as char;
+    const DEL: char = 127 as char;
+    const CTRL_H: char = 8 as char;
+    Some(match input {
+        Input::KeyUp    => Key::Up,
+        Input::KeyDown  => Key::Down,
+        Input::KeyLeft  => Key::Left,
+        Input::KeyRight => Key::Right,
+        Input::KeyEnter | Input::Character('\n') | Input::Character('\r') => Key::Enter,
+        Input::Character(ESC) => Key::Esc,
+        Input::KeyBackspace | Input::Character(DEL) | Input::Character(CTRL_H) => Key::Backspace,
+        Input::KeyDC    => Key::Delete,
+        Input::KeyPPage => Key::PageUp,
+        Input::KeyNPage => Key::PageDown,
+        Input::KeyHome  => Key::Home,
+        Input::KeyEnd   => Key::End,
+        Input::KeyF1  => Key::F(1),
+        Input::KeyF2  => Key::F(2),
+        Input::KeyF3  => Key::F(3),
+        Input::KeyF4  => Key::F(4),
+        Input::KeyF5  => Key::F(5),
+        Input::KeyF6  => Key::F(6),
+        Input::KeyF7  => Key::F(7),
+        Input::KeyF8  => Key::F(8),
+        Input::KeyF9  => Key::F(9),
+        Input::KeyF10 => Key::F(10),
+        Input::KeyF11 => Key::F(11),
+        Input::KeyF12 => Key::F(12),
+        Input::Character(c) => Key::Char(c),
+        _ => return None,
+    })
 }
 
+impl Drop for CursesFrontend {
+    fn drop(&mut self) {
+        endwin();
+    }
+}
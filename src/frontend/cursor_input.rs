@@ -4,4 +4,6 @@ pub trait CursorInput {
     type Action;
     fn move_cursor(&mut self, dir: Input);
     fn do_action(&mut self) -> Option<Self::Action>;
+    /// Mouse click at screen coordinates `y`, `x`
+    fn click(&mut self, y: i32, x: i32) -> Option<Self::Action>;
 }
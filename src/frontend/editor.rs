@@ -0,0 +1,174 @@
+use super::board::BoardView;
+use super::cursor_input::CursorInput;
+
+use crate::backend::{notation, Ai, Board, Piece, PieceType, SearchInfo, SearchLimits, Square, Team, DEFAULT_DEPTH};
+
+use std::time::Duration;
+
+use pancurses::Input;
+
+/// Longest the AI looks at a position when asked to analyse it, unless told otherwise
+const ANALYSIS_TIME: Duration = Duration::from_secs(2);
+
+/// What selecting a square puts on it, each in turn
+const CYCLE: [Option<Piece>; 5] = [
+    None,
+    Some(Piece{ team: Team::Light, piece_type: PieceType::Man }),
+    Some(Piece{ team: Team::Light, piece_type: PieceType::King }),
+    Some(Piece{ team: Team::Dark, piece_type: PieceType::Man }),
+    Some(Piece{ team: Team::Dark, piece_type: PieceType::King }),
+];
+
+/// A position being set up by hand, with every earlier version of it to undo back to.
+pub struct Editor {
+    pub board: Board,
+    pub to_move: Team,
+    history: Vec<(Board, Team)>,
+}
+impl Editor {
+    pub fn new(board: Board, to_move: Team) -> Editor {
+        Editor {
+            board,
+            to_move,
+            history: Vec::new(),
+        }
+    }
+
+    /// Swaps what's on `square` for the next of nothing, a light man, a light king, a dark man and a dark king.  Light
+    /// squares can't hold pieces, so returns `false` and leaves them alone.
+    pub fn cycle(&mut self, square: Square) -> bool {
+        if notation::square_number(&square).is_none() {
+            return false;
+        }
+        let current = self.board.get_piece_at(&square).copied();
+        let idx = CYCLE.iter().position(|piece| *piece == current).unwrap_or(0);
+        self.save();
+        match CYCLE[(idx + 1) % CYCLE.len()] {
+            Some(piece) => self.board.place_piece(square, piece),
+            None => {
+                self.board.remove_piece(&square);
+            },
+        }
+        true
+    }
+
+    /// Takes the piece off `square`, returning `false` if there wasn't one.
+    pub fn remove(&mut self, square: Square) -> bool {
+        if self.board.get_piece_at(&square).is_none() {
+            return false;
+        }
+        self.save();
+        self.board.remove_piece(&square);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.save();
+        self.board = Board::empty().with_variant(self.board.variant());
+    }
+
+    pub fn switch_side(&mut self) {
+        self.save();
+        self.to_move = self.to_move.other();
+    }
+
+    /// Goes back to before the last change, returning `false` if nothing has changed.
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some((board, to_move)) => {
+                self.board = board;
+                self.to_move = to_move;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Why a game can't start from here, if it can't
+    pub fn problems(&self) -> Vec<String> {
+        self.board.problems(self.to_move)
+    }
+
+    /// The position in PDN FEN, as `--position` reads it
+    pub fn fen(&self) -> String {
+        notation::format_fen(&self.board, self.to_move)
+    }
+
+    /// The AI's choice for the side to move and what it makes of the position.  Limits left out of `limits` fall back
+    /// on the usual depth and a couple of seconds.
+    pub fn analyse(&self, limits: SearchLimits) -> Option<SearchInfo> {
+        let limits = SearchLimits {
+            depth: limits.depth.or(Some(DEFAULT_DEPTH)),
+            time: limits.time.or(Some(ANALYSIS_TIME)),
+        };
+        Ai{ team: self.to_move }.search(self.board.clone(), limits, None, |_| ())
+    }
+
+    fn save(&mut self) {
+        self.history.push((self.board.clone(), self.to_move));
+    }
+}
+
+/// A search score in words, from the point of view of the side it was searched for
+pub fn describe_score(score: i32) -> String {
+    match score {
+        i32::MAX => "a win".to_string(),
+        i32::MIN => "a loss".to_string(),
+        score => format!("{:+}", score),
+    }
+}
+
+/// Edits the position shown on `view`.  The cursor moves as it does in a game, and selecting or clicking a square
+/// changes what's on it.  The action says the position changed.
+pub struct EditorInput<'a> {
+    pub editor: &'a mut Editor,
+    pub view: &'a mut BoardView,
+}
+impl CursorInput for EditorInput<'_> {
+    type Action = ();
+
+    fn move_cursor(&mut self, dir: Input) {
+        self.view.move_cursor(dir);
+    }
+
+    fn do_action(&mut self) -> Option<()> {
+        Some(()).filter(|_| self.editor.cycle(self.view.cursor()))
+    }
+
+    fn click(&mut self, y: i32, x: i32) -> Option<()> {
+        self.view.set_cursor(self.view.square_at(y, x)?);
+        self.do_action()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor() {
+        let mut editor = Editor::new(Board::empty(), Team::Light);
+        let square = notation::number_square(18).unwrap();
+        assert!(!editor.cycle(Square{ x: 0, y: 0 }));
+        for _ in 0..3 {
+            assert!(editor.cycle(square));
+        }
+        assert_eq!(editor.board.get_piece_at(&square), Some(&Piece{ team: Team::Dark, piece_type: PieceType::Man }));
+        editor.cycle(notation::number_square(11).unwrap());
+        editor.switch_side();
+        assert_eq!(editor.fen(), "B:W11:B18");
+        assert!(editor.problems().is_empty());
+
+        assert!(editor.undo());
+        assert_eq!(editor.to_move, Team::Light);
+        editor.clear();
+        assert!(!editor.remove(square));
+        assert!(editor.undo());
+        assert!(editor.remove(square));
+        assert_eq!(editor.fen(), "W:W11:B");
+        assert_eq!(editor.problems(), ["Dark has no pieces"]);
+
+        assert_eq!(describe_score(i32::MAX), "a win");
+        assert_eq!(describe_score(-10), "-10");
+    }
+}
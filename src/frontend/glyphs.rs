@@ -0,0 +1,66 @@
+//! Glyph sets for drawing pieces.  The curses frontend offers them all, along with any from theme files; the text
+//! frontend always uses plain ASCII.
+
+use crate::backend::{Piece, PieceType, Team};
+
+/// Characters to draw the pieces with
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphSet {
+    pub name: String,
+    /// Light man, light king, dark man and dark king
+    pub pieces: [char; 4],
+    /// Empty squares the selected piece can move to
+    pub destination: char,
+}
+impl GlyphSet {
+    /// Plain ASCII, for any terminal
+    pub fn ascii() -> GlyphSet {
+        GlyphSet{ name: "ascii".to_string(), pieces: ['O', '@', '=', '#'], destination: '.' }
+    }
+
+    #[cfg(feature = "curses")]
+    pub fn built_in() -> Vec<GlyphSet> {
+        vec![
+            GlyphSet{ name: "fancy".to_string(), pieces: ['⛂', '⛃', '⛀', '⛁'], destination: '·' },
+            GlyphSet::ascii(),
+            GlyphSet{ name: "discs".to_string(), pieces: ['○', '◎', '●', '◉'], destination: '·' },
+        ]
+    }
+
+    pub fn glyph(&self, piece: Option<&Piece>) -> char {
+        match piece {
+            Some(piece) => match (piece.team, piece.piece_type) {
+                (Team::Light, PieceType::Man)  => self.pieces[0],
+                (Team::Light, PieceType::King) => self.pieces[1],
+                (Team::Dark,  PieceType::Man)  => self.pieces[2],
+                (Team::Dark,  PieceType::King) => self.pieces[3],
+            },
+            None => ' ',
+        }
+    }
+
+    /// Whether a terminal without Unicode can show it
+    #[cfg(feature = "curses")]
+    pub fn is_ascii(&self) -> bool {
+        self.pieces.iter().chain(Some(&self.destination)).all(char::is_ascii)
+    }
+
+    /// Sets one glyph as a theme file names it
+    #[cfg(feature = "curses")]
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let mut chars = value.chars();
+        let glyph = match (chars.next(), chars.next()) {
+            (Some(glyph), None) => glyph,
+            _ => return Err(format!("'{}' is not a single character", value)),
+        };
+        match key {
+            "light-man"   => self.pieces[0] = glyph,
+            "light-king"  => self.pieces[1] = glyph,
+            "dark-man"    => self.pieces[2] = glyph,
+            "dark-king"   => self.pieces[3] = glyph,
+            "destination" => self.destination = glyph,
+            _ => return Err(format!("unknown glyph '{}'", key)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+extern crate pancurses;
+
+use super::keys::KeyBindings;
+
+use pancurses::{A_BOLD, ACS_HLINE, ACS_VLINE};
+
+const TITLE: &str = " Keys ";
+const FOOTER: &str = "Press any key to close";
+const COLUMN_GAP: usize = 4;
+
+/// Box listing every command and its keys, drawn over the game.
+pub struct HelpView {
+    window: pancurses::Window,
+    /// Description and keys of each command
+    lines: Vec<(&'static str, String)>,
+    rows: usize,
+    column_width: usize,
+}
+impl HelpView {
+    /// Centres the list over `parent`, in two columns if one is too tall, or `None` if even that doesn't fit.
+    pub fn new(parent: &pancurses::Window, bindings: &KeyBindings) -> Option<HelpView> {
+        let lines: Vec<_> = bindings.all().map(|(command, _)| (command.description(), bindings.describe(command))).collect();
+        let description_width = lines.iter().map(|(description, _)| description.len()).max().unwrap_or(0);
+        let keys_width = lines.iter().map(|(_, keys)| keys.chars().count()).max().unwrap_or(0);
+        let column_width = description_width + 2 + keys_width;
+
+        let (max_lines, max_cols) = parent.get_max_yx();
+        let (rows, columns) = [1, 2].iter()
+            .map(|&columns| (lines.len().div_ceil(columns), columns))
+            .find(|&(rows, _)| rows as i32 + 4 <= max_lines)?;
+        let width = (columns * column_width + (columns - 1) * COLUMN_GAP + 4) as i32;
+        let height = rows as i32 + 4;
+        if width > max_cols {
+            return None;
+        }
+
+        // Its own window rather than a subwindow, so the game underneath is still there once it closes
+        let window = pancurses::newwin(height, width, (max_lines - height) / 2, (max_cols - width) / 2);
+        Some(HelpView { window, lines, rows, column_width })
+    }
+
+    pub fn draw(&self) {
+        self.window.erase();
+        self.window.draw_box(ACS_VLINE(), ACS_HLINE());
+        self.window.attron(A_BOLD);
+        self.window.mvaddstr(0, 2, TITLE);
+        self.window.attroff(A_BOLD);
+        for (idx, (description, keys)) in self.lines.iter().enumerate() {
+            let (row, column) = (idx % self.rows, idx / self.rows);
+            let x = 2 + column * (self.column_width + COLUMN_GAP);
+            let gap = self.column_width - description.len() - keys.chars().count();
+            self.window.mvaddstr(1 + row as i32, x as i32, format!("{}{:gap$}{}", description, "", keys, gap=gap));
+        }
+        let (height, width) = self.window.get_max_yx();
+        self.window.mvaddstr(height - 2, (width - FOOTER.len() as i32) / 2, FOOTER);
+        self.window.refresh();
+    }
+}
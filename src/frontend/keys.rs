@@ -0,0 +1,260 @@
+//! Key bindings for the curses frontend.  Kept free of curses so the config file can read and write them in any
+//! build.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A key, as named in the config file: a single character, or one of the names in `NAMED_KEYS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Esc,
+    Backspace,
+    Delete,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    F(u8),
+}
+
+const NAMED_KEYS: &[(Key, &str)] = &[
+    (Key::Char(' '), "Space"),
+    (Key::Char('\t'), "Tab"),
+    (Key::Char(','), "Comma"),
+    (Key::Char('#'), "Hash"),
+    (Key::Up,        "Up"),
+    (Key::Down,      "Down"),
+    (Key::Left,      "Left"),
+    (Key::Right,     "Right"),
+    (Key::Enter,     "Enter"),
+    (Key::Esc,       "Esc"),
+    (Key::Backspace, "Backspace"),
+    (Key::Delete,    "Delete"),
+    (Key::PageUp,    "PageUp"),
+    (Key::PageDown,  "PageDown"),
+    (Key::Home,      "Home"),
+    (Key::End,       "End"),
+];
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((_, name)) = NAMED_KEYS.iter().find(|(key, _)| key == self) {
+            return write!(f, "{}", name);
+        }
+        match self {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::F(n) => write!(f, "F{}", n),
+            key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Key, String> {
+        let mut chars = text.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Key::Char(c));
+        }
+        if let Some((key, _)) = NAMED_KEYS.iter().find(|(_, name)| name.eq_ignore_ascii_case(text)) {
+            return Ok(*key);
+        }
+        match text.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok()) {
+            Some(n) if (1..=12).contains(&n) => Ok(Key::F(n)),
+            _ => Err(format!("'{}' is not a key", text)),
+        }
+    }
+}
+
+/// Something a key can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Cancel,
+    Undo,
+    Hint,
+    Help,
+    Quit,
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Flip,
+    Labels,
+    LogFilter,
+    LogUp,
+    LogDown,
+    First,
+    Last,
+    SideToMove,
+    ClearBoard,
+    Export,
+    Play,
+    NextPuzzle,
+    PreviousPuzzle,
+    ToggleMoves,
+    ToggleCaptures,
+    ToggleLastMove,
+    ToggleRestrict,
+}
+
+/// Every command, with its name in the config file, what it does and its default keys
+const COMMANDS: &[(Command, &str, &str, &[Key])] = &[
+    (Command::Up,             "up",               "Move up",                      &[Key::Up, Key::Char('k'), Key::Char('w')]),
+    (Command::Down,           "down",             "Move down",                    &[Key::Down, Key::Char('j'), Key::Char('s')]),
+    (Command::Left,           "left",             "Move left",                    &[Key::Left, Key::Char('h'), Key::Char('a')]),
+    (Command::Right,          "right",            "Move right",                   &[Key::Right, Key::Char('l'), Key::Char('d')]),
+    (Command::Select,         "select",           "Select",                       &[Key::Enter, Key::Char(' ')]),
+    (Command::Cancel,         "cancel",           "Cancel the selection",         &[Key::Backspace, Key::Char('x')]),
+    (Command::Undo,           "undo",             "Take back your last move",     &[Key::Char('u')]),
+    (Command::Hint,           "hint",             "Suggest a move",               &[Key::Char('t')]),
+    (Command::Help,           "help",             "Show these keys",              &[Key::Char('?'), Key::F(1)]),
+    (Command::Quit,           "quit",             "Quit",                         &[Key::Char('q'), Key::Esc, Key::Delete]),
+    (Command::Resign,         "resign",           "Resign",                       &[Key::Char('r')]),
+    (Command::OfferDraw,      "offer-draw",       "Offer a draw",                 &[Key::Char('o')]),
+    (Command::AcceptDraw,     "accept-draw",      "Accept a draw offer",          &[Key::Char('y')]),
+    (Command::DeclineDraw,    "decline-draw",     "Decline a draw offer",         &[Key::Char('n')]),
+    (Command::Flip,           "flip",             "Flip the board",               &[Key::Char('f')]),
+    (Command::Labels,         "labels",           "Change the square labels",     &[Key::Char('c')]),
+    (Command::LogFilter,      "log-filter",       "Show more or less log detail", &[Key::Char('v')]),
+    (Command::LogUp,          "log-up",           "Scroll the log back",          &[Key::PageUp]),
+    (Command::LogDown,        "log-down",         "Scroll the log forward",       &[Key::PageDown]),
+    (Command::First,          "first",            "Go to the start of a replay",  &[Key::Home, Key::Char('g')]),
+    (Command::Last,           "last",             "Go to the end of a replay",    &[Key::End, Key::Char('G')]),
+    (Command::SideToMove,     "side-to-move",     "Change the side to move",      &[Key::Char('m')]),
+    (Command::ClearBoard,     "clear-board",      "Empty the board",              &[Key::Char('z')]),
+    (Command::Export,         "export",           "Write out the position",       &[Key::Char('e')]),
+    (Command::Play,           "play",             "Play from the position",       &[Key::Char('p')]),
+    (Command::NextPuzzle,     "next-puzzle",      "Go to the next puzzle",        &[Key::Char(']')]),
+    (Command::PreviousPuzzle, "previous-puzzle",  "Go to the previous puzzle",    &[Key::Char('[')]),
+    (Command::ToggleMoves,    "toggle-moves",     "Toggle move highlights",       &[Key::Char('1')]),
+    (Command::ToggleCaptures, "toggle-captures",  "Toggle capture highlights",    &[Key::Char('2')]),
+    (Command::ToggleLastMove, "toggle-last-move", "Toggle last move highlights",  &[Key::Char('3')]),
+    (Command::ToggleRestrict, "toggle-restrict",  "Toggle cursor restriction",    &[Key::Char('4')]),
+];
+
+fn command_entry(command: Command) -> &'static (Command, &'static str, &'static str, &'static [Key]) {
+    COMMANDS.iter().find(|(c, _, _, _)| *c == command).unwrap()
+}
+
+impl Command {
+    /// Name in the config file
+    pub fn name(self) -> &'static str {
+        command_entry(self).1
+    }
+
+    pub fn description(self) -> &'static str {
+        command_entry(self).2
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Command, String> {
+        COMMANDS.iter().find(|(_, name, _, _)| *name == text).map(|(command, _, _, _)| *command)
+            .ok_or_else(|| format!("unknown command '{}'", text))
+    }
+}
+
+/// Keys for every command.  A key only ever belongs to one command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyBindings {
+    bindings: Vec<(Command, Vec<Key>)>,
+}
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            bindings: COMMANDS.iter().map(|(command, _, _, keys)| (*command, keys.to_vec())).collect(),
+        }
+    }
+}
+impl KeyBindings {
+    pub fn command(&self, key: Key) -> Option<Command> {
+        self.bindings.iter().find(|(_, keys)| keys.contains(&key)).map(|(command, _)| *command)
+    }
+
+    pub fn keys(&self, command: Command) -> &[Key] {
+        self.bindings.iter().find(|(c, _)| *c == command).map_or(&[], |(_, keys)| keys)
+    }
+
+    /// Binds `keys` to `command` instead of its current keys, taking them from any other command.
+    pub fn set(&mut self, command: Command, keys: Vec<Key>) {
+        for (c, bound) in &mut self.bindings {
+            if *c == command {
+                *bound = keys.clone();
+            } else {
+                bound.retain(|key| !keys.contains(key));
+            }
+        }
+    }
+
+    /// Commands whose keys differ from the defaults
+    pub fn changed(&self) -> impl Iterator<Item = (Command, &[Key])> {
+        self.bindings.iter()
+            .filter(|(command, keys)| keys[..] != *command_entry(*command).3)
+            .map(|(command, keys)| (*command, &keys[..]))
+    }
+
+    /// First key for `command`, to mention in messages
+    pub fn primary(&self, command: Command) -> String {
+        self.keys(command).first().map_or_else(|| "(unbound)".to_string(), Key::to_string)
+    }
+
+    /// Keys for `command` as they would be written in the config file, e.g. `Up, k, w`
+    pub fn describe(&self, command: Command) -> String {
+        format_keys(self.keys(command))
+    }
+
+    /// Every command and its keys, in the order they are listed in help
+    pub fn all(&self) -> impl Iterator<Item = (Command, &[Key])> {
+        self.bindings.iter().map(|(command, keys)| (*command, &keys[..]))
+    }
+}
+
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(Key::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Reads a comma separated list of keys, e.g. `Up, k, w`; nothing at all leaves the command unbound.
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    text.split(',').map(str::trim).filter(|key| !key.is_empty()).map(str::parse).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.command(Key::Char('k')), Some(Command::Up));
+        assert_eq!(bindings.command(Key::Char('d')), Some(Command::Right));
+        assert_eq!(bindings.changed().count(), 0);
+
+        // Taking a key away from movement
+        bindings.set(Command::OfferDraw, parse_keys("d, F2").unwrap());
+        assert_eq!(bindings.command(Key::Char('d')), Some(Command::OfferDraw));
+        assert_eq!(bindings.describe(Command::Right), "Right, l");
+        let changed: Vec<_> = bindings.changed().map(|(command, _)| command).collect();
+        assert_eq!(changed, [Command::Right, Command::OfferDraw]);
+
+        for key in [Key::Char(' '), Key::Char(','), Key::PageUp, Key::F(12), Key::Char('Q')] {
+            assert_eq!(key.to_string().parse(), Ok(key));
+        }
+        assert_eq!("pageup".parse(), Ok(Key::PageUp));
+        assert!("F13".parse::<Key>().is_err());
+        assert!("offer_draw".parse::<Command>().is_err());
+    }
+}
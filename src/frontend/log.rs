@@ -1,14 +1,102 @@
 extern crate pancurses;
 
+use super::log_file::LogFile;
+use super::theme;
+
+use crate::channel::LogLevel;
+
+use pancurses::{A_NORMAL, ACS_HLINE, ACS_VLINE};
+
+/// Messages for the player, newest at the top.  Everything is kept so it can be scrolled back through, filtered by
+/// level and redrawn after a resize.
 pub struct LogView {
     pub window: pancurses::Window,
+    entries: Vec<(LogLevel, String)>,
+    /// Most detailed level shown
+    filter: LogLevel,
+    /// How many lines back from the newest the view is scrolled
+    scroll: usize,
+    file: Option<LogFile>,
+}
+impl LogView {
+    pub fn new(window: pancurses::Window, filter: LogLevel, file: Option<LogFile>) -> LogView {
+        LogView {
+            window,
+            entries: Vec::new(),
+            filter,
+            scroll: 0,
+            file,
+        }
+    }
+
+    pub fn add(&mut self, level: LogLevel, line: String) {
+        if let Some(file) = self.file.as_mut() {
+            file.write(level, &line);
+        }
+        // Keep a scrolled view still while new lines arrive
+        if self.scroll > 0 && level <= self.filter {
+            self.scroll += 1;
+        }
+        self.entries.push((level, line));
+    }
+
+    /// Shows more detail, or goes back to just `Info` after showing everything.
+    pub fn cycle_filter(&mut self) -> LogLevel {
+        self.filter = match self.filter {
+            LogLevel::Info  => LogLevel::Ai,
+            LogLevel::Ai    => LogLevel::Debug,
+            LogLevel::Debug => LogLevel::Info,
+        };
+        self.scroll = 0;
+        self.filter
+    }
+
+    /// Scrolls back (positive) or forward (negative) by whole pages
+    pub fn scroll_pages(&mut self, pages: i32) {
+        let page = self.page_height() as i32;
+        let max_scroll = self.visible().count().saturating_sub(self.page_height());
+        self.scroll = (self.scroll as i32 + pages * page).clamp(0, max_scroll as i32) as usize;
+    }
+
+    fn page_height(&self) -> usize {
+        std::cmp::max(self.window.get_max_y() - 2, 0) as usize
+    }
+
+    /// Lines that pass the filter, newest first
+    fn visible(&self) -> impl Iterator<Item = (LogLevel, &str)> {
+        let filter = self.filter;
+        self.entries.iter().rev()
+            .filter(move |(level, _)| *level <= filter)
+            .map(|(level, line)| (*level, line.as_str()))
+    }
+
+    pub fn draw(&self) {
+        let width = std::cmp::max(self.window.get_max_x() - 2, 0) as usize;
+        let height = self.page_height();
+        self.window.draw_box(ACS_VLINE(), ACS_HLINE());
+        let shown = match self.filter {
+            LogLevel::Info  => "info",
+            LogLevel::Ai    => "info+ai",
+            LogLevel::Debug => "all",
+        };
+        let title = match self.scroll {
+            0 => format!(" Log: {} ", shown),
+            scroll => format!(" Log: {}, {} newer ", shown, scroll),
+        };
+        self.window.mvaddstr(0, 2, title);
+        let lines = self.visible().skip(self.scroll).chain(std::iter::repeat((LogLevel::Info, "")));
+        for (y, (level, line)) in (1..).zip(lines.take(height)) {
+            self.window.color_set(theme::log_pair(level));
+            self.window.mvaddstr(y, 1, format!("{:width$.width$}", line, width=width));
+        }
+        self.window.attrset(A_NORMAL);
+        self.window.refresh();
+    }
 }
 
+/// `log!(log_view, "...")` adds an `Info` line
 macro_rules! log {
     ( $log_view:expr, $( $arg:expr ),* ) => {{
-        let log = $log_view.borrow_mut();
-        log.window.mv(1, 1);
-        log.window.insertln();
-        log.window.addstr(format!($($arg),*));
+        $log_view.add($crate::channel::LogLevel::Info, format!($($arg),*));
     }};
 }
@@ -0,0 +1,67 @@
+//! Copies log messages to a file on disk so they outlive the game, each line stamped with the time (UTC).
+
+use crate::channel::LogLevel;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct LogFile {
+    file: LineWriter<File>,
+}
+impl LogFile {
+    /// Appends to `path`, so several games can share one file.
+    pub fn create(path: &str) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut result = LogFile {
+            file: LineWriter::new(file),
+        };
+        result.file.write_all(format!("{} --- new game ---\n", timestamp(SystemTime::now())).as_bytes())?;
+        Ok(result)
+    }
+
+    /// Writes every level, whatever the frontend is showing.  Errors are ignored: losing the copy shouldn't stop
+    /// the game.
+    pub fn write(&mut self, level: LogLevel, msg: &str) {
+        let line = format!("{} [{}] {}\n", timestamp(SystemTime::now()), level, msg);
+        self.file.write_all(line.as_bytes()).ok();
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS.mmm` in UTC
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}", year, month, day,
+            secs / 3600 % 24, secs / 60 % 60, secs % 60, since_epoch.subsec_millis())
+}
+
+/// Year, month and day of a count of days since 1970-01-01, from Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamp() {
+        let at = |secs, millis| timestamp(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis));
+        assert_eq!(at(0, 0), "1970-01-01 00:00:00.000");
+        assert_eq!(at(1_000_000_000, 250), "2001-09-09 01:46:40.250");
+        assert_eq!(at(951_782_400, 0), "2000-02-29 00:00:00.000");
+        assert_eq!(at(1_798_761_599, 999), "2026-12-31 23:59:59.999");
+    }
+}
@@ -1,106 +1,223 @@
 extern crate pancurses;
 
+use super::board;
 use super::cursor_input::CursorInput;
+use super::options::{MenuOption, OptionKind, OptionMenu, Row};
+use super::preferences::{Appearance, Preferences};
+use super::glyphs::GlyphSet;
+use super::theme::{self, Ink, Paper, Theme, Themes};
 
-use crate::backend;
-use backend::{Board, Player};
+use crate::backend::{Piece, PieceType, Team};
 
 use pancurses::{
-    A_REVERSE,
-    ACS_HLINE, ACS_VLINE,
-    curs_set, endwin, initscr, init_pair, Input, noecho, start_color,
+    A_NORMAL, A_REVERSE,
+    Input,
 };
 
-#[repr(i16)]
-pub enum Color {
-    Default      = 0,
-    RedOnWhite   = 1,
-    WhiteOnRed   = 2,
-    RedOnBlack   = 3,
-    BlackOnRed   = 4,
-    WhiteOnBlack = 5,
-    BlackOnWhite = 6,
-}
+const PLAYERS: [&str; 2] = ["Human", "CPU"];
 
-arg_enum! {
-    #[derive(Clone, Copy, Debug)]
-    pub enum ColorScheme {
-        WhiteRed,
-        RedBlack,
-        WhiteBlack,
-    }
+const SPACING_X: usize = 6;
+const SPACING_Y: usize = 1;
+const START_LABEL: &str = "[ Start ]";
+const BACK_LABEL: &str = "[ Back ]";
+/// Lines above the options, for a submenu's title
+const TITLE_HEIGHT: usize = 2;
+
+/// Everything the menu edits
+#[derive(Clone, PartialEq)]
+struct Settings {
+    preferences: Preferences,
+    appearance: Appearance,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Preferences {
-    // pub players: [Player; 2],
-    pub players: [&'static str; 2],
-    pub color_scheme: ColorScheme,
-    pub ascii: bool,
+/// What a preview square shows besides its piece
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    None,
+    Cursor,
+    Selected,
+    Destination,
+    Capture,
+    LastMove,
 }
 
-// struct MenuItem{description: &'static str, value_labels: &'static [&'static str], default: &'static str}
-// struct MenuItem<V> {
-struct MenuItem {
-    description: &'static str,
-    value_labels: &'static [&'static str],
-    // values: &'static [V],
-    default: usize,
+const fn piece(team: Team, piece_type: PieceType) -> Option<Piece> {
+    Some(Piece{ team, piece_type })
 }
-// const MENU: &'static [&'static MenuItem] = &[
-const MENU: &[&MenuItem] = &[
-    // TODO CPU difficulty
-    &MenuItem {
-        description:  "Player 1 (light)",
-        value_labels: &["Human", "CPU"],
-        // values:       &[true, false], // TODO
-        default:      0,
-    },
-    &MenuItem {
-        description:  "Player 2 (dark)",
-        value_labels: &["Human", "CPU"],
-        // values:       &[true, false], // TODO
-        default:      1,
-    },
-    // TODO remove this option if console does not support colors
-    &MenuItem {
-        description:  "Color Scheme",
-        value_labels: &["Red/Black", "White/Red", "White/Black"],
-        // values:       &[ColorScheme::RedBlack, ColorScheme::WhiteRed, ColorScheme::WhiteBlack],
-        default:      0,
-    },
-    &MenuItem {
-        description:  "Fancy Icons",
-        value_labels: &["ON (⛂⛃⛀⛁)", "OFF (O@=#)"],
-        // values:       &[true, false],
-        default:      0,
-    },
+
+/// Dark squares of a two row strip of board showing off the theme; the rest are empty
+const PREVIEW: &[(usize, usize, Option<Piece>, Mark)] = &[
+    (1, 0, piece(Team::Light, PieceType::Man),  Mark::None),
+    (3, 0, piece(Team::Dark,  PieceType::Man),  Mark::Cursor),
+    (5, 0, None,                                Mark::Destination),
+    (7, 0, piece(Team::Light, PieceType::King), Mark::LastMove),
+    (0, 1, piece(Team::Dark,  PieceType::King), Mark::None),
+    (2, 1, piece(Team::Light, PieceType::Man),  Mark::Selected),
+    (4, 1, None,                                Mark::LastMove),
+    (6, 1, piece(Team::Dark,  PieceType::Man),  Mark::Capture),
 ];
+const PREVIEW_COLUMNS: usize = 8;
+const PREVIEW_ROWS: usize = 2;
+const PREVIEW_SQUARE_WIDTH: i32 = 3;
+
+/// How an option's value is shown, and the part of that to highlight under the cursor
+fn value_text(option: &MenuOption<Settings>, settings: &Settings, editing: Option<&str>) -> (String, String) {
+    match (&option.kind, editing) {
+        (OptionKind::Submenu(_), _) => (">".to_string(), ">".to_string()),
+        (OptionKind::Text{ .. }, Some(text)) => (format!("[ {}_ ]", text), format!("{}_", text)),
+        (OptionKind::Text{ .. }, None) => {
+            let value = option.value_label(settings);
+            (format!("[ {} ]", value), value)
+        },
+        _ => {
+            let value = option.value_label(settings);
+            (format!("< {} >", value), value)
+        },
+    }
+}
+
+/// Widths of the description and value columns, wide enough for every page
+fn column_widths(options: &[&MenuOption<Settings>]) -> (usize, usize) {
+    let description_column_width = options
+        .iter()
+        .map(|option| option.label.len())
+        .max()
+        .unwrap();
+    let value_column_width = options
+        .iter()
+        .map(|option| option.value_width() + 5) // account for Unicode chars, and the cursor while typing
+        .max()
+        .unwrap();
+    (description_column_width, value_column_width)
+}
 
 pub struct Menu {
-    cursor: usize,
-    selections: [usize; 4],
+    options: OptionMenu<Settings>,
+    themes: Themes,
+    /// Settings the menu opened with
+    initial: Settings,
+    /// Theme the terminal's colours were last set up for
+    applied_theme: String,
+    /// Where the top left of the options was last drawn, for mouse clicks
+    offset_yx: (i32, i32),
 }
 impl Menu {
-    pub fn new() -> Menu {
-        let mut result = Menu {
-            cursor: 0, // TODO any way to use iterators here?
-            selections: [ // TODO *barf*
-                MENU[0].default,
-                MENU[1].default,
-                MENU[2].default,
-                MENU[3].default,
-            ]
+    /// A menu starting out on `defaults` and `appearance`, though never with glyphs a terminal can't show.  Names
+    /// that aren't in `themes` start on the first theme or glyph set.
+    pub fn new(defaults: &Preferences, appearance: &Appearance, themes: Themes) -> Menu {
+        let mut glyphs = themes.glyph_set_index(&appearance.glyphs).unwrap_or(0);
+        if !themes.glyph_sets[glyphs].is_ascii() && !console::Term::stdout().features().wants_emoji() {
+            glyphs = themes.glyph_sets.iter().position(GlyphSet::is_ascii).unwrap_or(glyphs);
+        }
+        let settings = Settings {
+            preferences: *defaults,
+            appearance: Appearance {
+                theme: themes.themes[themes.theme_index(&appearance.theme).unwrap_or(0)].name.clone(),
+                glyphs: themes.glyph_sets[glyphs].name.clone(),
+            },
         };
-        if !console::Term::stdout().features().wants_emoji() {
-            // TODO check this inline when declaring MENU (have to move MENU init in here)
-            result.selections[3] = 1;
+
+        let players = || PLAYERS.iter().map(|player| (player.to_string(), *player)).collect();
+        let theme_names = themes.themes.iter().map(|theme| (theme.name.clone(), theme.name.clone())).collect();
+        let glyph_sets = themes.glyph_sets.iter()
+            .map(|glyphs| (format!("{} ({})", glyphs.name, glyphs.pieces.iter().collect::<String>()), glyphs.name.clone()))
+            .collect();
+        let options = vec![
+            MenuOption::choice("Player 1 (light)", players(),
+                               |s: &Settings| s.preferences.players[0], |s, player| s.preferences.players[0] = player),
+            MenuOption::choice("Player 2 (dark)", players(),
+                               |s: &Settings| s.preferences.players[1], |s, player| s.preferences.players[1] = player),
+            MenuOption::choice("Theme", theme_names,
+                               |s: &Settings| s.appearance.theme.clone(), |s, theme| s.appearance.theme = theme)
+                .available(pancurses::has_colors()),
+            MenuOption::choice("Pieces", glyph_sets,
+                               |s: &Settings| s.appearance.glyphs.clone(), |s, glyphs| s.appearance.glyphs = glyphs),
+        ];
+
+        let menu = Menu {
+            options: OptionMenu::new(settings.clone(), options),
+            themes,
+            applied_theme: settings.appearance.theme.clone(),
+            initial: settings,
+            offset_yx: (0, 0),
+        };
+        theme::apply(menu.theme());
+        menu
+    }
+
+    /// Whether the user has changed any options
+    pub fn changed(&self) -> bool {
+        self.options.settings != self.initial
+    }
+
+    pub fn theme(&self) -> &Theme {
+        let idx = self.themes.theme_index(&self.options.settings.appearance.theme).unwrap_or(0);
+        &self.themes.themes[idx]
+    }
+
+    pub fn glyphs(&self) -> &GlyphSet {
+        let idx = self.themes.glyph_set_index(&self.options.settings.appearance.glyphs).unwrap_or(0);
+        &self.themes.glyph_sets[idx]
+    }
+
+    /// Theme files that couldn't be loaded
+    pub fn theme_errors(&self) -> &[String] {
+        &self.themes.errors
+    }
+
+    pub fn appearance(&self) -> Appearance {
+        self.options.settings.appearance.clone()
+    }
+
+    /// The game as set up so far
+    pub fn preferences(&self) -> Preferences {
+        self.options.settings.preferences
+    }
+
+    /// Leaves the text being typed or the submenu open.  Returns `false` if there was neither.
+    pub fn back(&mut self) -> bool {
+        self.options.back()
+    }
+
+    /// Whether keys should go to `type_input` rather than be commands
+    pub fn editing(&self) -> bool {
+        self.options.editing().is_some()
+    }
+
+    /// A key typed while editing text: Enter keeps the text and Esc throws it away.
+    pub fn type_input(&mut self, input: Input) {
+        match input {
+            Input::KeyEnter | Input::Character('\n') | Input::Character('\r') => self.options.finish_editing(),
+            Input::Character('\u{1b}') => {
+                self.options.back();
+            },
+            Input::KeyBackspace | Input::KeyDC | Input::Character('\u{7f}') | Input::Character('\u{8}') => self.options.erase(),
+            Input::Character(c) if !c.is_control() => self.options.type_char(c),
+            _ => (),
+        }
+    }
+
+    /// Recolours the screen if the theme option has changed, so the preview and anything else show it.
+    fn apply_theme(&mut self) {
+        if self.applied_theme != self.options.settings.appearance.theme {
+            self.applied_theme = self.options.settings.appearance.theme.clone();
+            theme::apply(self.theme());
         }
+    }
+
+    fn menu_width(&self) -> usize {
+        let (description_column_width, value_column_width) = column_widths(&self.options.all());
+        description_column_width + value_column_width + SPACING_X
+    }
 
-        result
+    /// Lines and columns the menu needs
+    pub fn size(&self) -> (i32, i32) {
+        let preview_width = PREVIEW_COLUMNS * PREVIEW_SQUARE_WIDTH as usize;
+        let height = TITLE_HEIGHT + self.options.all().len() + 2 * SPACING_Y + 1 + PREVIEW_ROWS;
+        (height as i32, self.menu_width().max(preview_width) as i32)
     }
 
-    pub fn draw(&self, window: &mut pancurses::Window) {
+    pub fn draw(&mut self, window: &pancurses::Window) {
         let mid_single = |small: i32, big: i32| -> i32 {
             small + (big - small) / 2
         };
@@ -108,82 +225,145 @@ impl Menu {
             (mid_single(small.0, big.0), mid_single(small.1, big.1))
         };
 
-        const SPACING_X: usize = 6;
-        const SPACING_Y: usize = 1;
-        let menu_height = MENU.len() + SPACING_Y + 1;
-        let description_column_width = MENU
-            .iter()
-            .map(|item| item.description.len())
-            .max()
-            .unwrap();
-        let value_column_width = MENU
-            .iter()
-            .map(|item| item.value_labels.iter().map(|x| x.chars().count()).max().unwrap() + 4) // account for Unicode chars
-            .max()
-            .unwrap();
-        let menu_width = description_column_width + value_column_width + SPACING_X;
-        let menu_size = (menu_height, menu_width);
-        let menu_half_size = (menu_height / 2, menu_width / 2);
+        let (menu_height, _) = self.size();
+        let (description_column_width, value_column_width) = column_widths(&self.options.all());
+        let menu_width = self.menu_width();
+        let menu_half_size = (menu_height as usize / 2, menu_width / 2);
 
         let top_left = window.get_beg_yx();
         let bottom_right = window.get_max_yx();
         let center_yx = mid_rect(top_left, bottom_right);
-        let offset_yx = (center_yx.0 - menu_half_size.0 as i32, center_yx.1 - menu_half_size.1 as i32);
+        let offset_yx = (center_yx.0 - menu_half_size.0 as i32 + TITLE_HEIGHT as i32,
+                         center_yx.1 - menu_half_size.1 as i32);
+        self.offset_yx = offset_yx;
 
-        for (idx, item) in MENU.iter().enumerate() {
-            let selected_value = item.value_labels[self.selections[idx]];
-            let value = format!("< {} >", selected_value);
-            // let value = format!("< {:^width$} >", item.value_labels[self.selections[idx]], width=);
+        // Pages differ in length, so start afresh
+        window.erase();
+        if let Some(title) = self.options.title() {
+            window.mvaddstr(offset_yx.0 - TITLE_HEIGHT as i32, offset_yx.1, format!("{:^width$}", title, width=menu_width));
+        }
+        let settings = &self.options.settings;
+        let mut rows = 0;
+        for (idx, option) in self.options.page().enumerate() {
+            let under_cursor = self.options.cursor() == idx;
+            let editing = self.options.editing().filter(|_| under_cursor);
+            let (value, highlighted) = value_text(option, settings, editing);
             window.mvaddstr(offset_yx.0 + idx as i32, offset_yx.1,
                             format!("{desc:<desc_width$}{spacing}{value:>value_width$}",
-                                    desc=item.description, desc_width=description_column_width,
+                                    desc=option.label, desc_width=description_column_width,
                                     spacing=str::repeat(" ", SPACING_X),
                                     value=value, value_width=value_column_width));
-            if self.cursor == idx {
-                let value_width = selected_value.chars().count() as i32; // account for Unicode chars
+            if under_cursor {
+                let value_width = highlighted.chars().count() as i32; // account for Unicode chars
+                let after = (value.chars().count() - highlighted.chars().count()) as i32 / 2;
                 window.mvchgat(
                     offset_yx.0 + idx as i32,
-                    offset_yx.1 + menu_width as i32 - value_width - 2,
+                    offset_yx.1 + menu_width as i32 - value_width - after,
                     value_width,
                     A_REVERSE,
-                    Color::Default as i16,
+                    0,
                 );
             }
+            rows += 1;
         }
+        let button_y = offset_yx.0 + (rows + SPACING_Y) as i32;
+        let button = if self.options.title().is_some() { BACK_LABEL } else { START_LABEL };
+        window.mvaddstr(button_y, offset_yx.1, format!("{:^width$}", button, width=menu_width));
+        if self.options.cursor() == rows {
+            window.mvchgat(button_y, offset_yx.1 + (menu_width - button.len()) as i32 / 2, button.len() as i32, A_REVERSE, 0);
+        }
+        let preview_y = offset_yx.0 + (self.options.all().len() + 1 + 2 * SPACING_Y) as i32;
+        self.draw_preview(window, preview_y, offset_yx.1 + menu_width as i32 / 2);
         window.refresh();
     }
+
+    /// A strip of board in the selected theme and glyphs, centred on `center_x`
+    fn draw_preview(&self, window: &pancurses::Window, top: i32, center_x: i32) {
+        let (theme, glyphs) = (self.theme(), self.glyphs());
+        let left = center_x - PREVIEW_COLUMNS as i32 * PREVIEW_SQUARE_WIDTH / 2;
+        for y in 0..PREVIEW_ROWS {
+            for x in 0..PREVIEW_COLUMNS {
+                let (piece, mark) = PREVIEW.iter()
+                    .find(|(px, py, _, _)| (*px, *py) == (x, y))
+                    .map_or((None, Mark::None), |(_, _, piece, mark)| (*piece, *mark));
+                let (brackets, center, ink) = match (piece, mark) {
+                    (None, Mark::Destination) => ((' ', ' '), glyphs.destination, Ink::Plain),
+                    (None, _) => ((' ', ' '), ' ', Ink::Plain),
+                    (Some(piece), mark) => {
+                        let brackets = match mark {
+                            Mark::Cursor   => ('[', ']'),
+                            Mark::Selected => ('(', ')'),
+                            _              => (' ', ' '),
+                        };
+                        let ink = if piece.team == Team::Light { Ink::LightPiece } else { Ink::DarkPiece };
+                        (brackets, glyphs.glyph(Some(&piece)), ink)
+                    },
+                };
+                let square_paper = if (x + y) % 2 == 1 { Paper::DarkSquare } else { Paper::LightSquare };
+                let highlighted = [mark == Mark::Destination, mark == Mark::Capture, mark == Mark::LastMove];
+                let (paper, attrs) = board::square_style(theme, square_paper, highlighted);
+                board::draw_square(window, (top + y as i32, left + x as i32 * PREVIEW_SQUARE_WIDTH), PREVIEW_SQUARE_WIDTH,
+                                   [brackets.0, center, brackets.1], ink, paper, attrs);
+            }
+        }
+        window.attrset(A_NORMAL);
+        window.color_set(0);
+    }
 }
 impl CursorInput for Menu {
     type Action = Preferences;
 
     fn move_cursor(&mut self, dir: Input) {
-        let mut cursor = self.cursor as i32;
-        let mut selection = self.selections[self.cursor] as i32;
-        let item = MENU[self.cursor];
-
         match dir {
-            Input::KeyLeft => selection -= 1,
-            Input::KeyRight => selection += 1,
-            Input::KeyUp => cursor -= 1,
-            Input::KeyDown => cursor += 1,
+            Input::KeyLeft => self.options.adjust(-1),
+            Input::KeyRight => self.options.adjust(1),
+            Input::KeyUp => self.options.move_cursor(-1),
+            Input::KeyDown => self.options.move_cursor(1),
             _ => panic!("Bad dir passed to move_cursor: {:?}", dir),
         }
-
-        let num_values = item.value_labels.len() as i32;
-        self.selections[self.cursor] = ((selection + num_values) % num_values) as usize;
-        self.cursor = std::cmp::min(std::cmp::max(cursor, 0), (MENU.len() - 1) as i32) as usize;
+        self.apply_theme();
     }
 
+    /// Opens a submenu or starts typing text; on anything else, starts the game (or leaves a submenu).
     fn do_action(&mut self) -> Option<Self::Action> {
-        // TODO put these alongside MENU somehow
-        let get_player       = |s| ["Human", "CPU"][s]; // TODO enum
-        let get_color_scheme = |s| [ColorScheme::RedBlack, ColorScheme::WhiteRed, ColorScheme::WhiteBlack][s];
-        let get_ascii        = |s| [false, true][s];
-       
-        Some(Preferences {
-            players: [get_player(self.selections[0]), get_player(self.selections[1])],
-            color_scheme: get_color_scheme(self.selections[2]),
-            ascii: get_ascii(self.selections[3]),
-        })
+        match self.options.select() {
+            true => Some(self.preferences()),
+            false => None,
+        }
+    }
+
+    /// Clicking an option's arrows or value changes it; clicking the button presses it.
+    fn click(&mut self, y: i32, x: i32) -> Option<Self::Action> {
+        let menu_width = self.menu_width() as i32;
+        let (row, col) = (y - self.offset_yx.0, x - self.offset_yx.1);
+        let rows = self.options.page().count() as i32;
+        if col < 0 || col >= menu_width {
+            return None;
+        }
+        if row == rows + SPACING_Y as i32 {
+            self.options.set_cursor(rows as usize);
+            return self.do_action();
+        }
+        if row < 0 || row >= rows {
+            return None;
+        }
+
+        self.options.set_cursor(row as usize);
+        let (value, adjustable) = match self.options.row() {
+            Row::Option(option) => {
+                let adjustable = matches!(option.kind, OptionKind::Choice{ .. } | OptionKind::Number{ .. });
+                (value_text(option, &self.options.settings, None).0, adjustable)
+            },
+            Row::Button => return None,
+        };
+        let value_start = menu_width - value.chars().count() as i32;
+        match adjustable {
+            true if col >= value_start => self.move_cursor(if col < value_start + 2 { Input::KeyLeft } else { Input::KeyRight }),
+            false if col >= value_start => {
+                self.options.select();
+            },
+            _ => (),
+        }
+        None
     }
 }
@@ -1,10 +1,214 @@
+//! User interfaces.  The curses frontend needs the `curses` feature; the text frontend is always available.
+
+#[cfg(feature = "curses")]
 #[macro_use]
 mod log;
 
+#[cfg(feature = "curses")]
 mod board;
+#[cfg(feature = "curses")]
+mod clock;
+mod controller;
+#[cfg(feature = "curses")]
 mod curses_frontend;
+#[cfg(feature = "curses")]
 mod cursor_input;
+#[cfg(feature = "curses")]
+mod editor;
+mod glyphs;
+#[cfg(feature = "curses")]
+mod help;
+pub mod keys;
+mod log_file;
+#[cfg(feature = "curses")]
 mod menu;
+#[cfg(feature = "curses")]
+mod moves;
+pub mod options;
+mod preferences;
+#[cfg(feature = "curses")]
+mod puzzle;
+#[cfg(feature = "curses")]
+mod replay;
+mod text_frontend;
+#[cfg(feature = "curses")]
+mod theme;
 
+#[cfg(feature = "curses")]
 pub use curses_frontend::CursesFrontend;
-pub use menu::Preferences;
+pub use preferences::{Appearance, Preferences};
+pub use text_frontend::TextFrontend;
+
+use crate::args::Args;
+use crate::backend::notation;
+use crate::channel::{BackToFrontMessage, FrontendEndpoint, FrontToBackMessage};
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::TryRecvError;
+
+/// Frontend used when none is asked for
+#[cfg(feature = "curses")]
+pub const DEFAULT_FRONTEND: &str = "Curses";
+#[cfg(not(feature = "curses"))]
+pub const DEFAULT_FRONTEND: &str = "Text";
+
+arg_enum! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum FrontendKind {
+        Curses,
+        Text,
+    }
+}
+
+#[derive(Debug)]
+pub enum FrontendError {
+    Window(i32),
+}
+impl From<i32> for FrontendError {
+    fn from(code: i32) -> Self {
+        FrontendError::Window(code) // pancurses only seems to have a single -1 code for everything
+    }
+}
+impl fmt::Display for FrontendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrontendError::Window(code) => write!(f, "Window error code {}", code),
+        }
+    }
+}
+
+/// Something the user did that the driver loop needs to act on.
+pub enum UserAction {
+    Send(FrontToBackMessage),
+    Quit,
+}
+
+/// A user interface for one side of the `channel` protocol.
+///
+/// Frontends never touch the channel directly; `run` owns the endpoint and shuttles messages in and out.
+pub trait Frontend {
+    /// Ask the user how the game should be set up.  Returns `None` if they quit instead.
+    fn get_preferences(&mut self) -> Result<Option<Preferences>, FrontendError>;
+    fn start_game(&mut self, preferences: Preferences) -> Result<(), FrontendError>;
+    fn handle_message(&mut self, msg: BackToFrontMessage);
+    /// Check for user input without blocking.
+    fn poll_input(&mut self) -> Option<UserAction>;
+    fn draw(&mut self);
+    /// Called once the backend has hung up.  Returns `false` to exit immediately.
+    fn backend_disconnected(&mut self) -> bool;
+}
+
+pub fn make_frontend(args: &Args) -> Box<dyn Frontend> {
+    match args.frontend {
+        #[cfg(feature = "curses")]
+        FrontendKind::Curses => Box::new(CursesFrontend::new(args)),
+        #[cfg(not(feature = "curses"))]
+        FrontendKind::Curses => {
+            eprintln!("Built without curses support, using the text frontend");
+            Box::new(TextFrontend::new(args))
+        },
+        FrontendKind::Text   => Box::new(TextFrontend::new(args)),
+    }
+}
+
+/// Shows the game recorded in the PDN file at `path`, such as one written with `--record`, to step through.  Only the
+/// curses frontend can.
+pub fn replay(args: &Args, path: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    let record = notation::parse_pdn(&text).map_err(|err| format!("Could not read a game from {}: {}", path.display(), err))?;
+    #[cfg(feature = "curses")]
+    return CursesFrontend::new(args).replay(record).map_err(|err| err.to_string());
+    #[cfg(not(feature = "curses"))]
+    {
+        let _ = (args, record);
+        Err("Built without curses support, which replays need".to_string())
+    }
+}
+
+/// Sets the puzzles in the PDN file at `path`, or the starter set without one, for the user to solve.  Only the
+/// curses frontend can.
+pub fn puzzles(args: &Args, path: Option<&Path>) -> Result<(), String> {
+    #[cfg(feature = "curses")]
+    {
+        let text = match path {
+            Some(path) => fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?,
+            None => puzzle::STARTER_SET.to_string(),
+        };
+        let puzzles = puzzle::parse_puzzles(&text).map_err(|err| match path {
+            Some(path) => format!("Could not read puzzles from {}: {}", path.display(), err),
+            None => format!("Bad starter puzzles: {}", err),
+        })?;
+        if puzzles.is_empty() {
+            return Err("No puzzles to solve".to_string());
+        }
+        CursesFrontend::new(args).puzzles(puzzles).map_err(|err| err.to_string())
+    }
+    #[cfg(not(feature = "curses"))]
+    {
+        let _ = (args, path);
+        Err("Built without curses support, which puzzles need".to_string())
+    }
+}
+
+/// Lets the user set up a position to play from, putting it in `args`.  Returns the frontend to play it on, or `None`
+/// if they quit instead.  Only the curses frontend can.
+pub fn edit_position(args: &mut Args) -> Result<Option<Box<dyn Frontend>>, String> {
+    #[cfg(feature = "curses")]
+    {
+        let mut frontend = CursesFrontend::new(args);
+        match frontend.edit_position().map_err(|err| err.to_string())? {
+            Some(position) => {
+                args.game.position = Some(position);
+                Ok(Some(Box::new(frontend)))
+            },
+            None => Ok(None),
+        }
+    }
+    #[cfg(not(feature = "curses"))]
+    {
+        let _ = args;
+        Err("Built without curses support, which the position editor needs".to_string())
+    }
+}
+
+pub fn run(frontend: &mut dyn Frontend, backend_channel: FrontendEndpoint) -> Result<(), FrontendError> {
+    let preferences = match frontend.get_preferences()? {
+        Some(prefs) => prefs,
+        None => return Ok(()),
+    };
+    if backend_channel.tx.send(FrontToBackMessage::StartGame(preferences)).is_err() {
+        return Ok(());
+    }
+    frontend.start_game(preferences)?;
+
+    let mut connected = true;
+    loop {
+        while connected {
+            match backend_channel.rx.try_recv() {
+                Ok(msg) => frontend.handle_message(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    connected = false;
+                    if !frontend.backend_disconnected() {
+                        return Ok(());
+                    }
+                },
+            }
+        }
+
+        frontend.draw();
+
+        match frontend.poll_input() {
+            Some(UserAction::Send(msg)) if connected => connected = backend_channel.tx.send(msg).is_ok(),
+            Some(UserAction::Quit) => break,
+            _ => (),
+        }
+
+        // TODO I think I can turn this up if I rearrange some things in here
+        std::thread::sleep(std::time::Duration::from_millis(10)); // Throttle to keep my laptop from melting
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use crate::backend::{notation, Move, Team};
+
+use pancurses::{A_REVERSE, ACS_HLINE, ACS_VLINE};
+
+pub const MOVES_WIDTH: i32 = 24;
+
+/// The game so far in standard notation, one line per pair of turns, with how many pieces each side has taken.
+pub struct MovesView {
+    pub window: pancurses::Window,
+    turns: Vec<(Team, Vec<Move>)>,
+    /// Turn to highlight and keep in view, when replaying
+    pub current: Option<usize>,
+}
+impl MovesView {
+    pub fn new(window: pancurses::Window) -> MovesView {
+        MovesView {
+            window,
+            turns: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub fn add_turn(&mut self, team: Team, moves: Vec<Move>) {
+        self.turns.push((team, moves));
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+        self.current = None;
+    }
+
+    /// Pieces captured by `team`
+    pub fn captured_by(&self, team: Team) -> usize {
+        self.turns.iter()
+            .filter(|(mover, _)| *mover == team)
+            .map(|(_, moves)| moves.iter().filter(|mv| mv.is_jump()).count())
+            .sum()
+    }
+
+    pub fn draw(&self) {
+        let width = (MOVES_WIDTH - 2) as usize;
+        let height = std::cmp::max(self.window.get_max_y() - 3, 0) as usize; // Border and the captures line
+        self.window.draw_box(ACS_VLINE(), ACS_HLINE());
+        let (rows, spans) = rows(&self.turns);
+        let mut first = rows.len().saturating_sub(height);
+        let current = self.current.and_then(|idx| spans.get(idx));
+        if let Some(&(row, _, _)) = current.filter(|(row, _, _)| *row < first) {
+            first = row.saturating_sub(height / 2);
+        }
+        for y in 0..height {
+            let row = rows.get(first + y).map_or("", String::as_str);
+            self.window.mvaddstr(y as i32 + 1, 1, format!("{:width$.width$}", row, width=width));
+        }
+        if let Some(&(row, col, len)) = current.filter(|(row, col, _)| *row >= first && *row < first + height && *col < width) {
+            self.window.mvchgat((row - first) as i32 + 1, col as i32 + 1, len.min(width - col) as i32, A_REVERSE, 0);
+        }
+        let captures = format!("Taken L:{} D:{}", self.captured_by(Team::Light), self.captured_by(Team::Dark));
+        self.window.mvaddstr(height as i32 + 1, 1, format!("{:width$.width$}", captures, width=width));
+        self.window.refresh();
+    }
+}
+
+/// Numbers the turns Light first, the way games are written down.  A game that starts with Dark to move gets `...`
+/// in place of Light's first turn.  Also gives the row, column and length of each turn, for highlighting.
+fn rows(turns: &[(Team, Vec<Move>)]) -> (Vec<String>, Vec<(usize, usize, usize)>) {
+    const NUMBER_WIDTH: usize = 5;
+    let mut rows = Vec::new();
+    let mut spans = Vec::new();
+    let mut awaiting_dark = false;
+    for (team, moves) in turns {
+        let path = notation::format_path(moves);
+        match team {
+            Team::Light => rows.push(format!("{:>3}. {:<8}", rows.len() + 1, path)),
+            Team::Dark if awaiting_dark => {
+                let row: &mut String = rows.last_mut().expect("Light's turn has a row");
+                *row = format!("{} {}", row, path);
+            },
+            Team::Dark => rows.push(format!("{:>3}. {:<8} {}", rows.len() + 1, "...", path)),
+        }
+        let row = &rows[rows.len() - 1];
+        let col = if *team == Team::Light { NUMBER_WIDTH } else { row.chars().count() - path.len() };
+        spans.push((rows.len() - 1, col, path.len()));
+        awaiting_dark = *team == Team::Light;
+    }
+    (rows, spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Square;
+
+    #[test]
+    fn test_rows() {
+        let step = |from: (i8, i8), to: (i8, i8)| vec![Move{ from: Square{ x: from.0, y: from.1 }, to: Square{ x: to.0, y: to.1 } }];
+        let light = step((2, 5), (3, 4));
+        let dark = step((1, 2), (0, 3));
+        assert_eq!(rows(&[(Team::Light, light.clone()), (Team::Dark, dark.clone()), (Team::Light, light.clone())]).0,
+                   vec!["  1. 22-18    9-13", "  2. 22-18   "]);
+        assert_eq!(rows(&[(Team::Dark, dark.clone())]).0, vec!["  1. ...      9-13"]);
+        let (_, spans) = rows(&[(Team::Light, light.clone()), (Team::Dark, dark), (Team::Light, light)]);
+        assert_eq!(spans, [(0, 5, 5), (0, 14, 4), (1, 5, 5)]);
+    }
+}
@@ -0,0 +1,359 @@
+//! Menus of typed options, each reading and writing its part of a settings value directly.  Kept free of curses so
+//! any frontend can use them; the curses frontend's menu draws one.
+
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// Reads which of a choice's values a setting has
+type ChoiceGetter<T> = Box<dyn Fn(&T) -> usize>;
+/// Sets a setting to one of a choice's values
+type ChoiceSetter<T> = Box<dyn Fn(&mut T, usize)>;
+
+pub enum OptionKind<T> {
+    /// One of a list of labelled values
+    Choice {
+        labels: Vec<String>,
+        get: ChoiceGetter<T>,
+        set: ChoiceSetter<T>,
+    },
+    /// A whole number, moved through `range` in steps of `step`
+    Number {
+        range: RangeInclusive<i64>,
+        step: i64,
+        get: fn(&T) -> i64,
+        set: fn(&mut T, i64),
+    },
+    /// Text typed in, up to `max_len` characters
+    Text {
+        max_len: usize,
+        get: fn(&T) -> String,
+        set: fn(&mut T, String),
+    },
+    /// More options, on a page of their own
+    Submenu(Vec<MenuOption<T>>),
+}
+
+pub struct MenuOption<T> {
+    pub label: &'static str,
+    pub kind: OptionKind<T>,
+    /// Whether to show the option at all, e.g. only offering colours on a terminal that has them
+    pub available: bool,
+}
+impl<T> MenuOption<T> {
+    /// Choosing between `values`, shown by their labels.  A setting that isn't one of the values shows as the first.
+    pub fn choice<V: PartialEq + Clone + 'static>(label: &'static str, values: Vec<(String, V)>, get: fn(&T) -> V,
+                                                   set: fn(&mut T, V)) -> MenuOption<T>
+        where T: 'static {
+        let (labels, values): (Vec<_>, Vec<_>) = values.into_iter().unzip();
+        let values = Rc::new(values);
+        let set_values = Rc::clone(&values);
+        MenuOption::new(label, OptionKind::Choice {
+            labels,
+            get: Box::new(move |settings| values.iter().position(|value| *value == get(settings)).unwrap_or(0)),
+            set: Box::new(move |settings, idx| set(settings, set_values[idx].clone())),
+        })
+    }
+
+    pub fn number(label: &'static str, range: RangeInclusive<i64>, step: i64, get: fn(&T) -> i64,
+                  set: fn(&mut T, i64)) -> MenuOption<T> {
+        MenuOption::new(label, OptionKind::Number{ range, step, get, set })
+    }
+
+    pub fn text(label: &'static str, max_len: usize, get: fn(&T) -> String, set: fn(&mut T, String)) -> MenuOption<T> {
+        MenuOption::new(label, OptionKind::Text{ max_len, get, set })
+    }
+
+    pub fn submenu(label: &'static str, options: Vec<MenuOption<T>>) -> MenuOption<T> {
+        MenuOption::new(label, OptionKind::Submenu(options))
+    }
+
+    fn new(label: &'static str, kind: OptionKind<T>) -> MenuOption<T> {
+        MenuOption{ label, kind, available: true }
+    }
+
+    pub fn available(mut self, available: bool) -> MenuOption<T> {
+        self.available = available;
+        self
+    }
+
+    /// The option's current value as shown in the menu
+    pub fn value_label(&self, settings: &T) -> String {
+        match &self.kind {
+            OptionKind::Choice{ labels, get, .. } => labels[get(settings)].clone(),
+            OptionKind::Number{ get, .. } => get(settings).to_string(),
+            OptionKind::Text{ get, .. } => get(settings),
+            OptionKind::Submenu(_) => String::new(),
+        }
+    }
+
+    /// Most characters `value_label` can ever return
+    pub fn value_width(&self) -> usize {
+        match &self.kind {
+            OptionKind::Choice{ labels, .. } => labels.iter().map(|label| label.chars().count()).max().unwrap_or(0),
+            OptionKind::Number{ range, .. } => range.start().to_string().len().max(range.end().to_string().len()),
+            OptionKind::Text{ max_len, .. } => *max_len,
+            OptionKind::Submenu(_) => 0,
+        }
+    }
+
+    /// Moves a choice or number `steps` values along, wrapping choices around and stopping numbers at the ends of
+    /// their range.
+    fn adjust(&self, settings: &mut T, steps: i64) {
+        match &self.kind {
+            OptionKind::Choice{ labels, get, set } => {
+                let len = labels.len() as i64;
+                set(settings, (get(settings) as i64 + steps).rem_euclid(len) as usize);
+            },
+            OptionKind::Number{ range, step, get, set } => {
+                let value = (get(settings) + steps * step).clamp(*range.start(), *range.end());
+                set(settings, value);
+            },
+            OptionKind::Text{ .. } | OptionKind::Submenu(_) => (),
+        }
+    }
+}
+
+/// The options on the page `path` leads to
+fn page_of<'a, T>(options: &'a [MenuOption<T>], path: &[(usize, usize)]) -> &'a [MenuOption<T>] {
+    path.iter().fold(options, |page, (idx, _)| match &page[*idx].kind {
+        OptionKind::Submenu(options) => options,
+        _ => unreachable!("Only submenus are opened"),
+    })
+}
+
+/// What the row under the cursor is
+pub enum Row<'a, T> {
+    Option(&'a MenuOption<T>),
+    /// Start at the top of the menu, or go back from a submenu
+    Button,
+}
+impl<T> Row<'_, T> {
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            Row::Option(option) => Some(option.label),
+            Row::Button => None,
+        }
+    }
+}
+
+/// A tree of options editing `settings`, with a cursor on the current page.  Each page lists its available options
+/// then a button: start at the top, back in a submenu.
+pub struct OptionMenu<T> {
+    pub settings: T,
+    options: Vec<MenuOption<T>>,
+    /// Each submenu opened, as its index in its page and the cursor to go back to
+    path: Vec<(usize, usize)>,
+    /// Row on the current page, counting only available options
+    cursor: usize,
+    /// Text typed so far, while the option under the cursor is being edited
+    editing: Option<String>,
+}
+impl<T> OptionMenu<T> {
+    pub fn new(settings: T, options: Vec<MenuOption<T>>) -> OptionMenu<T> {
+        OptionMenu{ settings, options, path: Vec::new(), cursor: 0, editing: None }
+    }
+
+    /// Available options on the current page, with their indices in it
+    fn visible(&self) -> impl Iterator<Item = (usize, &MenuOption<T>)> {
+        page_of(&self.options, &self.path).iter().enumerate().filter(|(_, option)| option.available)
+    }
+
+    /// Index in the current page of the option under the cursor
+    fn current(&self) -> Option<usize> {
+        self.visible().nth(self.cursor).map(|(idx, _)| idx)
+    }
+
+    /// Available options on the current page
+    pub fn page(&self) -> impl Iterator<Item = &MenuOption<T>> {
+        self.visible().map(|(_, option)| option)
+    }
+
+    /// Every option in every page, for sizing the menu once
+    pub fn all(&self) -> Vec<&MenuOption<T>> {
+        fn collect<'a, T>(options: &'a [MenuOption<T>], all: &mut Vec<&'a MenuOption<T>>) {
+            for option in options {
+                all.push(option);
+                if let OptionKind::Submenu(options) = &option.kind {
+                    collect(options, all);
+                }
+            }
+        }
+        let mut all = Vec::new();
+        collect(&self.options, &mut all);
+        all
+    }
+
+    /// Label of the submenu open, if any
+    pub fn title(&self) -> Option<&'static str> {
+        let (idx, _) = self.path.last()?;
+        Some(page_of(&self.options, &self.path[..self.path.len() - 1])[*idx].label)
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn row(&self) -> Row<'_, T> {
+        match self.visible().nth(self.cursor) {
+            Some((_, option)) => Row::Option(option),
+            None => Row::Button,
+        }
+    }
+
+    /// Moves the cursor to `row` of the current page, or as near as there is.  Stops any editing.
+    pub fn set_cursor(&mut self, row: usize) {
+        self.editing = None;
+        self.cursor = row.min(self.visible().count());
+    }
+
+    pub fn move_cursor(&mut self, rows: i32) {
+        self.set_cursor((self.cursor as i32 + rows).max(0) as usize);
+    }
+
+    /// Changes the choice or number under the cursor
+    pub fn adjust(&mut self, steps: i64) {
+        if let Some(idx) = self.current() {
+            page_of(&self.options, &self.path)[idx].adjust(&mut self.settings, steps);
+        }
+    }
+
+    /// Acts on the row under the cursor: opens a submenu, starts editing text, or presses the page's button.
+    /// Returns whether that started the game.
+    pub fn select(&mut self) -> bool {
+        let idx = self.current();
+        let option = idx.map(|idx| &page_of(&self.options, &self.path)[idx]);
+        match option.map(|option| &option.kind) {
+            Some(OptionKind::Submenu(_)) => {
+                self.path.push((idx.unwrap(), self.cursor));
+                self.cursor = 0;
+                false
+            },
+            Some(OptionKind::Text{ get, .. }) => {
+                self.editing = Some(get(&self.settings));
+                false
+            },
+            _ if self.path.is_empty() => true,
+            _ => self.back(),
+        }
+    }
+
+    /// Stops editing, or leaves the current submenu.  Returns `false` at the top with nothing to stop.
+    pub fn back(&mut self) -> bool {
+        if self.editing.take().is_some() {
+            return true;
+        }
+        match self.path.pop() {
+            Some((_, cursor)) => {
+                self.cursor = cursor;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Text typed so far, while editing
+    pub fn editing(&self) -> Option<&str> {
+        self.editing.as_deref()
+    }
+
+    /// Adds `c` to the text being edited, if there is room
+    pub fn type_char(&mut self, c: char) {
+        let max_len = match self.row() {
+            Row::Option(MenuOption{ kind: OptionKind::Text{ max_len, .. }, .. }) => *max_len,
+            _ => return,
+        };
+        if let Some(text) = self.editing.as_mut().filter(|text| text.chars().count() < max_len) {
+            text.push(c);
+        }
+    }
+
+    pub fn erase(&mut self) {
+        if let Some(text) = self.editing.as_mut() {
+            text.pop();
+        }
+    }
+
+    /// Stores the text being edited
+    pub fn finish_editing(&mut self) {
+        let text = match self.editing.take() {
+            Some(text) => text,
+            None => return,
+        };
+        if let Some(idx) = self.current() {
+            if let OptionKind::Text{ set, .. } = &page_of(&self.options, &self.path)[idx].kind {
+                set(&mut self.settings, text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Settings {
+        player: &'static str,
+        depth: i64,
+        name: String,
+        colour: bool,
+    }
+
+    fn menu() -> OptionMenu<Settings> {
+        let players = vec![("Human".to_string(), "Human"), ("CPU".to_string(), "CPU")];
+        OptionMenu::new(Settings{ player: "CPU", depth: 5, ..Default::default() }, vec![
+            MenuOption::choice("Player", players, |s: &Settings| s.player, |s, player| s.player = player),
+            MenuOption::choice("Colour", vec![("on".to_string(), true), ("off".to_string(), false)],
+                               |s: &Settings| s.colour, |s, colour| s.colour = colour).available(false),
+            MenuOption::submenu("More", vec![
+                MenuOption::number("Depth", 1..=8, 2, |s| s.depth, |s, depth| s.depth = depth),
+                MenuOption::text("Name", 4, |s| s.name.clone(), |s, name| s.name = name),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn test_options() {
+        let mut menu = menu();
+        let labels: Vec<_> = menu.page().map(|option| option.value_label(&menu.settings)).collect();
+        assert_eq!(labels, ["CPU", ""]);
+        menu.adjust(1);
+        assert_eq!(menu.settings.player, "Human");
+        menu.adjust(-3);
+        assert_eq!(menu.settings.player, "CPU");
+
+        // Unavailable options are skipped, and the button comes last
+        menu.move_cursor(1);
+        assert_eq!(menu.row().label(), Some("More"));
+        menu.move_cursor(5);
+        assert!(matches!(menu.row(), Row::Button));
+        menu.set_cursor(1);
+
+        assert!(!menu.select());
+        assert_eq!(menu.title(), Some("More"));
+        menu.adjust(1);
+        assert_eq!(menu.settings.depth, 7);
+        menu.adjust(1);
+        assert_eq!(menu.settings.depth, 8);
+
+        menu.move_cursor(1);
+        menu.select();
+        for c in "Alice".chars() {
+            menu.type_char(c);
+        }
+        menu.erase();
+        assert_eq!(menu.editing(), Some("Ali"));
+        menu.finish_editing();
+        assert_eq!(menu.settings.name, "Ali");
+        menu.select();
+        menu.type_char('!');
+        assert!(menu.back());
+        assert_eq!(menu.settings.name, "Ali");
+
+        assert!(menu.back());
+        assert_eq!((menu.title(), menu.cursor()), (None, 1));
+        assert!(!menu.back());
+        menu.move_cursor(-1);
+        assert!(menu.select());
+    }
+}
@@ -0,0 +1,45 @@
+//! Game settings chosen by a frontend before the game starts.  Shared by every frontend, so no curses here.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Preferences {
+    // pub players: [Player; 2],
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_players"))]
+    pub players: [&'static str; 2],
+}
+
+impl Default for Preferences {
+    fn default() -> Preferences {
+        Preferences {
+            players: ["Human", "CPU"],
+        }
+    }
+}
+
+/// How the curses frontend draws the game: a colour theme and a glyph set for the pieces, each built in or from a
+/// theme file.  Only the frontend cares, so these don't travel with `Preferences` to the game or over the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Appearance {
+    pub theme: String,
+    pub glyphs: String,
+}
+impl Default for Appearance {
+    fn default() -> Appearance {
+        Appearance {
+            theme: "redblack".to_string(),
+            glyphs: "fancy".to_string(),
+        }
+    }
+}
+
+/// Player types are `&'static str`s, so borrow the matching name rather than the deserialiser's buffer.
+#[cfg(feature = "serde")]
+fn deserialize_players<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[&'static str; 2], D::Error> {
+    let names = <[String; 2]>::deserialize(deserializer)?;
+    let player = |name: &str| ["Human", "CPU"].iter().copied().find(|p| *p == name)
+        .ok_or_else(|| serde::de::Error::unknown_variant(name, &["Human", "CPU"]));
+    Ok([player(&names[0])?, player(&names[1])?])
+}
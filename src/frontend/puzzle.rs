@@ -0,0 +1,404 @@
+use crate::backend::notation::{self, GameRecord};
+use crate::backend::{Ai, Board, Move, SearchLimits, Team, Variant};
+use crate::config;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Puzzles to start with when no file is given
+pub const STARTER_SET: &str = include_str!("puzzles.pdn");
+
+/// Positions looked at when checking a line off the puzzle's own wins, so a long puzzle can't hang the frontend
+const MAX_WIN_SEARCH_NODES: u32 = 20_000;
+/// How hard the AI looks for the defender's reply
+const SEARCH_LIMITS: SearchLimits = SearchLimits{ depth: Some(12), time: Some(Duration::from_secs(2)) };
+
+/// A position where the side to move has a winning line.  The line alternates between the solver's turns and the
+/// defender's forced replies, and is played with compulsory captures.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub title: String,
+    pub start: Board,
+    pub solver: Team,
+    pub solution: Vec<Vec<Move>>,
+}
+impl Puzzle {
+    /// The puzzle in a PDN game: its `FEN` tag is the position and its moves the solution.  Titled by its `Event` tag,
+    /// or `number` without one.
+    pub fn from_record(record: GameRecord, number: usize) -> Result<Puzzle, String> {
+        if record.tag("FEN").is_none() {
+            return Err("no FEN tag giving the position".to_string());
+        }
+        if record.turns.is_empty() {
+            return Err("no solution".to_string());
+        }
+        let start = record.start.clone().with_variant(Variant::American);
+        let mut board = start.clone();
+        for (idx, turn) in record.turns.iter().enumerate() {
+            if !board.get_all_valid_turns(record.team(idx)).contains(turn) {
+                return Err(format!("move {} ({}) is not legal when captures are compulsory", idx + 1,
+                                   notation::format_path(turn)));
+            }
+            for mv in turn {
+                board.apply_move(mv);
+            }
+        }
+
+        Ok(Puzzle {
+            title: record.tag("Event").map_or_else(|| format!("Puzzle {}", number), str::to_string),
+            start,
+            solver: record.first_team,
+            solution: record.turns,
+        })
+    }
+
+    /// What the progress file knows it by: the position in FEN
+    pub fn id(&self) -> String {
+        notation::format_fen(&self.start, self.solver)
+    }
+}
+
+/// Reads the puzzles in PDN `text`, one per game.
+pub fn parse_puzzles(text: &str) -> Result<Vec<Puzzle>, String> {
+    notation::parse_pdn_games(text).map_err(|err| err.to_string())?
+        .into_iter().enumerate()
+        .map(|(idx, record)| Puzzle::from_record(record, idx + 1).map_err(|err| format!("Puzzle {}: {}", idx + 1, err)))
+        .collect()
+}
+
+/// What happened after the solver moved a piece
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// The piece that just jumped has to jump again, with one of these
+    Jumping(Vec<Move>),
+    /// Still winning, and the defender replies with this turn
+    Reply(Vec<Move>),
+    Solved,
+    /// That lets the win slip, where this turn would have kept it
+    Wrong(Vec<Move>),
+    /// The attempt was already over, so the move wasn't played
+    Over,
+}
+
+/// One go at a puzzle, from its start until it's solved or the solver goes wrong.  The defender's replies come from the
+/// AI, and any line winning as soon as the puzzle's own counts.
+pub struct Attempt {
+    pub puzzle: Puzzle,
+    pub board: Board,
+    /// The position before the solver's turn in progress
+    turn_start: Board,
+    /// Turns played, by either side
+    turns: Vec<Vec<Move>>,
+    /// Whether every turn so far is the puzzle's solution, so it can still give the next one
+    on_script: bool,
+    /// The solver's hops so far this turn
+    hops: Vec<Move>,
+    /// Whether a hint was taken, which stops this attempt counting
+    pub hinted: bool,
+    pub finished: bool,
+}
+impl Attempt {
+    pub fn new(puzzle: Puzzle) -> Attempt {
+        Attempt {
+            board: puzzle.start.clone(),
+            turn_start: puzzle.start.clone(),
+            puzzle,
+            turns: Vec::new(),
+            on_script: true,
+            hops: Vec::new(),
+            hinted: false,
+            finished: false,
+        }
+    }
+
+    /// Plays one hop of the solver's turn, and the defender's reply if that finishes the turn.
+    pub fn play(&mut self, mv: Move) -> Step {
+        if self.finished {
+            return Step::Over;
+        }
+        let crowned = self.board.apply_move(&mv);
+        self.hops.push(mv);
+        if mv.is_jump() {
+            let jumps = self.board.get_continuing_jumps(&mv.to, crowned);
+            if !jumps.is_empty() {
+                return Step::Jumping(jumps);
+            }
+        }
+
+        let turn = std::mem::take(&mut self.hops);
+        // The puzzle's turn here, while it's being followed
+        let scripted = self.scripted(self.turns.len()).filter(|_| self.on_script);
+        self.on_script = scripted.as_ref() == Some(&turn);
+        self.turns.push(turn);
+        if self.on_script && self.turns.len() == self.puzzle.solution.len() {
+            self.finished = true;
+            return Step::Solved;
+        }
+
+        // The puzzle's own line is known to win, anything else has to be checked
+        let defender = self.puzzle.solver.other();
+        if !self.on_script && !WinSearch::new().defender_lost(&self.board, defender, self.solver_turns_left()) {
+            self.finished = true;
+            let winning = scripted.or_else(|| {
+                WinSearch::new().winning_turn(&self.turn_start, self.puzzle.solver, self.solver_turns_left() + 1)
+            });
+            return Step::Wrong(winning.unwrap_or_default());
+        }
+        let search = Ai{ team: defender }.search(self.board.clone(), SEARCH_LIMITS, None, |_| ());
+        let reply = match search {
+            Some(info) => info.moves,
+            None => {
+                self.finished = true;
+                return Step::Solved;
+            },
+        };
+
+        // The puzzle's defence is only forced if the AI agrees
+        self.on_script &= self.scripted(self.turns.len()).as_ref() == Some(&reply);
+        for mv in &reply {
+            self.board.apply_move(mv);
+        }
+        self.turns.push(reply.clone());
+        self.turn_start = self.board.clone();
+        if self.on_script && self.turns.len() == self.puzzle.solution.len() {
+            self.finished = true;
+        }
+        Step::Reply(reply)
+    }
+
+    /// The solver's next turn: the puzzle's while it's being followed, otherwise one that still wins.  Taking it means the
+    /// attempt doesn't count.
+    pub fn hint(&mut self) -> Option<Vec<Move>> {
+        if self.finished {
+            return None;
+        }
+        self.hinted = true;
+        if self.on_script {
+            self.scripted(self.turns.len())
+        } else {
+            WinSearch::new().winning_turn(&self.turn_start, self.puzzle.solver, self.solver_turns_left())
+        }
+    }
+
+    /// Turns played so far
+    pub fn played(&self) -> &[Vec<Move>] {
+        &self.turns
+    }
+
+    /// Whether the solver is partway through a multi-jump
+    pub fn jumping_from(&self) -> Option<Move> {
+        self.hops.last().copied()
+    }
+
+    /// The solver's turns left in the puzzle's own line after those played.  Other lines have to win as soon.
+    fn solver_turns_left(&self) -> usize {
+        let solver_turns = |turns: usize| turns.div_ceil(2);
+        solver_turns(self.puzzle.solution.len()).saturating_sub(solver_turns(self.turns.len()))
+    }
+
+    fn scripted(&self, idx: usize) -> Option<Vec<Move>> {
+        self.puzzle.solution.get(idx).cloned()
+    }
+}
+
+/// Tries every turn looking for forced wins, giving up once it has looked at `MAX_WIN_SEARCH_NODES` positions.  Giving
+/// up counts as not finding one.
+struct WinSearch {
+    nodes_left: u32,
+}
+impl WinSearch {
+    fn new() -> WinSearch {
+        WinSearch{ nodes_left: MAX_WIN_SEARCH_NODES }
+    }
+
+    /// A turn for `solver` on `board` that wins within `turns` of its turns however the defender replies, if there is
+    /// one.  The defender loses when left without a move.
+    fn winning_turn(&mut self, board: &Board, solver: Team, turns: usize) -> Option<Vec<Move>> {
+        if turns == 0 || !self.visit() {
+            return None;
+        }
+        board.get_all_valid_turns(solver).into_iter()
+            .find(|turn| self.defender_lost(&after(board, turn), solver.other(), turns - 1))
+    }
+
+    /// Whether `defender`, to move on `board`, loses to every reply within `turns` of the solver's turns
+    fn defender_lost(&mut self, board: &Board, defender: Team, turns: usize) -> bool {
+        self.visit() && board.get_all_valid_turns(defender).iter()
+            .all(|turn| self.winning_turn(&after(board, turn), defender.other(), turns).is_some())
+    }
+
+    fn visit(&mut self) -> bool {
+        self.nodes_left = self.nodes_left.saturating_sub(1);
+        self.nodes_left > 0
+    }
+}
+
+fn after(board: &Board, turn: &[Move]) -> Board {
+    let mut board = board.clone();
+    for mv in turn {
+        board.apply_move(mv);
+    }
+    board
+}
+
+/// Puzzles solved and the current and best runs of them without a miss, kept in the progress file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// Ids of solved puzzles
+    pub solved: Vec<String>,
+    pub streak: u32,
+    pub best_streak: u32,
+}
+impl Progress {
+    /// Reads the file at `path`; a missing file is no progress yet.
+    pub fn load(path: &Path) -> io::Result<Progress> {
+        match fs::read_to_string(path) {
+            Ok(text) => Progress::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Progress::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Progress, String> {
+        let mut progress = Progress::default();
+        let count = |value: &str| value.parse().map_err(|_| format!("'{}' is not a count", value));
+        config::parse_lines(text, |key, value| {
+            match key {
+                "solved"      => progress.solved.push(value.to_string()),
+                "streak"      => progress.streak = count(value)?,
+                "best-streak" => progress.best_streak = count(value)?,
+                _ => return Err(format!("unknown setting '{}'", key)),
+            }
+            Ok(())
+        })?;
+        Ok(progress)
+    }
+
+    pub fn is_solved(&self, puzzle: &Puzzle) -> bool {
+        self.solved.contains(&puzzle.id())
+    }
+
+    /// Counts a solve, or a miss which ends the streak
+    pub fn record(&mut self, puzzle: &Puzzle, solved: bool) {
+        if !solved {
+            self.streak = 0;
+            return;
+        }
+        if !self.is_solved(puzzle) {
+            self.solved.push(puzzle.id());
+        }
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+    }
+}
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Checkers puzzles solved, rewritten after each puzzle")?;
+        writeln!(f, "streak = {}", self.streak)?;
+        writeln!(f, "best-streak = {}", self.best_streak)?;
+        for id in &self.solved {
+            writeln!(f, "solved = {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starter_set() {
+        let puzzles = parse_puzzles(STARTER_SET).unwrap();
+        assert!(puzzles.len() >= 10);
+        for puzzle in &puzzles {
+            // Every reply is forced, and the defender is left without a move
+            let mut board = puzzle.start.clone();
+            for (idx, turn) in puzzle.solution.iter().enumerate() {
+                if idx % 2 == 1 {
+                    assert_eq!(board.get_all_valid_turns(puzzle.solver.other()).len(), 1, "{}", puzzle.title);
+                }
+                for mv in turn {
+                    board.apply_move(mv);
+                }
+            }
+            assert!(board.get_all_valid_turns(puzzle.solver.other()).is_empty(), "{}", puzzle.title);
+
+            // And the AI defends the way the puzzle says
+            let mut attempt = Attempt::new(puzzle.clone());
+            let mut step = Step::Over;
+            for turn in puzzle.solution.iter().step_by(2) {
+                for mv in turn {
+                    step = attempt.play(*mv);
+                }
+            }
+            assert!(attempt.finished && step != Step::Over && !matches!(step, Step::Wrong(_)), "{}", puzzle.title);
+            assert_eq!(attempt.played(), &puzzle.solution[..], "{}", puzzle.title);
+        }
+    }
+
+    #[test]
+    fn test_attempt() {
+        let puzzles = parse_puzzles("[Event \"Double\"] [FEN \"W:W5,20,K27,31:B17,19\"] 27-23 19x26 31x22x13 *").unwrap();
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.title, "Double");
+        let mv = |from, to| Move{ from: notation::number_square(from).unwrap(), to: notation::number_square(to).unwrap() };
+
+        let mut attempt = Attempt::new(puzzle.clone());
+        assert_eq!(attempt.play(mv(27, 23)), Step::Reply(vec![mv(19, 26)]));
+        assert_eq!(attempt.played().len(), 2);
+        assert_eq!(attempt.play(mv(31, 22)), Step::Jumping(vec![mv(22, 13)]));
+        assert_eq!(attempt.jumping_from(), Some(mv(31, 22)));
+        assert_eq!(attempt.play(mv(22, 13)), Step::Solved);
+        assert!(attempt.finished && !attempt.hinted);
+
+        assert_eq!(attempt.play(mv(13, 9)), Step::Over);
+
+        let mut attempt = Attempt::new(puzzle.clone());
+        assert_eq!(attempt.hint(), Some(vec![mv(27, 23)]));
+        assert_eq!(attempt.play(mv(27, 24)), Step::Wrong(vec![mv(27, 23)]));
+        assert!(attempt.finished && attempt.hinted);
+        assert_eq!(attempt.hint(), None);
+
+        // Another capture wins just as well
+        let puzzles = parse_puzzles("[FEN \"W:W22,23:B18\"] 22x15 *").unwrap();
+        let mut attempt = Attempt::new(puzzles[0].clone());
+        assert_eq!(attempt.play(mv(23, 14)), Step::Solved);
+        assert_eq!(attempt.played(), [vec![mv(23, 14)]]);
+
+        // Checking other lines gives up rather than searching without end
+        let start = &puzzles[0].start;
+        assert!(WinSearch::new().winning_turn(start, Team::Light, 1).is_some());
+        assert!(WinSearch{ nodes_left: 1 }.winning_turn(start, Team::Light, 1).is_none());
+        let (busy, _) = notation::parse_fen("W:W21,22,23,24,25,26,27,28:B5,6,7,8,9,10,11,12").unwrap();
+        assert!(WinSearch::new().winning_turn(&busy.with_variant(Variant::American), Team::Light, 10).is_none());
+
+        assert!(parse_puzzles("22-18 *").unwrap_err().contains("FEN"));
+        assert!(parse_puzzles("[FEN \"W:W22:B18\"] 22-17 *").unwrap_err().contains("compulsory"));
+    }
+
+    #[test]
+    fn test_progress() {
+        let puzzles = parse_puzzles("[FEN \"W:W5,20,K27,31:B17,19\"] 27-23 *\n[FEN \"W:W22:B18\"] 22x15 *").unwrap();
+        let mut progress = Progress::default();
+        progress.record(&puzzles[0], true);
+        progress.record(&puzzles[0], true);
+        progress.record(&puzzles[1], false);
+        assert_eq!(progress.solved, [puzzles[0].id()]);
+        assert_eq!((progress.streak, progress.best_streak), (0, 2));
+        assert!(progress.is_solved(&puzzles[0]) && !progress.is_solved(&puzzles[1]));
+
+        assert_eq!(Progress::parse(&progress.to_string()), Ok(progress));
+        assert!(Progress::parse("streak = lots").is_err());
+    }
+}
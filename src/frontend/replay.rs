@@ -0,0 +1,145 @@
+use super::cursor_input::CursorInput;
+
+use crate::backend::notation::GameRecord;
+
+use std::time::{Duration, Instant};
+
+use pancurses::Input;
+
+/// Time between moves when playing a replay, slowest first
+const SPEEDS: [Duration; 6] = [
+    Duration::from_secs(4),
+    Duration::from_secs(2),
+    Duration::from_secs(1),
+    Duration::from_millis(500),
+    Duration::from_millis(250),
+    Duration::from_millis(100),
+];
+const DEFAULT_SPEED: usize = 2;
+
+/// Where a replay is up to.  Left and right step through the game, up and down change the speed and selecting plays
+/// or pauses.
+pub struct Replay {
+    pub record: GameRecord,
+    /// Turns played so far
+    pub position: usize,
+    /// When the last move was played, while playing
+    playing: Option<Instant>,
+    speed: usize,
+}
+impl Replay {
+    pub fn new(record: GameRecord) -> Replay {
+        Replay {
+            record,
+            position: 0,
+            playing: None,
+            speed: DEFAULT_SPEED,
+        }
+    }
+
+    /// Goes to just after turn `position`, stopping at either end of the game
+    pub fn go_to(&mut self, position: usize) {
+        self.position = position.min(self.record.turns.len());
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Starts playing from here, or from the start if the game is over.  Pauses if already playing.
+    pub fn toggle_playing(&mut self, now: Instant) {
+        self.playing = match self.playing {
+            Some(_) => None,
+            None => {
+                if self.position == self.record.turns.len() {
+                    self.position = 0;
+                }
+                Some(now)
+            },
+        };
+    }
+
+    /// Plays the next move if it's time.  Returns whether it did.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        match self.playing {
+            Some(last) if now.duration_since(last) >= SPEEDS[self.speed] => {
+                self.go_to(self.position + 1);
+                self.playing = Some(now).filter(|_| self.position < self.record.turns.len());
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn move_delay(&self) -> Duration {
+        SPEEDS[self.speed]
+    }
+
+    /// Where things stand, for the log
+    pub fn status(&self) -> String {
+        let state = if self.is_playing() { "playing" } else { "paused" };
+        format!("Move {} of {}, {} at {:.1}s a move", self.position, self.record.turns.len(), state,
+                self.move_delay().as_secs_f64())
+    }
+}
+impl CursorInput for Replay {
+    type Action = ();
+
+    /// Stepping by hand pauses the replay
+    fn move_cursor(&mut self, dir: Input) {
+        match dir {
+            Input::KeyLeft => {
+                self.playing = None;
+                self.go_to(self.position.saturating_sub(1));
+            },
+            Input::KeyRight => {
+                self.playing = None;
+                self.go_to(self.position + 1);
+            },
+            Input::KeyUp => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            Input::KeyDown => self.speed = self.speed.saturating_sub(1),
+            _ => panic!("Bad dir passed to move_cursor: {:?}", dir),
+        }
+    }
+
+    fn do_action(&mut self) -> Option<()> {
+        self.toggle_playing(Instant::now());
+        None
+    }
+
+    fn click(&mut self, _y: i32, _x: i32) -> Option<()> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::notation;
+
+    #[test]
+    fn test_replay() {
+        let mut replay = Replay::new(notation::parse_pdn("22-18 9-14 18x9").unwrap());
+        replay.move_cursor(Input::KeyLeft);
+        assert_eq!(replay.position, 0);
+        replay.go_to(10);
+        assert_eq!(replay.position, 3);
+
+        // Playing from the end starts again
+        let start = Instant::now();
+        replay.toggle_playing(start);
+        assert_eq!(replay.position, 0);
+        assert!(!replay.tick(start + Duration::from_millis(10)));
+        replay.move_cursor(Input::KeyUp);
+        assert_eq!(replay.move_delay(), Duration::from_millis(500));
+        assert!(replay.tick(start + Duration::from_secs(1)));
+        assert_eq!(replay.position, 1);
+        replay.go_to(2);
+        assert!(replay.tick(start + Duration::from_secs(2)));
+        assert!(!replay.is_playing());
+
+        replay.toggle_playing(start);
+        replay.move_cursor(Input::KeyRight);
+        assert!(!replay.is_playing());
+    }
+}
@@ -0,0 +1,505 @@
+use super::{Frontend, FrontendError, UserAction};
+use super::controller::{MoveController, Selection};
+use super::glyphs::GlyphSet;
+use super::log_file::LogFile;
+use super::preferences::Preferences;
+
+use crate::args::{Args, Mode};
+use crate::backend::{clock, notation, Board, Clock, Move, Square, Team};
+use crate::channel::{BackToFrontMessage, FrontToBackMessage, LogLevel};
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+const HELP: &str = "\
+Enter moves in standard notation, e.g. 11-15 or 15x24x31 (squares are numbered 1-32 as shown beside the board)
+Commands:
+  help     Show this message
+  moves    List your legal moves
+  clock    Show the time left, in timed games
+  undo     Take back your last move
+  resign   Give up the game
+  draw     Offer a draw
+  accept   Accept a draw offer
+  decline  Decline a draw offer
+  quit     Exit";
+
+/// Line-based frontend on stdin/stdout, for dumb terminals, scripts and screen readers.
+pub struct TextFrontend {
+    input: mpsc::Receiver<String>,
+    board: Board,
+    board_changed: bool,
+    /// Always ASCII, which any terminal or screen reader can show
+    glyphs: GlyphSet,
+    controller: MoveController,
+    pending_squares: VecDeque<Square>,
+    outgoing: VecDeque<UserAction>,
+    /// Only watching someone else's game, so there is nothing to ask the user
+    watching: bool,
+    clock: Option<Clock>,
+    /// Team whose draw offer we owe an answer to
+    draw_offer: Option<Team>,
+    /// Players offered by default, and the whole setup when skipping the questions
+    defaults: Preferences,
+    skip_menu: bool,
+    /// Most detailed log level printed
+    verbosity: LogLevel,
+    log_file: Option<LogFile>,
+}
+impl TextFrontend {
+    pub fn new(args: &Args) -> TextFrontend {
+        // Read stdin on its own thread so polling for input never blocks the message loop
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => if tx.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+        TextFrontend::with_input(args, rx)
+    }
+
+    /// Reads lines from `input` instead of stdin
+    fn with_input(args: &Args, input: mpsc::Receiver<String>) -> TextFrontend {
+        TextFrontend {
+            input,
+            board: Board::new(),
+            board_changed: false,
+            glyphs: GlyphSet::ascii(),
+            controller: MoveController::new(),
+            pending_squares: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            watching: matches!(args.mode, Mode::Watch(_)),
+            clock: None,
+            draw_offer: None,
+            defaults: args.preferences,
+            skip_menu: args.skip_menu,
+            verbosity: if args.debug { LogLevel::Debug } else { LogLevel::Info },
+            log_file: args.log_file.as_deref().and_then(|path| match LogFile::create(path) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    eprintln!("Could not open the log file: {}", err);
+                    None
+                },
+            }),
+        }
+    }
+
+    fn prompt(&self, text: &str) -> Option<String> {
+        print!("{}", text);
+        io::stdout().flush().ok();
+        self.input.recv().ok()
+    }
+
+    fn ask_player(&self, description: &str, default: &'static str) -> Option<&'static str> {
+        loop {
+            let answer = self.prompt(&format!("{} [Human/CPU] (default {}): ", description, default))?;
+            match answer.trim().to_lowercase().as_str() {
+                ""               => return Some(default),
+                "human" | "h"    => return Some("Human"),
+                "cpu" | "c"      => return Some("CPU"),
+                _                => println!("Please answer Human or CPU"),
+            }
+        }
+    }
+
+    fn print_board(&mut self) {
+        println!();
+        for y in 0..Board::SIZE {
+            let mut row = String::new();
+            let mut key = String::new();
+            for x in 0..Board::SIZE {
+                let square = Square{ x, y };
+                match notation::square_number(&square) {
+                    Some(number) => {
+                        let glyph = match self.board.get_piece_at(&square) {
+                            Some(piece) => self.glyphs.glyph(Some(piece)),
+                            None => '.',
+                        };
+                        row.push_str(&format!(" {} ", glyph));
+                        key.push_str(&format!("{:>2} ", number));
+                    },
+                    None => {
+                        row.push_str("   ");
+                        key.push_str("   ");
+                    },
+                }
+            }
+            println!("{}    {}", row, key);
+        }
+        println!("{}", self.legend());
+        println!();
+        self.board_changed = false;
+    }
+
+    fn legend(&self) -> String {
+        let [light_man, light_king, dark_man, dark_king] = self.glyphs.pieces;
+        format!("Light: {} (man) {} (king)   Dark: {} (man) {} (king)", light_man, light_king, dark_man, dark_king)
+    }
+
+    fn print_moves(&self) {
+        let moves = if self.controller.is_jumping() {
+            self.controller.valid_moves().iter().map(|mv| notation::format_path(&[*mv])).collect::<Vec<_>>()
+        } else {
+            match self.controller.active_team() {
+                Some(team) => self.board.get_all_valid_turns(team).iter().map(|turn| notation::format_path(turn)).collect(),
+                None => Vec::new(),
+            }
+        };
+        if moves.is_empty() {
+            println!("No moves available");
+        } else {
+            println!("Legal moves: {}", moves.join(", "));
+        }
+    }
+
+    fn request_jump(&self, square: &Square) {
+        println!("Keep jumping from {} (e.g. {}), or type 'stop'",
+                 notation::square_number(square).unwrap_or(0),
+                 notation::format_path(&self.controller.valid_moves()[..1]));
+    }
+
+    fn print_clock(&self, clock: &Clock) {
+        let face = |team| {
+            let marker = if clock.running() == Some(team) { "*" } else { "" };
+            format!("{:?} {}{}", team, clock::format_time(clock.remaining(team)), marker)
+        };
+        println!("Clock: {}  {}", face(Team::Light), face(Team::Dark));
+    }
+
+    fn handle_line(&mut self, line: &str) -> Option<UserAction> {
+        let team = self.controller.active_team();
+        match line.trim().to_lowercase().as_str() {
+            "" => None,
+            "help" => {
+                println!("{}", HELP);
+                None
+            },
+            "moves" => {
+                self.print_moves();
+                None
+            },
+            "clock" => {
+                match &self.clock {
+                    Some(clock) => self.print_clock(clock),
+                    None => println!("This game isn't timed"),
+                }
+                None
+            },
+            "quit" | "exit" => Some(UserAction::Quit),
+            "accept" | "decline" => match self.draw_offer.take() {
+                Some(_) => Some(UserAction::Send(FrontToBackMessage::AnswerDraw(line.trim().eq_ignore_ascii_case("accept")))),
+                None => {
+                    println!("No one has offered a draw");
+                    None
+                },
+            },
+            "undo" | "resign" | "draw" if team.is_none() => {
+                println!("Wait for your turn");
+                None
+            },
+            "undo" => {
+                self.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::Undo))
+            },
+            "resign" => {
+                self.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::Resign))
+            },
+            "draw" if self.controller.is_jumping() => {
+                println!("Finish your jump first");
+                None
+            },
+            "draw" => {
+                self.controller.stop();
+                Some(UserAction::Send(FrontToBackMessage::OfferDraw))
+            },
+            "stop" if self.controller.is_jumping() => {
+                let square = self.controller.selected_piece().unwrap();
+                self.select(square)
+            },
+            text if team.is_some() => match notation::parse_path(text) {
+                Ok(squares) => self.start_path(squares),
+                Err(err) => {
+                    println!("{} (type 'help' for commands)", err);
+                    None
+                },
+            },
+            _ => {
+                println!("Wait for your turn (type 'help' for commands)");
+                None
+            },
+        }
+    }
+
+    fn start_path(&mut self, squares: Vec<Square>) -> Option<UserAction> {
+        let mut squares = VecDeque::from(squares);
+        let from = squares.pop_front().unwrap();
+        if self.controller.is_jumping() {
+            if Some(from) != self.controller.selected_piece() {
+                println!("You must keep jumping with the piece on {}", notation::square_number(&self.controller.selected_piece().unwrap()).unwrap_or(0));
+                return None;
+            }
+        } else {
+            match self.controller.select(&self.board, from) {
+                Some(Selection::PieceSelected(_)) => (),
+                _ => {
+                    println!("You have no piece that can move on square {}", notation::square_number(&from).unwrap_or(0));
+                    return None;
+                },
+            }
+        }
+
+        let to = squares.pop_front().unwrap();
+        self.pending_squares = squares;
+        self.select(to)
+    }
+
+    fn select(&mut self, square: Square) -> Option<UserAction> {
+        let selection = self.controller.select(&self.board, square)?;
+        match &selection {
+            Selection::IllegalMove(mv) => {
+                self.pending_squares.clear();
+                println!("Illegal move {}", notation::format_path(&[*mv]));
+                if !self.controller.is_jumping() {
+                    self.controller.select(&self.board, mv.from); // Back to choosing a piece
+                }
+            },
+            Selection::JumpCanceled => println!("Jump stopped"),
+            _ => (),
+        };
+
+        selection.message().map(UserAction::Send)
+    }
+
+    fn continue_jumping(&mut self, team: Team, square: Square, valid_moves: Vec<Move>) {
+        self.controller.continue_jumping(team, square, valid_moves);
+        match self.pending_squares.pop_front() {
+            Some(next) => {
+                if let Some(action) = self.select(next) {
+                    self.outgoing.push_back(action);
+                }
+            },
+            None => self.request_jump(&square),
+        }
+    }
+}
+impl Frontend for TextFrontend {
+    fn get_preferences(&mut self) -> Result<Option<Preferences>, FrontendError> {
+        if self.watching {
+            return Ok(Some(Preferences{ players: ["Human", "Human"] }));
+        }
+
+        if self.skip_menu {
+            return Ok(Some(self.defaults));
+        }
+
+        let light = match self.ask_player("Player 1 (light)", self.defaults.players[0]) {
+            Some(player) => player,
+            None => return Ok(None),
+        };
+        let dark = match self.ask_player("Player 2 (dark)", self.defaults.players[1]) {
+            Some(player) => player,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Preferences{ players: [light, dark] }))
+    }
+
+    fn start_game(&mut self, _preferences: Preferences) -> Result<(), FrontendError> {
+        if self.watching {
+            println!("Watching the game, type 'quit' to stop");
+            return Ok(());
+        }
+        println!("Type 'help' for a list of commands");
+        Ok(())
+    }
+
+    fn handle_message(&mut self, msg: BackToFrontMessage) {
+        match msg {
+            BackToFrontMessage::Log{ level, msg } => {
+                if let Some(file) = self.log_file.as_mut() {
+                    file.write(level, &msg);
+                }
+                match level {
+                    LogLevel::Info => println!("{}", msg),
+                    level if level <= self.verbosity => println!("[{}] {}", level, msg),
+                    _ => (),
+                }
+            },
+            BackToFrontMessage::BoardState(state) => {
+                self.board = state;
+                self.board_changed = true;
+            },
+            BackToFrontMessage::RequestMove(team) => {
+                if self.board_changed {
+                    self.print_board();
+                }
+                self.controller.start_selecting_piece(team);
+                println!("{:?} to move", team);
+            },
+            BackToFrontMessage::RequestJump(team, square, valid_moves) => self.continue_jumping(team, square, valid_moves),
+            BackToFrontMessage::Clock(state) => {
+                let clock = Clock::resume(&state);
+                if clock.running().is_some() {
+                    self.print_clock(&clock);
+                }
+                self.clock = Some(clock);
+            },
+            BackToFrontMessage::DrawOffered(team) => {
+                self.draw_offer = Some(team);
+                println!("{:?} offers a draw, type 'accept' or 'decline'", team);
+            },
+            BackToFrontMessage::GameOver(_) => {
+                self.controller.stop();
+                self.draw_offer = None;
+            },
+            BackToFrontMessage::MoveMade(team, moves) => {
+                if !self.pending_squares.is_empty() {
+                    println!("Ignoring the rest of the move, your turn is over");
+                    self.pending_squares.clear();
+                }
+                println!("{:?} played {}", team, notation::format_path(&moves));
+                if self.board_changed {
+                    self.print_board();
+                }
+            },
+        };
+    }
+
+    fn poll_input(&mut self) -> Option<UserAction> {
+        if let Some(action) = self.outgoing.pop_front() {
+            return Some(action);
+        }
+        match self.input.try_recv() {
+            Ok(line) => self.handle_line(&line),
+            Err(TryRecvError::Empty) => None,
+            // Out of input; only give up once we actually need a move, so piped CPU games can play out
+            Err(TryRecvError::Disconnected) if self.controller.active_team().is_some() || self.draw_offer.is_some() => {
+                Some(UserAction::Quit)
+            },
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    fn draw(&mut self) {
+        // Players see the board when asked to move, watchers whenever it changes
+        if self.watching && self.board_changed {
+            self.print_board();
+        }
+        io::stdout().flush().ok();
+    }
+
+    fn backend_disconnected(&mut self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{EndReason, GameResult};
+
+    fn make_frontend(mode: Mode) -> (TextFrontend, mpsc::Sender<String>) {
+        let args = Args{ mode, ..Default::default() };
+        let (tx, rx) = mpsc::channel();
+        (TextFrontend::with_input(&args, rx), tx)
+    }
+
+    fn square(number: u8) -> Square {
+        notation::number_square(number).unwrap()
+    }
+
+    fn sent_move(action: Option<UserAction>) -> Option<Move> {
+        match action {
+            Some(UserAction::Send(FrontToBackMessage::Move(mv))) => Some(mv),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_moves() {
+        let (mut frontend, _input) = make_frontend(Mode::Play);
+        assert!(frontend.handle_line("22-18").is_none()); // Not our turn yet
+        assert!(frontend.handle_line("resign").is_none());
+
+        frontend.handle_message(BackToFrontMessage::RequestMove(Team::Light));
+        assert!(frontend.handle_line("help").is_none());
+        assert!(frontend.handle_line("22-").is_none());
+        assert!(frontend.handle_line("9-13").is_none()); // Not our piece
+        assert!(frontend.handle_line("22-17x").is_none());
+        assert!(frontend.handle_line("22-15").is_none()); // Illegal, and back to choosing a piece
+        assert_eq!(frontend.controller.selected_piece(), None);
+        assert_eq!(sent_move(frontend.handle_line(" 22-18 ")), Some(Move{ from: square(22), to: square(18) }));
+        assert_eq!(frontend.controller.active_team(), None);
+
+        frontend.handle_message(BackToFrontMessage::RequestMove(Team::Light));
+        assert!(matches!(frontend.handle_line("DRAW"), Some(UserAction::Send(FrontToBackMessage::OfferDraw))));
+        frontend.handle_message(BackToFrontMessage::RequestMove(Team::Light));
+        assert!(matches!(frontend.handle_line("undo"), Some(UserAction::Send(FrontToBackMessage::Undo))));
+        assert!(matches!(frontend.handle_line("quit"), Some(UserAction::Quit)));
+    }
+
+    #[test]
+    fn test_multi_jump() {
+        let (mut frontend, input) = make_frontend(Mode::Play);
+        let (mut board, _) = notation::parse_fen("W:W22:B11,18").unwrap();
+        frontend.handle_message(BackToFrontMessage::BoardState(board.clone()));
+        frontend.handle_message(BackToFrontMessage::RequestMove(Team::Light));
+        let first = Move{ from: square(22), to: square(15) };
+        assert_eq!(sent_move(frontend.handle_line("22x15x8")), Some(first));
+
+        // The rest of the path answers the request to keep jumping
+        board.apply_move(&first);
+        let second = Move{ from: square(15), to: square(8) };
+        frontend.handle_message(BackToFrontMessage::BoardState(board));
+        frontend.handle_message(BackToFrontMessage::RequestJump(Team::Light, square(15), vec![second]));
+        assert_eq!(sent_move(frontend.poll_input()), Some(second));
+        assert!(frontend.poll_input().is_none());
+
+        // Running out of input only quits once a move is wanted
+        drop(input);
+        assert!(frontend.poll_input().is_none());
+        frontend.handle_message(BackToFrontMessage::RequestMove(Team::Light));
+        assert!(matches!(frontend.poll_input(), Some(UserAction::Quit)));
+    }
+
+    #[test]
+    fn test_draw_offer() {
+        let (mut frontend, input) = make_frontend(Mode::Play);
+        assert!(frontend.handle_line("accept").is_none()); // Nothing offered
+        frontend.handle_message(BackToFrontMessage::DrawOffered(Team::Dark));
+        input.send("Accept".to_string()).unwrap();
+        assert!(matches!(frontend.poll_input(), Some(UserAction::Send(FrontToBackMessage::AnswerDraw(true)))));
+        assert!(frontend.handle_line("decline").is_none()); // Already answered
+
+        frontend.handle_message(BackToFrontMessage::DrawOffered(Team::Dark));
+        assert!(matches!(frontend.handle_line("decline"), Some(UserAction::Send(FrontToBackMessage::AnswerDraw(false)))));
+
+        // The game ending drops an offer still open
+        frontend.handle_message(BackToFrontMessage::DrawOffered(Team::Dark));
+        frontend.handle_message(BackToFrontMessage::GameOver(GameResult{ winner: None, reason: EndReason::DrawAgreed }));
+        assert!(frontend.handle_line("accept").is_none());
+    }
+
+    #[test]
+    fn test_watching() {
+        let (mut frontend, _input) = make_frontend(Mode::Watch("localhost".to_string()));
+        let preferences = frontend.get_preferences().unwrap().unwrap();
+        assert_eq!(preferences.players, ["Human", "Human"]);
+
+        assert_eq!(frontend.legend(), "Light: O (man) @ (king)   Dark: = (man) # (king)");
+        frontend.handle_message(BackToFrontMessage::BoardState(Board::new()));
+        assert!(frontend.board_changed);
+        frontend.draw();
+        assert!(!frontend.board_changed);
+        frontend.handle_message(BackToFrontMessage::MoveMade(Team::Light, vec![Move{ from: square(22), to: square(18) }]));
+        assert!(frontend.handle_line("22-18").is_none());
+        assert!(frontend.handle_line("resign").is_none());
+        assert!(matches!(frontend.handle_line("quit"), Some(UserAction::Quit)));
+    }
+}
@@ -0,0 +1,367 @@
+//! Colour themes and glyph sets for the curses frontend.
+//!
+//! Besides the built-in ones, each `*.theme` file in the themes directory adds a theme, a glyph set or both, named
+//! after the file.  They use the config file's `key = value` format:
+//!
+//! ```text
+//! base = redblack         # theme to start from
+//! light-square = 223      # colour names, bright-<name>, 0-255 or default
+//! dark-square = 94
+//! light-piece = bright-white
+//! dark-piece = 16
+//! cursor = yellow         # these four can be none
+//! destination = 22
+//! capture = none
+//! last-move = 58
+//! log = default           # info, AI and debug lines
+//! log-ai = 109
+//! log-debug = 244
+//! glyph.light-man = o     # any glyph makes a glyph set too, starting from fancy
+//! glyph.light-king = O
+//! glyph.dark-man = x
+//! glyph.dark-king = X
+//! glyph.destination = +
+//! ```
+//!
+//! Colours past what the terminal supports are shown as the nearest of the eight basic colours.
+
+extern crate pancurses;
+
+use super::glyphs::GlyphSet;
+
+use crate::channel::LogLevel;
+use crate::config;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const COLOR_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// A terminal colour: the terminal's default, or a colour number as in xterm's 256 colours (0-7 are the basic
+/// colours and 8-15 their bright versions).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThemeColor {
+    Default,
+    Index(u8),
+}
+impl ThemeColor {
+    /// Colour number for curses on a terminal with `colors` colours
+    fn curses_color(self, colors: i32) -> i16 {
+        match self {
+            ThemeColor::Default => -1,
+            ThemeColor::Index(n) if (n as i32) < colors => n as i16,
+            ThemeColor::Index(n) => basic_color(n) as i16,
+        }
+    }
+}
+impl fmt::Display for ThemeColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThemeColor::Default => write!(f, "default"),
+            ThemeColor::Index(n) if n < 8 => write!(f, "{}", COLOR_NAMES[n as usize]),
+            ThemeColor::Index(n) if n < 16 => write!(f, "bright-{}", COLOR_NAMES[n as usize - 8]),
+            ThemeColor::Index(n) => write!(f, "{}", n),
+        }
+    }
+}
+impl FromStr for ThemeColor {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ThemeColor, String> {
+        let text = text.to_lowercase();
+        let named = |name: &str| COLOR_NAMES.iter().position(|color| *color == name).map(|n| n as u8);
+        let index = match text.strip_prefix("bright-") {
+            _ if text == "default" => return Ok(ThemeColor::Default),
+            Some(name) => named(name).map(|n| n + 8),
+            None => named(&text).or_else(|| text.parse().ok()),
+        };
+        index.map(ThemeColor::Index).ok_or_else(|| format!("'{}' is not a colour", text))
+    }
+}
+
+/// The nearest basic colour to a 256-colour number, for terminals with only eight
+fn basic_color(n: u8) -> u8 {
+    match n {
+        0..=15 => n % 8,
+        16..=231 => {
+            // 6x6x6 cube; each channel counts as on from halfway up
+            let n = n - 16;
+            let on = |level: u8| (level >= 3) as u8;
+            on(n / 36) | on(n / 6 % 6) << 1 | on(n % 6) << 2
+        },
+        // Grey ramp
+        _ => if n >= 244 { 7 } else { 0 },
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// The squares no one plays on
+    pub light_square: ThemeColor,
+    pub dark_square: ThemeColor,
+    pub light_piece: ThemeColor,
+    pub dark_piece: ThemeColor,
+    /// Brackets around the cursor and the selected piece; the other square colour if `None`
+    pub cursor: Option<ThemeColor>,
+    /// Backgrounds for highlighted squares.  Without them highlights are bold, underlined and reversed text.
+    pub destination: Option<ThemeColor>,
+    pub capture: Option<ThemeColor>,
+    pub last_move: Option<ThemeColor>,
+    /// Text of info, AI and debug log lines
+    pub log: [ThemeColor; 3],
+}
+impl Theme {
+    fn basic(name: &str, light_square: u8, dark_square: u8, pieces: u8) -> Theme {
+        Theme {
+            name: name.to_string(),
+            light_square: ThemeColor::Index(light_square),
+            dark_square: ThemeColor::Index(dark_square),
+            light_piece: ThemeColor::Index(pieces),
+            dark_piece: ThemeColor::Index(pieces),
+            cursor: None,
+            destination: None,
+            capture: None,
+            last_move: None,
+            log: [ThemeColor::Default; 3],
+        }
+    }
+
+    /// Themes that need no file: the original three colour schemes, and one for 256-colour terminals
+    pub fn built_in() -> Vec<Theme> {
+        const BLACK: u8 = 0;
+        const RED: u8 = 1;
+        const WHITE: u8 = 7;
+        vec![
+            Theme::basic("redblack", RED, BLACK, RED),
+            Theme::basic("whitered", WHITE, RED, WHITE),
+            Theme::basic("whiteblack", WHITE, BLACK, WHITE),
+            Theme {
+                name: "wood".to_string(),
+                light_square: ThemeColor::Index(223),
+                dark_square: ThemeColor::Index(94),
+                light_piece: ThemeColor::Index(231),
+                dark_piece: ThemeColor::Index(16),
+                cursor: Some(ThemeColor::Index(226)),
+                destination: Some(ThemeColor::Index(22)),
+                capture: Some(ThemeColor::Index(88)),
+                last_move: Some(ThemeColor::Index(58)),
+                log: [ThemeColor::Default, ThemeColor::Index(109), ThemeColor::Index(244)],
+            },
+        ]
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let optional = |value: &str| match value {
+            "none" => Ok(None),
+            value => value.parse().map(Some),
+        };
+        match key {
+            "light-square" => self.light_square = value.parse()?,
+            "dark-square"  => self.dark_square = value.parse()?,
+            "light-piece"  => self.light_piece = value.parse()?,
+            "dark-piece"   => self.dark_piece = value.parse()?,
+            "cursor"       => self.cursor = optional(value)?,
+            "destination"  => self.destination = optional(value)?,
+            "capture"      => self.capture = optional(value)?,
+            "last-move"    => self.last_move = optional(value)?,
+            "log"          => self.log[0] = value.parse()?,
+            "log-ai"       => self.log[1] = value.parse()?,
+            "log-debug"    => self.log[2] = value.parse()?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Every theme and glyph set to choose from, built-in ones first
+pub struct Themes {
+    pub themes: Vec<Theme>,
+    pub glyph_sets: Vec<GlyphSet>,
+    /// Files that couldn't be used, and why
+    pub errors: Vec<String>,
+}
+impl Themes {
+    /// The built-in themes plus any in `dir`.  A file named like a built-in theme replaces it.
+    pub fn load(dir: Option<&Path>) -> Themes {
+        let mut result = Themes {
+            themes: Theme::built_in(),
+            glyph_sets: GlyphSet::built_in(),
+            errors: Vec::new(),
+        };
+        let mut paths: Vec<_> = match dir.map(fs::read_dir) {
+            Some(Ok(entries)) => entries.filter_map(|entry| Some(entry.ok()?.path())).collect(),
+            _ => Vec::new(), // Having no themes directory is normal
+        };
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "theme"));
+        paths.sort();
+        for path in paths {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+            let parsed = fs::read_to_string(&path).map_err(|err| err.to_string())
+                .and_then(|text| result.parse(&name, &text));
+            match parsed {
+                Ok((theme, glyph_set)) => {
+                    if let Some(theme) = theme {
+                        result.themes.retain(|t| t.name != name);
+                        result.themes.push(theme);
+                    }
+                    if let Some(glyph_set) = glyph_set {
+                        result.glyph_sets.retain(|g| g.name != name);
+                        result.glyph_sets.push(glyph_set);
+                    }
+                },
+                Err(err) => result.errors.push(format!("Could not use theme {}: {}", path.display(), err)),
+            }
+        }
+        result
+    }
+
+    /// The theme and glyph set a file defines, if it defines any colours or glyphs
+    fn parse(&self, name: &str, text: &str) -> Result<(Option<Theme>, Option<GlyphSet>), String> {
+        let mut theme = Theme{ name: name.to_string(), ..self.themes[0].clone() };
+        let mut glyph_set = GlyphSet{ name: name.to_string(), ..self.glyph_sets[0].clone() };
+        let (mut has_colors, mut has_glyphs) = (false, false);
+        config::parse_lines(text, |key, value| {
+            if key == "base" {
+                let base = self.theme_index(value).map(|idx| &self.themes[idx]).ok_or_else(|| format!("no theme called '{}'", value))?;
+                theme = Theme{ name: name.to_string(), ..base.clone() };
+                return Ok(());
+            }
+            match key.strip_prefix("glyph.") {
+                Some(key) => {
+                    has_glyphs = true;
+                    glyph_set.set(key, value)
+                },
+                None => {
+                    has_colors = true;
+                    theme.set(key, value)
+                },
+            }
+        })?;
+        if !has_colors && !has_glyphs {
+            return Err("no colours or glyphs in it".to_string());
+        }
+        Ok((Some(theme).filter(|_| has_colors), Some(glyph_set).filter(|_| has_glyphs)))
+    }
+
+    /// Where the theme called `name` is in `themes`
+    pub fn theme_index(&self, name: &str) -> Option<usize> {
+        self.themes.iter().position(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Where the glyph set called `name` is in `glyph_sets`
+    pub fn glyph_set_index(&self, name: &str) -> Option<usize> {
+        self.glyph_sets.iter().position(|glyphs| glyphs.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Text colour for part of the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ink {
+    LightPiece,
+    DarkPiece,
+    Cursor,
+    /// Labels and markers, in the other square colour
+    Plain,
+}
+const INKS: [Ink; 4] = [Ink::LightPiece, Ink::DarkPiece, Ink::Cursor, Ink::Plain];
+
+/// Background colour for a square
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Paper {
+    LightSquare,
+    DarkSquare,
+    Destination,
+    Capture,
+    LastMove,
+}
+const PAPERS: [Paper; 5] = [Paper::LightSquare, Paper::DarkSquare, Paper::Destination, Paper::Capture, Paper::LastMove];
+
+/// Colour pairs below this are left to curses
+const FIRST_PAIR: i16 = 16;
+
+/// Colour pair for `ink` on `paper` in the current theme
+pub fn pair(ink: Ink, paper: Paper) -> i16 {
+    FIRST_PAIR + (paper as usize * INKS.len() + ink as usize) as i16
+}
+
+/// Colour pair for log lines at `level`
+pub fn log_pair(level: LogLevel) -> i16 {
+    FIRST_PAIR + (PAPERS.len() * INKS.len()) as i16 + level as i16
+}
+
+/// Makes `theme` the current one, recolouring anything already drawn with its pairs.
+pub fn apply(theme: &Theme) {
+    if !pancurses::has_colors() {
+        return;
+    }
+    let colors = pancurses::COLORS();
+    let paper_color = |paper| match paper {
+        Paper::LightSquare => theme.light_square,
+        Paper::DarkSquare  => theme.dark_square,
+        Paper::Destination => theme.destination.unwrap_or(theme.dark_square),
+        Paper::Capture     => theme.capture.unwrap_or(theme.dark_square),
+        Paper::LastMove    => theme.last_move.unwrap_or(theme.dark_square),
+    };
+    for paper in PAPERS {
+        let other_square = if paper == Paper::LightSquare { theme.dark_square } else { theme.light_square };
+        for ink in INKS {
+            let ink_color = match ink {
+                Ink::LightPiece => theme.light_piece,
+                Ink::DarkPiece  => theme.dark_piece,
+                Ink::Cursor     => theme.cursor.unwrap_or(other_square),
+                Ink::Plain      => other_square,
+            };
+            pancurses::init_pair(pair(ink, paper), ink_color.curses_color(colors), paper_color(paper).curses_color(colors));
+        }
+    }
+    for (level, color) in [LogLevel::Info, LogLevel::Ai, LogLevel::Debug].iter().zip(theme.log.iter()) {
+        pancurses::init_pair(log_pair(*level), color.curses_color(colors), -1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors() {
+        for (text, color) in [("default", ThemeColor::Default), ("red", ThemeColor::Index(1)),
+                              ("bright-white", ThemeColor::Index(15)), ("94", ThemeColor::Index(94))] {
+            assert_eq!(text.parse(), Ok(color));
+            assert_eq!(color.to_string(), text);
+        }
+        assert!("256".parse::<ThemeColor>().is_err());
+        assert!("mauve".parse::<ThemeColor>().is_err());
+
+        assert_eq!(ThemeColor::Index(94).curses_color(256), 94);
+        assert_eq!(ThemeColor::Index(196).curses_color(8), 1); // Bright red
+        assert_eq!(ThemeColor::Index(231).curses_color(8), 7); // White corner of the cube
+        assert_eq!(ThemeColor::Index(9).curses_color(8), 1);
+        assert_eq!(ThemeColor::Default.curses_color(8), -1);
+    }
+
+    #[test]
+    fn test_theme_files() {
+        let themes = Themes::load(None);
+        let (theme, glyph_set) = themes.parse("mine", "base = wood\ndark-square = blue\ncapture = none\n").unwrap();
+        let theme = theme.unwrap();
+        assert_eq!(theme.dark_square, ThemeColor::Index(4));
+        assert_eq!(theme.light_square, themes.themes[themes.theme_index("wood").unwrap()].light_square);
+        assert_eq!(theme.capture, None);
+        assert!(glyph_set.is_none());
+
+        let (theme, glyph_set) = themes.parse("letters", "glyph.light-man = l\nglyph.dark-king = D").unwrap();
+        assert!(theme.is_none());
+        let glyph_set = glyph_set.unwrap();
+        assert_eq!(glyph_set.pieces, ['l', '⛃', '⛀', 'D']);
+        assert!(!glyph_set.is_ascii());
+        assert!(themes.glyph_sets[themes.glyph_set_index("ASCII").unwrap()].is_ascii());
+
+        assert!(themes.parse("bad", "glyph.light-man = ll").is_err());
+        assert!(themes.parse("bad", "base = nope").is_err());
+        assert!(themes.parse("empty", "# nothing\n").is_err());
+    }
+}
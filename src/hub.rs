@@ -0,0 +1,383 @@
+//! Headless engine mode speaking the Hub protocol (the line-based protocol used by Scan and the GUIs built around it).
+//!
+//! Positions are a side-to-move character (`W` for Light, `B` for Dark) followed by one character per playable square
+//! in notation order: `w`/`b` for men, `W`/`B` for kings and `e` for empty.  Quiet moves are written `22-18`; jumps
+//! are written `from x to` followed by each captured square, e.g. `26x17x22`.
+
+use crate::backend::{notation, Ai, Board, Move, Piece, PieceType, SearchInfo, SearchLimits, Team, Variant, MAX_DEPTH};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Moves left assumed when budgeting a clock with no `moves=` count.
+const DEFAULT_MOVES_TO_GO: f64 = 30.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Level {
+    depth: Option<usize>,
+    move_time: Option<f64>,
+    time: Option<f64>,
+    inc: f64,
+    moves: Option<u32>,
+    infinite: bool,
+}
+impl Level {
+    fn limits(&self) -> SearchLimits {
+        let time = match (self.move_time, self.time) {
+            _ if self.infinite => None,
+            (Some(move_time), _) => Some(move_time),
+            (None, Some(time)) => {
+                let moves_to_go = self.moves.map_or(DEFAULT_MOVES_TO_GO, |moves| std::cmp::max(moves, 1) as f64);
+                // Leave a little on the clock for communication overhead
+                Some((time / moves_to_go + self.inc * 0.8).min(time * 0.9))
+            },
+            (None, None) => None,
+        };
+
+        SearchLimits {
+            depth: if self.infinite { Some(MAX_DEPTH) } else { self.depth },
+            // Too long to represent is as good as no limit
+            time: time.map(|secs| Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX)),
+        }
+    }
+}
+
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// Plays under American rules, as Hub GUIs expect captures to be compulsory.
+pub struct HubEngine {
+    board: Board,
+    to_move: Team,
+    level: Level,
+    search: Option<Search>,
+}
+impl Default for HubEngine {
+    fn default() -> HubEngine {
+        HubEngine::new()
+    }
+}
+impl HubEngine {
+    pub fn new() -> HubEngine {
+        let (board, to_move) = start_position();
+        HubEngine {
+            board,
+            to_move,
+            level: Level::default(),
+            search: None,
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line?;
+            let (command, args) = match parse_line(&line) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    reply_error(&err);
+                    continue;
+                },
+            };
+            if !self.handle_command(&command, &args) {
+                break;
+            }
+        }
+        self.stop_search();
+
+        Ok(())
+    }
+
+    /// Returns `false` once the GUI has asked us to quit.
+    fn handle_command(&mut self, command: &str, args: &HashMap<String, String>) -> bool {
+        match command {
+            "" => (),
+            "hub" => {
+                println!("id name=checkers version={} author=\"{}\"", env!("CARGO_PKG_VERSION"), env!("CARGO_PKG_AUTHORS"));
+                println!("wait");
+            },
+            "init" => println!("ready"),
+            "new-game" => {
+                self.stop_search();
+                let (board, to_move) = start_position();
+                self.board = board;
+                self.to_move = to_move;
+            },
+            "pos" => {
+                self.stop_search();
+                if let Err(err) = self.set_position(args) {
+                    reply_error(&err);
+                }
+            },
+            "level" => match parse_level(args) {
+                Ok(level) => self.level = level,
+                Err(err) => reply_error(&err),
+            },
+            "go" => match args.get("ponder") {
+                Some(_) => reply_error("pondering is not supported"),
+                None => {
+                    let mut level = self.level;
+                    level.infinite |= args.contains_key("analyze");
+                    self.start_search(level);
+                },
+            },
+            "stop" => self.stop_search(),
+            "ping" => println!("pong"),
+            "quit" => return false,
+            _ => reply_error(&format!("unknown command '{}'", command)),
+        }
+
+        true
+    }
+
+    fn set_position(&mut self, args: &HashMap<String, String>) -> Result<(), String> {
+        let (mut board, mut to_move) = match args.get("pos") {
+            Some(pos) => parse_position(pos)?,
+            None => start_position(),
+        };
+        if let Some(moves) = args.get("moves") {
+            for text in moves.split_whitespace() {
+                let turn = find_turn(&board, to_move, text)?;
+                for mv in &turn {
+                    board.apply_move(mv);
+                }
+                to_move = to_move.other();
+            }
+        }
+        self.board = board;
+        self.to_move = to_move;
+
+        Ok(())
+    }
+
+    fn start_search(&mut self, level: Level) {
+        self.stop_search();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let ai = Ai{ team: self.to_move };
+        let board = self.board.clone();
+        let search_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            match ai.search(board, level.limits(), Some(&search_stop), print_info) {
+                Some(info) => println!("done move={}", format_move(&info.moves)),
+                None => println!("done"),
+            }
+        });
+        self.search = Some(Search{ stop, handle });
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            search.handle.join().ok();
+        }
+    }
+}
+
+fn start_position() -> (Board, Team) {
+    (Board::new().with_variant(Variant::American), Variant::American.first_team())
+}
+
+fn reply_error(message: &str) {
+    println!("error message=\"{}\"", message.replace('"', "'"));
+}
+
+fn print_info(info: &SearchInfo) {
+    let secs = info.elapsed.as_secs_f64();
+    let nps = if secs > 0.0 { (info.nodes as f64 / secs) as u64 } else { 0 };
+    println!("info depth={} score={} nodes={} time={:.3} nps={} pv=\"{}\"",
+             info.depth, info.score, info.nodes, secs, nps, format_move(&info.moves));
+}
+
+/// Splits `command key=value key="quoted value" flag` into the command and its arguments.  Flags map to "".
+fn parse_line(line: &str) -> Result<(String, HashMap<String, String>), String> {
+    let mut chars = line.trim().chars().peekable();
+    let command: String = chars.by_ref().take_while(|c| !c.is_whitespace()).collect();
+    let mut args = HashMap::new();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=')).collect();
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                value = chars.by_ref().take_while(|c| *c != '"').collect();
+            } else {
+                value = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect();
+            }
+        }
+        if key.is_empty() {
+            return Err(format!("malformed arguments in '{}'", line.trim()));
+        }
+        args.insert(key, value);
+    }
+
+    Ok((command, args))
+}
+
+fn parse_level(args: &HashMap<String, String>) -> Result<Level, String> {
+    let number = |key: &str| -> Result<Option<f64>, String> {
+        args.get(key)
+            .map(|value| value.parse::<f64>().ok().filter(|number| number.is_finite())
+                 .ok_or_else(|| format!("bad value for {}: '{}'", key, value)))
+            .transpose()
+    };
+
+    Ok(Level {
+        depth: number("depth")?.map(|depth| depth as usize),
+        move_time: number("move-time")?,
+        time: number("time")?,
+        inc: number("inc")?.unwrap_or(0.0),
+        moves: number("moves")?.map(|moves| moves as u32),
+        infinite: args.contains_key("infinite"),
+    })
+}
+
+fn parse_position(pos: &str) -> Result<(Board, Team), String> {
+    let mut chars = pos.chars();
+    let to_move = match chars.next() {
+        Some('W') | Some('w') => Team::Light,
+        Some('B') | Some('b') => Team::Dark,
+        _ => return Err(format!("bad side to move in position '{}'", pos)),
+    };
+    let squares: Vec<char> = chars.collect();
+    if squares.len() != notation::NUM_SQUARES as usize {
+        return Err(format!("position '{}' should have {} squares", pos, notation::NUM_SQUARES));
+    }
+
+    let mut board = Board::empty().with_variant(Variant::American);
+    for (idx, c) in squares.iter().enumerate() {
+        let square = notation::number_square(idx as u8 + 1).unwrap();
+        let piece = match c {
+            'w' => Piece{ team: Team::Light, piece_type: PieceType::Man },
+            'W' => Piece{ team: Team::Light, piece_type: PieceType::King },
+            'b' => Piece{ team: Team::Dark,  piece_type: PieceType::Man },
+            'B' => Piece{ team: Team::Dark,  piece_type: PieceType::King },
+            'e' | '.' => continue,
+            _ => return Err(format!("bad square '{}' in position '{}'", c, pos)),
+        };
+        board.place_piece(square, piece);
+    }
+
+    Ok((board, to_move))
+}
+
+#[cfg(test)]
+fn format_position(board: &Board, to_move: Team) -> String {
+    let mut result = String::from(if to_move == Team::Light { "W" } else { "B" });
+    for number in 1..=notation::NUM_SQUARES {
+        let square = notation::number_square(number).unwrap();
+        result.push(match board.get_piece_at(&square) {
+            Some(Piece{ team: Team::Light, piece_type: PieceType::Man })  => 'w',
+            Some(Piece{ team: Team::Light, piece_type: PieceType::King }) => 'W',
+            Some(Piece{ team: Team::Dark,  piece_type: PieceType::Man })  => 'b',
+            Some(Piece{ team: Team::Dark,  piece_type: PieceType::King }) => 'B',
+            None => 'e',
+        });
+    }
+
+    result
+}
+
+fn format_move(turn: &[Move]) -> String {
+    let (from, to, captured) = notation::turn_summary(turn);
+    if captured.is_empty() {
+        format!("{}-{}", from, to)
+    } else {
+        let captured: Vec<String> = captured.iter().map(|n| n.to_string()).collect();
+        format!("{}x{}x{}", from, to, captured.join("x"))
+    }
+}
+
+/// Finds the legal turn matching a Hub move.  Captured squares are optional, but if given must match exactly.
+fn find_turn(board: &Board, to_move: Team, text: &str) -> Result<Vec<Move>, String> {
+    let numbers = text
+        .split(['-', 'x'])
+        .map(|part| part.parse::<u8>().map_err(|_| format!("bad move '{}'", text)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() < 2 {
+        return Err(format!("bad move '{}'", text));
+    }
+
+    notation::find_turn(board, to_move, numbers[0], numbers[1], &numbers[2..])
+        .ok_or_else(|| format!("illegal move '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let (command, args) = parse_line("pos pos=Wbbbbbbbbbbbbeeeeeeeewwwwwwwwwwww moves=\"22-18 11-15\"").unwrap();
+        assert_eq!(command, "pos");
+        assert_eq!(args["pos"].len(), 33);
+        assert_eq!(args["moves"], "22-18 11-15");
+
+        let (command, args) = parse_line("  go think ").unwrap();
+        assert_eq!(command, "go");
+        assert!(args.contains_key("think"));
+    }
+
+    #[test]
+    fn test_position_round_trip() {
+        let start = "Bbbbbbbbbbbbbeeeeeeeewwwwwwwwwwww";
+        let (board, to_move) = parse_position(start).unwrap();
+        assert_eq!(to_move, Team::Dark);
+        assert_eq!(board.pieces_alive(Team::Light), 12);
+        assert_eq!(board.pieces_alive(Team::Dark), 12);
+        assert_eq!(format_position(&board, to_move), start);
+
+        assert!(parse_position("Wbbb").is_err());
+        assert!(parse_position("Xbbbbbbbbbbbbeeeeeeeewwwwwwwwwwww").is_err());
+    }
+
+    #[test]
+    fn test_moves() {
+        let mut engine = HubEngine::new();
+        let mut args = HashMap::new();
+        args.insert("moves".to_string(), "9-14 22-18 10-15".to_string());
+        engine.set_position(&args).unwrap();
+        assert_eq!(engine.to_move, Team::Light);
+
+        let jump = find_turn(&engine.board, Team::Light, "18x9x14").unwrap();
+        assert_eq!(format_move(&jump), "18x9x14");
+        assert_eq!(find_turn(&engine.board, Team::Light, "18x9").unwrap(), jump);
+        assert!(find_turn(&engine.board, Team::Light, "18x9x13").is_err());
+        assert!(find_turn(&engine.board, Team::Light, "18-14").is_err());
+
+        // The capture is compulsory, so it's all the engine may play
+        assert!(find_turn(&engine.board, Team::Light, "21-17").is_err());
+        let info = Ai{ team: engine.to_move }.search(engine.board.clone(), engine.level.limits(), None, |_| ()).unwrap();
+        assert_eq!(info.moves, jump);
+
+        let (board, to_move) = parse_position("Wbbbbbbbbbbbbeeeeeeeewwwwwwwwwwww").unwrap();
+        assert_eq!(board.variant(), Variant::American);
+        assert_eq!((HubEngine::new().to_move, to_move), (Team::Dark, Team::Light));
+    }
+
+    #[test]
+    fn test_level() {
+        let (_, args) = parse_line("level time=60 inc=1 moves=20").unwrap();
+        let limits = parse_level(&args).unwrap().limits();
+        assert_eq!(limits.depth, None);
+        assert_eq!(limits.time, Some(Duration::from_secs_f64(3.8)));
+
+        let (_, args) = parse_line("level depth=3").unwrap();
+        assert_eq!(parse_level(&args).unwrap().limits().depth, Some(3));
+        assert!(parse_level(&parse_line("level depth=abc").unwrap().1).is_err());
+        assert!(parse_level(&parse_line("level move-time=inf").unwrap().1).is_err());
+        let (_, args) = parse_line("level move-time=1e30").unwrap();
+        assert_eq!(parse_level(&args).unwrap().limits().time, Some(Duration::MAX));
+    }
+}
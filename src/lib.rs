@@ -0,0 +1,39 @@
+//! Checkers (English draughts) rules, notation and AI, plus the frontends and network modes of the `checkers` game.
+//!
+//! Tools that only need the game itself want `backend`: `Board` holds a position and knows the rules, `notation`
+//! reads and writes standard move notation and `Ai` searches for moves.
+//!
+//! ```
+//! use checkers::backend::{notation, Ai, Board, SearchLimits, Team};
+//!
+//! let mut board = Board::new();
+//! let turn = notation::find_turn(&board, Team::Light, 22, 18, &[]).unwrap();
+//! for mv in &turn {
+//!     board.apply_move(mv);
+//! }
+//!
+//! let limits = SearchLimits{ depth: Some(3), time: None };
+//! let reply = Ai{ team: Team::Dark }.search(board, limits, None, |_| ()).unwrap();
+//! assert!(!notation::format_path(&reply.moves).is_empty());
+//! ```
+//!
+//! The rest drives whole games: `channel` is the message protocol between a `backend::Game` and a frontend, and
+//! `run_command` runs the `checkers` command, with `config` keeping its settings between runs.  The engine and server
+//! modes are commands of their own too, run by `run_mode_command`.  Cargo features: `curses` (the default terminal
+//! frontend) and `serde` (serialisation and the JSON API).
+
+#[macro_use]
+extern crate clap;
+
+mod args;
+pub mod backend;
+pub mod channel;
+mod cli;
+pub mod config;
+pub mod dxp;
+pub mod frontend;
+pub mod hub;
+pub mod net;
+pub mod web;
+
+pub use cli::{run_command, run_mode_command};
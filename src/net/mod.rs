@@ -0,0 +1,684 @@
+//! Play across machines: the host runs the `Game` and one frontend locally, a joining frontend connects over TCP and
+//! plays the other side.  The `channel` messages are sent over the socket as text lines (see `wire`).
+//!
+//! The host routes the game's messages between the connections: move requests only go to the player whose turn it
+//! is, everything else is broadcast.  If the joining player's connection drops they may reconnect, and are sent the
+//! current board and any move they still owe.
+//!
+//! Any number of observers may also connect to watch.  They are sent the moves so far on joining and then see
+//! everything but move requests.
+
+pub mod wire;
+
+pub use wire::Hello;
+
+use crate::args::{Args, NetArgs};
+use crate::backend::{Board, Clock, Game, Move, Team};
+use crate::channel::{self, BackendEndpoint, BackToFrontMessage, FrontendEndpoint, FrontToBackMessage, LogLevel};
+use crate::frontend::Preferences;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 27532;
+/// Sent as the last line by either side when it leaves on purpose, so the other knows not to expect a reconnect.
+pub const GOODBYE: &str = "bye";
+const RECONNECT_ATTEMPTS: u32 = 30;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sending half of a client connection, one protocol line at a time.
+pub trait LineSender: Send {
+    fn send_line(&mut self, line: &str) -> io::Result<()>;
+    fn close(&mut self);
+}
+
+/// Receiving half of a client connection.
+pub trait LineReceiver: Send {
+    /// Next line, or `None` once the connection has closed.
+    fn recv_line(&mut self) -> io::Result<Option<String>>;
+}
+
+impl LineSender for TcpStream {
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        write_line(self, line)
+    }
+
+    fn close(&mut self) {
+        self.shutdown(Shutdown::Both).ok();
+    }
+}
+
+impl LineReceiver for BufReader<TcpStream> {
+    fn recv_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        match self.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+        }
+    }
+}
+
+/// A client that has said hello, over whatever transport.
+pub struct Connection {
+    pub sender: Box<dyn LineSender>,
+    pub receiver: Box<dyn LineReceiver>,
+    pub hello: Hello,
+}
+
+fn write_line(stream: &mut TcpStream, line: &str) -> io::Result<()> {
+    stream.write_all(line.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+/// Handle for adding connections to a running game.
+#[derive(Clone)]
+pub struct GameServer {
+    events: mpsc::Sender<Event>,
+}
+impl GameServer {
+    /// Returns false once the game is over and no one else can join.
+    pub fn add_connection(&self, connection: Connection) -> bool {
+        self.events.send(Event::Connected(connection)).is_ok()
+    }
+
+    /// Accepts plain TCP clients on `addr` in the background.
+    pub fn listen(&self, addr: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Some(connection) = accept_hello(stream) {
+                    if !server.add_connection(connection) {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(addr)
+    }
+}
+
+/// Starts the game and waits for the other player to join.  Returns the endpoint for the host's own frontend.
+pub fn host(args: Args, net_args: NetArgs) -> io::Result<FrontendEndpoint> {
+    let open_seats = vec![net_args.team.other()];
+    serve_local(args, &net_args.addr, Role::Player(net_args.team), open_seats, "Waiting for an opponent to join on")
+}
+
+/// Starts a game played entirely by the local frontend, open to observers.
+pub fn host_spectated(args: Args, addr: &str) -> io::Result<FrontendEndpoint> {
+    serve_local(args, addr, Role::BothPlayers, Vec::new(), "Spectators can watch on")
+}
+
+fn serve_local(args: Args, addr: &str, local_role: Role, open_seats: Vec<Team>, waiting_msg: &str)
+    -> io::Result<FrontendEndpoint>
+{
+    let (local_endpoint, frontend_endpoint) = channel::make_two_way_channel();
+    let mut router = Router::new(args, open_seats);
+    router.add_local(local_endpoint, local_role);
+
+    let server = router.server();
+    let addr = server.listen(addr)?;
+    router.log_to(0, format!("{} {}", waiting_msg, addr));
+    thread::spawn(move || router.run());
+
+    Ok(frontend_endpoint)
+}
+
+/// Starts a game with no local frontend.  Every "Human" seat in `preferences` is left for a connection to take.
+pub fn serve_remote(args: Args, preferences: Preferences) -> GameServer {
+    let open_seats = [Team::Light, Team::Dark].iter()
+        .copied()
+        .filter(|team| preferences.players[*team as usize] == "Human")
+        .collect();
+    let mut router = Router::new(args, open_seats);
+    router.start_game(preferences);
+
+    let server = router.server();
+    thread::spawn(move || router.run());
+    server
+}
+
+fn accept_hello(stream: TcpStream) -> Option<Connection> {
+    stream.set_read_timeout(Some(HELLO_TIMEOUT)).ok()?;
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let hello = wire::decode_hello(&reader.recv_line().ok()??)?;
+    stream.set_read_timeout(None).ok()?;
+
+    Some(Connection{ sender: Box::new(stream), receiver: Box::new(reader), hello })
+}
+
+/// Connects to a host, as a player or only to watch.  Returns the endpoint for the local frontend, which behaves as if
+/// the game were in-process.
+pub fn join(addr: &str, hello: Hello) -> io::Result<FrontendEndpoint> {
+    let (stream, reader) = connect(addr, hello)?;
+    let writer = Arc::new(Mutex::new(stream));
+    let (bridge_endpoint, frontend_endpoint) = channel::make_two_way_channel();
+    let BackendEndpoint{ tx, rx } = bridge_endpoint;
+
+    let outgoing = Arc::clone(&writer);
+    thread::spawn(move || {
+        for msg in rx {
+            // A failed write is dropped: after reconnecting the host asks again for anything it is still owed
+            write_line(&mut outgoing.lock().unwrap(), &wire::encode_front(&msg)).ok();
+        }
+        let mut stream = outgoing.lock().unwrap();
+        write_line(&mut stream, GOODBYE).ok();
+        stream.shutdown(Shutdown::Both).ok();
+    });
+
+    let addr = addr.to_string();
+    thread::spawn(move || {
+        let mut reader = reader;
+        loop {
+            if !forward_lines(reader, &tx) {
+                return;
+            }
+
+            let log = |msg: String| tx.send(BackToFrontMessage::Log{ level: LogLevel::Info, msg }).is_ok();
+            if !log("Lost connection to the host, reconnecting...".to_string()) {
+                return;
+            }
+            match reconnect(&addr, hello) {
+                Some((stream, new_reader)) => {
+                    *writer.lock().unwrap() = stream;
+                    reader = new_reader;
+                    log("Reconnected".to_string());
+                },
+                None => {
+                    log("Could not reconnect to the host".to_string());
+                    return;
+                },
+            }
+        }
+    });
+
+    Ok(frontend_endpoint)
+}
+
+fn connect(addr: &str, hello: Hello) -> io::Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut stream = TcpStream::connect(addr)?;
+    write_line(&mut stream, &wire::encode_hello(hello))?;
+    let reader = BufReader::new(stream.try_clone()?);
+    Ok((stream, reader))
+}
+
+fn reconnect(addr: &str, hello: Hello) -> Option<(TcpStream, BufReader<TcpStream>)> {
+    for _ in 0..RECONNECT_ATTEMPTS {
+        thread::sleep(RECONNECT_DELAY);
+        if let Ok(connection) = connect(addr, hello) {
+            return Some(connection);
+        }
+    }
+    None
+}
+
+/// Passes the host's messages on to the frontend.  Returns whether the connection was lost (rather than closed on
+/// purpose by either side).
+fn forward_lines(reader: BufReader<TcpStream>, tx: &mpsc::Sender<BackToFrontMessage>) -> bool {
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return true,
+        };
+        if line == GOODBYE {
+            tx.send(BackToFrontMessage::Log{ level: LogLevel::Info, msg: "The host ended the game".to_string() }).ok();
+            return false;
+        }
+        match wire::decode_back(&line) {
+            Ok(msg) => if tx.send(msg).is_err() {
+                return false;
+            },
+            Err(err) => {
+                tx.send(BackToFrontMessage::Log{ level: LogLevel::Debug, msg: format!("Bad message from host: {}", err) }).ok();
+            },
+        }
+    }
+    true
+}
+
+enum Event {
+    Game(BackToFrontMessage),
+    GameClosed,
+    Connected(Connection),
+    Client(usize, FrontToBackMessage),
+    /// The client left; `true` if it said goodbye rather than losing its connection.
+    ClientClosed(usize, bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Role {
+    Player(Team),
+    /// Both sides are played from the same frontend, as in a local game
+    BothPlayers,
+    Observer,
+}
+impl Role {
+    fn plays(self, team: Team) -> bool {
+        match self {
+            Role::Player(own_team) => own_team == team,
+            Role::BothPlayers => true,
+            Role::Observer => false,
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Role::Player(team) => format!("{:?} player", team),
+            Role::BothPlayers => "host".to_string(),
+            Role::Observer => "observer".to_string(),
+        }
+    }
+}
+
+struct Client {
+    role: Role,
+    tx: mpsc::Sender<BackToFrontMessage>,
+}
+
+/// Hands the game's messages to whichever connections should see them, and only lets the player being asked for a
+/// move answer.
+struct Router {
+    game_tx: mpsc::Sender<FrontToBackMessage>,
+    events_tx: mpsc::Sender<Event>,
+    events: mpsc::Receiver<Event>,
+    /// Indexed by client id.
+    clients: Vec<Option<Client>>,
+    /// The local frontend, if any.  It starts the game, and the game ends when it leaves.
+    host: Option<usize>,
+    /// Sides a connection may take.
+    open_seats: Vec<Team>,
+    board: Option<Board>,
+    /// Every completed turn, replayed to observers when they join.
+    history: Vec<(Team, Vec<Move>)>,
+    /// The move request still waiting for an answer, resent to its player if they reconnect.
+    pending: Option<BackToFrontMessage>,
+    /// Running copy of the game's clocks, so joiners see the time left now rather than at the start of the turn.
+    clock: Option<Clock>,
+    /// Team whose draw offer is waiting for an answer
+    draw_offer: Option<Team>,
+    started: bool,
+}
+impl Router {
+    fn new(args: Args, open_seats: Vec<Team>) -> Router {
+        let (backend_endpoint, game_endpoint) = channel::make_two_way_channel();
+        thread::spawn(move || {
+            let mut game = Game::new(args, backend_endpoint);
+            game.start();
+        });
+
+        let (events_tx, events) = mpsc::channel();
+        let FrontendEndpoint{ tx: game_tx, rx: game_rx } = game_endpoint;
+
+        let game_events = events_tx.clone();
+        thread::spawn(move || {
+            for msg in game_rx {
+                if game_events.send(Event::Game(msg)).is_err() {
+                    return;
+                }
+            }
+            game_events.send(Event::GameClosed).ok();
+        });
+
+        Router {
+            game_tx,
+            events_tx,
+            events,
+            clients: Vec::new(),
+            host: None,
+            open_seats,
+            board: None,
+            history: Vec::new(),
+            pending: None,
+            clock: None,
+            draw_offer: None,
+            started: false,
+        }
+    }
+
+    fn server(&self) -> GameServer {
+        GameServer{ events: self.events_tx.clone() }
+    }
+
+    fn start_game(&mut self, preferences: Preferences) {
+        self.started = true;
+        self.game_tx.send(FrontToBackMessage::StartGame(preferences)).ok();
+    }
+
+    fn add_local(&mut self, endpoint: BackendEndpoint, role: Role) {
+        let BackendEndpoint{ tx, rx } = endpoint;
+        let id = self.add_client(Client{ role, tx });
+        self.host = Some(id);
+
+        let events = self.events_tx.clone();
+        thread::spawn(move || {
+            for msg in rx {
+                if events.send(Event::Client(id, msg)).is_err() {
+                    return;
+                }
+            }
+            events.send(Event::ClientClosed(id, true)).ok();
+        });
+    }
+
+    fn add_remote(&mut self, connection: Connection) {
+        let Connection{ mut sender, mut receiver, hello } = connection;
+        let is_free = |team: &Team| self.open_seats.contains(team) && !self.has_player(*team);
+        let role = match hello {
+            Hello::Play(Some(team)) if is_free(&team) => Role::Player(team),
+            Hello::Play(None) => self.open_seats.iter().find(|team| is_free(team)).map_or(Role::Observer, |team| Role::Player(*team)),
+            _ => Role::Observer,
+        };
+
+        let (tx, rx) = mpsc::channel::<BackToFrontMessage>();
+        let id = self.add_client(Client{ role, tx });
+
+        thread::spawn(move || {
+            for msg in rx {
+                if sender.send_line(&wire::encode_back(&msg)).is_err() {
+                    return;
+                }
+            }
+            sender.send_line(GOODBYE).ok();
+            sender.close();
+        });
+
+        let events = self.events_tx.clone();
+        thread::spawn(move || {
+            let mut said_goodbye = false;
+            while let Ok(Some(line)) = receiver.recv_line() {
+                if line == GOODBYE {
+                    said_goodbye = true;
+                    break;
+                }
+                // Anything unreadable is ignored, the host keeps waiting for a valid move
+                if let Ok(msg) = wire::decode_front(&line) {
+                    if events.send(Event::Client(id, msg)).is_err() {
+                        return;
+                    }
+                }
+            }
+            events.send(Event::ClientClosed(id, said_goodbye)).ok();
+        });
+
+        if hello != Hello::Watch && role == Role::Observer {
+            self.log_to(id, "There is no free seat in this game, you are watching it".to_string());
+        }
+        self.broadcast_log(format!("The {} joined", role.describe()));
+        for (team, moves) in self.history.iter() {
+            self.send_to(id, BackToFrontMessage::MoveMade(*team, moves.clone()));
+        }
+        if let Some(board) = &self.board {
+            self.send_to(id, BackToFrontMessage::BoardState(board.clone()));
+        }
+        if let Some(clock) = &self.clock {
+            self.send_to(id, BackToFrontMessage::Clock(clock.state()));
+        }
+        if let Some(request) = self.pending.clone() {
+            if request_team(&request).is_some_and(|team| role.plays(team)) {
+                self.send_to(id, request);
+            }
+        }
+        if let Some(team) = self.draw_offer {
+            if role.plays(team.other()) {
+                self.send_to(id, BackToFrontMessage::DrawOffered(team));
+            }
+        }
+    }
+
+    fn add_client(&mut self, client: Client) -> usize {
+        self.clients.push(Some(client));
+        self.clients.len() - 1
+    }
+
+    fn has_player(&self, team: Team) -> bool {
+        self.clients.iter().flatten().any(|client| client.role.plays(team))
+    }
+
+    fn run(mut self) {
+        while let Ok(event) = self.events.recv() {
+            match event {
+                Event::Game(msg) => self.handle_game(msg),
+                Event::GameClosed => return,
+                Event::Connected(connection) => self.add_remote(connection),
+                Event::Client(id, msg) => self.handle_client(id, msg),
+                Event::ClientClosed(id, _) if Some(id) == self.host => return, // The game goes with its host
+                Event::ClientClosed(id, said_goodbye) => {
+                    if let Some(Some(client)) = self.clients.get_mut(id).map(Option::take) {
+                        match client.role {
+                            Role::Observer => self.broadcast_log("An observer left".to_string()),
+                            role if said_goodbye => self.broadcast_log(format!("The {} left, they may rejoin", role.describe())),
+                            role => self.broadcast_log(format!("Lost connection to the {}, waiting for them to reconnect", role.describe())),
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn handle_game(&mut self, msg: BackToFrontMessage) {
+        match &msg {
+            BackToFrontMessage::BoardState(board) => self.board = Some(board.clone()),
+            BackToFrontMessage::RequestMove(team) | BackToFrontMessage::RequestJump(team, ..) => {
+                let team = *team;
+                self.pending = Some(msg.clone());
+                if !self.has_player(team) {
+                    self.broadcast_log(format!("Waiting for the {:?} player to connect", team));
+                }
+                for id in self.client_ids() {
+                    if self.client_role(id).is_some_and(|role| role.plays(team)) {
+                        self.send_to(id, msg.clone());
+                    }
+                }
+                return;
+            },
+            BackToFrontMessage::MoveMade(team, moves) => {
+                self.pending = None;
+                self.history.push((*team, moves.clone()));
+            },
+            BackToFrontMessage::Clock(state) => self.clock = Some(Clock::resume(state)),
+            BackToFrontMessage::DrawOffered(team) => {
+                self.draw_offer = Some(*team);
+                for id in self.client_ids() {
+                    if self.client_role(id).is_some_and(|role| role.plays(team.other())) {
+                        self.send_to(id, msg.clone());
+                    }
+                }
+                return;
+            },
+            BackToFrontMessage::GameOver(_) => self.pending = None,
+            BackToFrontMessage::Log{ .. } => (),
+        };
+
+        for id in self.client_ids() {
+            self.send_to(id, msg.clone());
+        }
+    }
+
+    fn handle_client(&mut self, id: usize, msg: FrontToBackMessage) {
+        if let FrontToBackMessage::StartGame(mut prefs) = msg {
+            // Only the host's preferences count, and in a networked game both sides are played by people
+            if Some(id) == self.host && !self.started {
+                if let Some(Role::Player(_)) = self.client_role(id) {
+                    prefs.players = ["Human", "Human"];
+                }
+                self.start_game(prefs);
+            }
+            return;
+        }
+
+        let role = match self.client_role(id) {
+            Some(Role::Observer) | None => return self.log_to(id, "Observers can't play".to_string()),
+            Some(role) => role,
+        };
+        if let FrontToBackMessage::AnswerDraw(_) = msg {
+            match self.draw_offer {
+                Some(team) if role.plays(team.other()) => {
+                    self.draw_offer = None;
+                    self.game_tx.send(msg).ok();
+                },
+                _ => self.log_to(id, "There is no draw offer to answer".to_string()),
+            }
+            return;
+        }
+        match self.pending.as_ref().and_then(request_team) {
+            Some(team) if role.plays(team) => {
+                if let FrontToBackMessage::Undo = msg {
+                    // Taking back a turn also takes back the opponent's reply, which only they can agree to
+                    if self.open_seats.contains(&team.other()) {
+                        self.log_to(id, "You can't take back moves against another player".to_string());
+                        self.send_to(id, self.pending.clone().unwrap());
+                        return;
+                    }
+                    // Mirror the game: the player's last turn and everything after it are taken back
+                    if let Some(idx) = self.history.iter().rposition(|(turn_team, _)| *turn_team == team) {
+                        self.history.truncate(idx);
+                    }
+                }
+                if let FrontToBackMessage::Move(mv) = &msg {
+                    if !self.is_legal(mv) {
+                        self.log_to(id, format!("Illegal move: {}", mv));
+                        // Start their selection over
+                        self.send_to(id, self.pending.clone().unwrap());
+                        return;
+                    }
+                }
+                // A move answers a request, while anything else is answered by the game with a new one
+                self.pending = None;
+                self.game_tx.send(msg).ok();
+            },
+            _ => self.log_to(id, "It's not your turn".to_string()),
+        }
+    }
+
+    /// Whether `mv` answers the pending request.  Connections can't be trusted to only send moves the game allows.
+    fn is_legal(&self, mv: &Move) -> bool {
+        match (&self.pending, &self.board) {
+            (Some(BackToFrontMessage::RequestMove(team)), Some(board)) => board.get_all_valid_moves(*team).contains(mv),
+            (Some(BackToFrontMessage::RequestJump(_, _, moves)), _) => moves.contains(mv),
+            _ => false,
+        }
+    }
+
+    fn client_ids(&self) -> Vec<usize> {
+        (0..self.clients.len()).filter(|id| self.clients[*id].is_some()).collect()
+    }
+
+    fn client_role(&self, id: usize) -> Option<Role> {
+        self.clients.get(id).and_then(|client| client.as_ref()).map(|client| client.role)
+    }
+
+    fn send_to(&self, id: usize, msg: BackToFrontMessage) {
+        if let Some(Some(client)) = self.clients.get(id) {
+            client.tx.send(msg).ok();
+        }
+    }
+
+    fn log_to(&self, id: usize, msg: String) {
+        self.send_to(id, BackToFrontMessage::Log{ level: LogLevel::Info, msg });
+    }
+
+    fn broadcast_log(&self, msg: String) {
+        for id in self.client_ids() {
+            self.log_to(id, msg.clone());
+        }
+    }
+}
+
+fn request_team(msg: &BackToFrontMessage) -> Option<Team> {
+    match msg {
+        BackToFrontMessage::RequestMove(team) | BackToFrontMessage::RequestJump(team, ..) => Some(*team),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::notation;
+    use crate::frontend::Preferences;
+
+    fn recv_until<F: Fn(&BackToFrontMessage) -> bool>(rx: &mpsc::Receiver<BackToFrontMessage>, found: F) -> BackToFrontMessage {
+        loop {
+            let msg = rx.recv_timeout(Duration::from_secs(5)).expect("Timed out waiting for message");
+            if found(&msg) {
+                return msg;
+            }
+        }
+    }
+
+    fn turn(path: &str) -> Vec<FrontToBackMessage> {
+        let squares = notation::parse_path(path).unwrap();
+        squares.windows(2).map(|pair| FrontToBackMessage::Move(crate::backend::Move{ from: pair[0], to: pair[1] })).collect()
+    }
+
+    #[test]
+    fn test_host_and_join() {
+        let args = Args::default();
+        let host_endpoint = host(args, NetArgs{ addr: "127.0.0.1:0".to_string(), team: Team::Light }).unwrap();
+        let addr = match recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ .. })) {
+            BackToFrontMessage::Log{ msg, .. } => msg.rsplit(' ').next().unwrap().to_string(),
+            _ => unreachable!(),
+        };
+
+        host_endpoint.tx.send(FrontToBackMessage::StartGame(Preferences{ players: ["Human", "CPU"] })).unwrap();
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Light)));
+
+        // Joining mid-turn is fine, the joiner gets the board straight away
+        let join_endpoint = join(&addr, Hello::Play(None)).unwrap();
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::BoardState(_)));
+
+        // Moving out of turn is refused
+        join_endpoint.tx.send(turn("9-13").remove(0)).unwrap();
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg == "It's not your turn"));
+
+        // Moves the game doesn't allow never reach it, and the player is asked again
+        host_endpoint.tx.send(turn("22-15").remove(0)).unwrap();
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg.starts_with("Illegal move")));
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Light)));
+
+        for msg in turn("22-18") {
+            host_endpoint.tx.send(msg).unwrap();
+        }
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::MoveMade(Team::Light, _)));
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Dark)));
+
+        // The same goes for the joiner, whose moves would otherwise reach the host's game unchecked
+        join_endpoint.tx.send(turn("9-18").remove(0)).unwrap();
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg.starts_with("Illegal move")));
+        recv_until(&join_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Dark)));
+
+        for msg in turn("9-13") {
+            join_endpoint.tx.send(msg).unwrap();
+        }
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::MoveMade(Team::Dark, _)));
+
+        // Neither player can take back the other's reply
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Light)));
+        host_endpoint.tx.send(FrontToBackMessage::Undo).unwrap();
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg.contains("can't take back")));
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::RequestMove(Team::Light)));
+
+        // Late observers catch up on the moves so far, and can't play
+        let observer = join(&addr, Hello::Watch).unwrap();
+        recv_until(&observer.rx, |msg| matches!(msg, BackToFrontMessage::MoveMade(Team::Light, _)));
+        recv_until(&observer.rx, |msg| matches!(msg, BackToFrontMessage::MoveMade(Team::Dark, _)));
+        recv_until(&observer.rx, |msg| matches!(msg, BackToFrontMessage::BoardState(_)));
+        observer.tx.send(FrontToBackMessage::Resign).unwrap();
+        recv_until(&observer.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg == "Observers can't play"));
+
+        // So does anyone asking to play once both seats are taken
+        let late_player = join(&addr, Hello::Play(None)).unwrap();
+        recv_until(&late_player.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg.contains("you are watching")));
+
+        // The host hears about the joiner leaving
+        drop(join_endpoint);
+        recv_until(&host_endpoint.rx, |msg| matches!(msg, BackToFrontMessage::Log{ msg, .. } if msg.contains("left")));
+    }
+}
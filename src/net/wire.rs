@@ -0,0 +1,406 @@
+//! Line-based text encoding of the `channel` messages, one message per line.
+//!
+//! Squares are written `x,y`, moves `x,y:x,y`, teams `light`/`dark` and times in milliseconds.  Boards are 64
+//! characters row by row (`.` empty, `l`/`d` men and `L`/`D` kings).
+//!
+//! From the game to a player:
+//! - `log <text>`, `log-ai <text>` or `log-debug <text>`: a message for the log at that level
+//! - `board <squares> <casual|american>`: the whole board and the rules in play
+//! - `request-move <team>`: it's that team's turn
+//! - `request-jump <team> <square> <moves>...`: the piece on that square must carry on jumping with one of the moves
+//! - `move-made <team> <moves>...`: a turn was played
+//! - `clock <sudden|fischer|bronstein> <base> <bonus> <light> <dark> <light|dark|->`: time control, time left and
+//!   whose clock is running
+//! - `draw-offered <team>`: that team offers a draw
+//! - `game-over <light|dark|draw> <no-pieces|no-moves|resigned|out-of-time|draw-agreed>`: the winner and why
+//!
+//! From a player to the game:
+//! - `start <Human|CPU> <Human|CPU>`: start a game with these light and dark players
+//! - `move <move>`, `cancel`, `undo`, `resign` and `offer-draw`
+//! - `answer-draw <yes|no>`
+//!
+//! Connections open with a `hello` line from the joining side.  Browsers speak the same protocol over a WebSocket, one
+//! line per text message.
+
+use crate::backend::{Board, ClockState, EndReason, GameResult, Move, Piece, PieceType, Square, Team, TimeControl, Variant};
+use crate::channel::{BackToFrontMessage, FrontToBackMessage, LogLevel};
+use crate::frontend::Preferences;
+
+use std::io;
+use std::time::Duration;
+
+/// First line sent by a joining connection: `hello` to take any free seat, `hello light`/`hello dark` for a
+/// particular one, or `hello observer` only to watch.
+const HELLO: &str = "hello";
+const OBSERVER: &str = "observer";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hello {
+    /// Play the given side, or whichever is free
+    Play(Option<Team>),
+    Watch,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn encode_hello(hello: Hello) -> String {
+    match hello {
+        Hello::Play(None) => HELLO.to_string(),
+        Hello::Play(Some(team)) => format!("{} {}", HELLO, team_str(team)),
+        Hello::Watch => format!("{} {}", HELLO, OBSERVER),
+    }
+}
+
+/// `None` if this isn't a hello.
+pub fn decode_hello(line: &str) -> Option<Hello> {
+    match split_command(line) {
+        (HELLO, "") => Some(Hello::Play(None)),
+        (HELLO, OBSERVER) => Some(Hello::Watch),
+        (HELLO, team) => parse_team(team).ok().map(|team| Hello::Play(Some(team))),
+        _ => None,
+    }
+}
+
+fn team_str(team: Team) -> &'static str {
+    match team {
+        Team::Light => "light",
+        Team::Dark  => "dark",
+    }
+}
+
+fn parse_team(text: &str) -> io::Result<Team> {
+    match text {
+        "light" => Ok(Team::Light),
+        "dark"  => Ok(Team::Dark),
+        _ => Err(invalid(format!("bad team '{}'", text))),
+    }
+}
+
+fn square_str(square: &Square) -> String {
+    format!("{},{}", square.x, square.y)
+}
+
+fn parse_square(text: &str) -> io::Result<Square> {
+    let mut parts = text.splitn(2, ',').map(|part| part.parse::<i8>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y))) => Ok(Square{ x, y }),
+        _ => Err(invalid(format!("bad square '{}'", text))),
+    }
+}
+
+fn move_str(mv: &Move) -> String {
+    format!("{}:{}", square_str(&mv.from), square_str(&mv.to))
+}
+
+fn parse_move(text: &str) -> io::Result<Move> {
+    let mut parts = text.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(from), Some(to)) => Ok(Move{ from: parse_square(from)?, to: parse_square(to)? }),
+        _ => Err(invalid(format!("bad move '{}'", text))),
+    }
+}
+
+fn moves_str(moves: &[Move]) -> String {
+    moves.iter().map(move_str).collect::<Vec<_>>().join(" ")
+}
+
+fn board_str(board: &Board) -> String {
+    let mut result = String::new();
+    for y in 0..Board::SIZE {
+        for x in 0..Board::SIZE {
+            result.push(match board.get_piece_at(&Square{ x, y }) {
+                Some(Piece{ team: Team::Light, piece_type: PieceType::Man })  => 'l',
+                Some(Piece{ team: Team::Light, piece_type: PieceType::King }) => 'L',
+                Some(Piece{ team: Team::Dark,  piece_type: PieceType::Man })  => 'd',
+                Some(Piece{ team: Team::Dark,  piece_type: PieceType::King }) => 'D',
+                None => '.',
+            });
+        }
+    }
+    result
+}
+
+fn parse_board(text: &str) -> io::Result<Board> {
+    let size = Board::SIZE as usize;
+    if text.chars().count() != size * size {
+        return Err(invalid(format!("board must have {} squares", size * size)));
+    }
+
+    let mut board = Board::empty();
+    for (idx, c) in text.chars().enumerate() {
+        let (team, piece_type) = match c {
+            'l' => (Team::Light, PieceType::Man),
+            'L' => (Team::Light, PieceType::King),
+            'd' => (Team::Dark,  PieceType::Man),
+            'D' => (Team::Dark,  PieceType::King),
+            '.' => continue,
+            _ => return Err(invalid(format!("bad board square '{}'", c))),
+        };
+        let square = Square{ x: (idx % size) as i8, y: (idx / size) as i8 };
+        board.place_piece(square, Piece{ team, piece_type });
+    }
+    Ok(board)
+}
+
+fn clock_str(state: &ClockState) -> String {
+    let (kind, bonus) = match state.control {
+        TimeControl::SuddenDeath{ .. } => ("sudden", Duration::from_secs(0)),
+        TimeControl::Fischer{ increment, .. } => ("fischer", increment),
+        TimeControl::Bronstein{ delay, .. } => ("bronstein", delay),
+    };
+    let running = state.running.map_or("-", team_str);
+    format!("{} {} {} {} {} {}", kind, state.control.base().as_millis(), bonus.as_millis(),
+            state.remaining[0].as_millis(), state.remaining[1].as_millis(), running)
+}
+
+fn parse_clock(words: &[&str]) -> io::Result<ClockState> {
+    let millis = |text: &str| text.parse::<u64>().map(Duration::from_millis)
+        .map_err(|_| invalid(format!("bad time '{}'", text)));
+    match words {
+        [kind, base, bonus, light, dark, running] => {
+            let (base, bonus) = (millis(base)?, millis(bonus)?);
+            let control = match *kind {
+                "sudden" => TimeControl::SuddenDeath{ base },
+                "fischer" => TimeControl::Fischer{ base, increment: bonus },
+                "bronstein" => TimeControl::Bronstein{ base, delay: bonus },
+                _ => return Err(invalid(format!("bad time control '{}'", kind))),
+            };
+            let running = match *running {
+                "-" => None,
+                team => Some(parse_team(team)?),
+            };
+            Ok(ClockState{ control, remaining: [millis(light)?, millis(dark)?], running })
+        },
+        _ => Err(invalid(format!("bad clock '{}'", words.join(" ")))),
+    }
+}
+
+const END_REASONS: &[(EndReason, &str)] = &[
+    (EndReason::NoPieces,   "no-pieces"),
+    (EndReason::NoMoves,    "no-moves"),
+    (EndReason::Resigned,   "resigned"),
+    (EndReason::OutOfTime,  "out-of-time"),
+    (EndReason::DrawAgreed, "draw-agreed"),
+];
+
+fn result_str(result: &GameResult) -> String {
+    let winner = result.winner.map_or("draw", team_str);
+    let reason = END_REASONS.iter().find(|(reason, _)| *reason == result.reason).unwrap().1;
+    format!("{} {}", winner, reason)
+}
+
+fn parse_result(winner: &str, reason: &str) -> io::Result<GameResult> {
+    let result = GameResult {
+        winner: match winner {
+            "draw" => None,
+            team => Some(parse_team(team)?),
+        },
+        reason: END_REASONS.iter().find(|(_, name)| *name == reason)
+            .ok_or_else(|| invalid(format!("bad game end reason '{}'", reason)))?.0,
+    };
+    if !result.is_consistent() {
+        return Err(invalid(format!("game end reason '{}' doesn't go with '{}'", reason, winner)));
+    }
+    Ok(result)
+}
+
+/// Command for each log level.  Plain `log` is info so older clients still show it.
+const LOG_COMMANDS: [(LogLevel, &str); 3] = [
+    (LogLevel::Info,  "log"),
+    (LogLevel::Ai,    "log-ai"),
+    (LogLevel::Debug, "log-debug"),
+];
+
+fn log_command(level: LogLevel) -> &'static str {
+    LOG_COMMANDS.iter().find(|(l, _)| *l == level).expect("Every level has a command").1
+}
+
+pub fn encode_back(msg: &BackToFrontMessage) -> String {
+    match msg {
+        // Log messages are always one line, but don't let a stray newline break the framing
+        BackToFrontMessage::Log{ level, msg }                => format!("{} {}", log_command(*level), msg.replace('\n', " ")),
+        BackToFrontMessage::BoardState(board)                => {
+            format!("board {} {}", board_str(board), board.variant().to_string().to_lowercase())
+        },
+        BackToFrontMessage::RequestMove(team)                => format!("request-move {}", team_str(*team)),
+        BackToFrontMessage::RequestJump(team, square, moves) => {
+            format!("request-jump {} {} {}", team_str(*team), square_str(square), moves_str(moves)).trim_end().to_string()
+        },
+        BackToFrontMessage::MoveMade(team, moves)            => format!("move-made {} {}", team_str(*team), moves_str(moves)),
+        BackToFrontMessage::Clock(state)                     => format!("clock {}", clock_str(state)),
+        BackToFrontMessage::DrawOffered(team)                => format!("draw-offered {}", team_str(*team)),
+        BackToFrontMessage::GameOver(result)                 => format!("game-over {}", result_str(result)),
+    }
+}
+
+pub fn decode_back(line: &str) -> io::Result<BackToFrontMessage> {
+    let (command, rest) = split_command(line);
+    let mut words = rest.split_whitespace();
+    let mut next_word = || words.next().ok_or_else(|| invalid(format!("'{}' is missing arguments", line)));
+
+    if let Some((level, _)) = LOG_COMMANDS.iter().find(|(_, name)| *name == command) {
+        return Ok(BackToFrontMessage::Log{ level: *level, msg: rest.to_string() });
+    }
+    match command {
+        "board" => {
+            let board = parse_board(next_word()?)?;
+            // Older hosts don't say, and only played casual rules
+            let variant = match words.next() {
+                Some(variant) => variant.parse::<Variant>().map_err(invalid)?,
+                None => Variant::Casual,
+            };
+            Ok(BackToFrontMessage::BoardState(board.with_variant(variant)))
+        },
+        "request-move" => Ok(BackToFrontMessage::RequestMove(parse_team(next_word()?)?)),
+        "request-jump" => {
+            let team = parse_team(next_word()?)?;
+            let square = parse_square(next_word()?)?;
+            let moves = words.map(parse_move).collect::<io::Result<Vec<_>>>()?;
+            Ok(BackToFrontMessage::RequestJump(team, square, moves))
+        },
+        "move-made" => {
+            let team = parse_team(next_word()?)?;
+            let moves = words.map(parse_move).collect::<io::Result<Vec<_>>>()?;
+            Ok(BackToFrontMessage::MoveMade(team, moves))
+        },
+        "clock" => Ok(BackToFrontMessage::Clock(parse_clock(&words.collect::<Vec<_>>())?)),
+        "draw-offered" => Ok(BackToFrontMessage::DrawOffered(parse_team(next_word()?)?)),
+        "game-over" => {
+            let winner = next_word()?;
+            Ok(BackToFrontMessage::GameOver(parse_result(winner, next_word()?)?))
+        },
+        _ => Err(invalid(format!("unknown message '{}'", line))),
+    }
+}
+
+pub fn encode_front(msg: &FrontToBackMessage) -> String {
+    match msg {
+        FrontToBackMessage::StartGame(prefs) => {
+            format!("start {} {}", prefs.players[0], prefs.players[1])
+        },
+        FrontToBackMessage::Move(mv) => format!("move {}", move_str(mv)),
+        FrontToBackMessage::CancelMove => "cancel".to_string(),
+        FrontToBackMessage::Undo => "undo".to_string(),
+        FrontToBackMessage::Resign => "resign".to_string(),
+        FrontToBackMessage::OfferDraw => "offer-draw".to_string(),
+        FrontToBackMessage::AnswerDraw(accepted) => format!("answer-draw {}", if *accepted { "yes" } else { "no" }),
+    }
+}
+
+pub fn decode_front(line: &str) -> io::Result<FrontToBackMessage> {
+    let (command, rest) = split_command(line);
+    let words: Vec<&str> = rest.split_whitespace().collect();
+
+    match (command, words.as_slice()) {
+        ("start", [light, dark]) => {
+            let player = |name: &str| ["Human", "CPU"].iter().copied().find(|p| *p == name)
+                .ok_or_else(|| invalid(format!("unknown player type '{}'", name)));
+            Ok(FrontToBackMessage::StartGame(Preferences{ players: [player(light)?, player(dark)?] }))
+        },
+        ("move", [mv]) => Ok(FrontToBackMessage::Move(parse_move(mv)?)),
+        ("cancel", []) => Ok(FrontToBackMessage::CancelMove),
+        ("undo", []) => Ok(FrontToBackMessage::Undo),
+        ("resign", []) => Ok(FrontToBackMessage::Resign),
+        ("offer-draw", []) => Ok(FrontToBackMessage::OfferDraw),
+        ("answer-draw", ["yes"]) => Ok(FrontToBackMessage::AnswerDraw(true)),
+        ("answer-draw", ["no"]) => Ok(FrontToBackMessage::AnswerDraw(false)),
+        _ => Err(invalid(format!("unknown message '{}'", line))),
+    }
+}
+
+fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim_end_matches(['\r', '\n']);
+    match line.find(' ') {
+        Some(idx) => (&line[..idx], &line[idx + 1..]),
+        None => (line, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(from: (i8, i8), to: (i8, i8)) -> Move {
+        Move{ from: Square{ x: from.0, y: from.1 }, to: Square{ x: to.0, y: to.1 } }
+    }
+
+    #[test]
+    fn test_hello() {
+        for hello in [Hello::Play(None), Hello::Play(Some(Team::Dark)), Hello::Watch].iter() {
+            assert_eq!(decode_hello(&encode_hello(*hello)), Some(*hello));
+        }
+        assert_eq!(encode_hello(Hello::Play(Some(Team::Light))), "hello light");
+        assert_eq!(decode_hello("hello blue"), None);
+        assert_eq!(decode_hello("move 2,5:3,4"), None);
+    }
+
+    #[test]
+    fn test_back_messages() {
+        let board = Board::new();
+        match decode_back(&encode_back(&BackToFrontMessage::BoardState(board.clone()))).unwrap() {
+            BackToFrontMessage::BoardState(decoded) => assert!(decoded == board),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        let american = board.clone().with_variant(Variant::American);
+        match decode_back(&encode_back(&BackToFrontMessage::BoardState(american.clone()))).unwrap() {
+            BackToFrontMessage::BoardState(decoded) => assert!(decoded == american),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+
+        let messages = [
+            BackToFrontMessage::Log{ level: LogLevel::Info, msg: "Player 0's turn".to_string() },
+            BackToFrontMessage::Log{ level: LogLevel::Ai, msg: "Depth 6, score 12".to_string() },
+            BackToFrontMessage::RequestMove(Team::Dark),
+            BackToFrontMessage::RequestJump(Team::Light, Square{ x: 3, y: 4 }, vec![mv((3, 4), (5, 2))]),
+            BackToFrontMessage::MoveMade(Team::Light, vec![mv((1, 6), (3, 4)), mv((3, 4), (5, 2))]),
+            BackToFrontMessage::Clock(ClockState {
+                control: TimeControl::Fischer{ base: Duration::from_secs(180), increment: Duration::from_secs(2) },
+                remaining: [Duration::from_millis(170_250), Duration::from_secs(180)],
+                running: Some(Team::Dark),
+            }),
+            BackToFrontMessage::DrawOffered(Team::Light),
+            BackToFrontMessage::GameOver(GameResult{ winner: Some(Team::Dark), reason: EndReason::OutOfTime }),
+            BackToFrontMessage::GameOver(GameResult{ winner: None, reason: EndReason::DrawAgreed }),
+        ];
+        for msg in messages.iter() {
+            let encoded = encode_back(msg);
+            assert_eq!(encode_back(&decode_back(&encoded).unwrap()), encoded);
+        }
+        assert_eq!(encode_back(&messages[0]), "log Player 0's turn");
+        assert_eq!(encode_back(&messages[1]), "log-ai Depth 6, score 12");
+        assert_eq!(encode_back(&messages[4]), "move-made light 1,6:3,4 3,4:5,2");
+        assert_eq!(encode_back(&messages[5]), "clock fischer 180000 2000 170250 180000 dark");
+        assert!(decode_back("clock sudden 60000 0 1000 1000 -").is_ok());
+        assert!(decode_back("clock sudden 60000 0 1000").is_err());
+        assert_eq!(encode_back(&messages[8]), "game-over draw draw-agreed");
+        assert!(decode_back("game-over light bored").is_err());
+        assert!(decode_back("game-over light draw-agreed").is_err());
+        assert!(decode_back("game-over draw resigned").is_err());
+        assert_eq!(GameResult{ winner: Some(Team::Light), reason: EndReason::DrawAgreed }.to_string(), "Draw agreed");
+
+        assert!(decode_back("board ...").is_err());
+        assert!(decode_back("request-move blue").is_err());
+        assert!(decode_back("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_front_messages() {
+        let start = FrontToBackMessage::StartGame(Preferences{ players: ["Human", "CPU"] });
+        assert_eq!(encode_front(&start), "start Human CPU");
+
+        for msg in [start, FrontToBackMessage::Move(mv((2, 5), (3, 4))), FrontToBackMessage::CancelMove,
+                    FrontToBackMessage::Undo, FrontToBackMessage::Resign, FrontToBackMessage::OfferDraw,
+                    FrontToBackMessage::AnswerDraw(true), FrontToBackMessage::AnswerDraw(false)].iter() {
+            let encoded = encode_front(msg);
+            assert_eq!(encode_front(&decode_front(&encoded).unwrap()), encoded);
+        }
+
+        assert!(decode_front("start Human Robot").is_err());
+        assert!(decode_front("start Human CPU WhiteRed 1").is_err());
+        assert!(decode_front("move 2,5").is_err());
+        assert!(decode_front("undo now").is_err());
+        assert!(decode_front("answer-draw maybe").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! JSON API for bots and test scripts.  Games live in memory and are driven directly through `Board`, so a client
+//! can play at its own pace without holding a connection open.
+//!
+//! ```text
+//! GET    /games                 ids of every game
+//! POST   /games                 create a game: {"variant", "players": {"light", "dark"}, "ai": {"depth", "time_ms"}}
+//! GET    /games/{id}            position, side to move, history and result
+//! DELETE /games/{id}
+//! GET    /games/{id}/moves      legal moves for the side to move, single hops and whole turns
+//! POST   /games/{id}/moves      play a turn: {"moves": [{"from", "to"}, ...]} or {"notation": "11-15"}
+//! POST   /games/{id}/undo       take back turns until it is a human's move again
+//! POST   /games/{id}/ai         search for the side to move: {"depth", "time_ms", "play"}
+//! ```
+//!
+//! After a turn is played, any CPU player replies straight away.  Searches stop after `MAX_SEARCH_TIME` whatever the
+//! client asks for.  Errors come back as `{"error": "..."}`.
+
+use super::http::{self, Request};
+
+use crate::backend::{notation, Ai, Board, Move, SearchInfo, SearchLimits, Team, Variant, DEFAULT_DEPTH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8081";
+/// The only rules served: 8x8 English draughts / American checkers
+const VARIANT: &str = "american";
+const VARIANT_ALIASES: &[&str] = &[VARIANT, "checkers", "english", "draughts"];
+/// Longest a search may hold a game's lock
+const MAX_SEARCH_TIME: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PlayerKind {
+    Human,
+    Cpu,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Players {
+    light: PlayerKind,
+    dark: PlayerKind,
+}
+impl Default for Players {
+    fn default() -> Players {
+        Players{ light: PlayerKind::Human, dark: PlayerKind::Cpu }
+    }
+}
+impl Players {
+    fn get(&self, team: Team) -> PlayerKind {
+        match team {
+            Team::Light => self.light,
+            Team::Dark  => self.dark,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AiSettings {
+    depth: Option<usize>,
+    time_ms: Option<u64>,
+}
+impl AiSettings {
+    fn limits(&self) -> SearchLimits {
+        SearchLimits {
+            depth: self.depth,
+            time: self.time_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NewGame {
+    variant: Option<String>,
+    players: Players,
+    ai: AiSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TurnRequest {
+    moves: Option<Vec<Move>>,
+    notation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AiRequest {
+    depth: Option<usize>,
+    time_ms: Option<u64>,
+    /// Play the move found, rather than only reporting it
+    play: bool,
+}
+impl Default for AiRequest {
+    fn default() -> AiRequest {
+        AiRequest{ depth: None, time_ms: None, play: true }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TurnView {
+    team: Team,
+    moves: Vec<Move>,
+    notation: String,
+}
+impl TurnView {
+    fn new(team: Team, moves: Vec<Move>) -> TurnView {
+        let notation = notation::format_path(&moves);
+        TurnView{ team, moves, notation }
+    }
+}
+
+struct Turn {
+    view: TurnView,
+    board_before: Board,
+}
+
+struct ApiGame {
+    id: u64,
+    players: Players,
+    ai: AiSettings,
+    board: Board,
+    to_move: Team,
+    history: Vec<Turn>,
+}
+impl ApiGame {
+    fn winner(&self) -> Option<Team> {
+        if self.board.get_all_valid_moves(self.to_move).is_empty() {
+            Some(self.to_move.other())
+        } else {
+            None
+        }
+    }
+
+    fn view(&self) -> Value {
+        let winner = self.winner();
+        json!({
+            "id": self.id,
+            "variant": VARIANT,
+            "players": self.players,
+            "ai": self.ai,
+            "board": self.board,
+            "to_move": self.to_move,
+            "status": if winner.is_some() { "finished" } else { "in_progress" },
+            "winner": winner,
+            "history": self.history.iter().map(|turn| &turn.view).collect::<Vec<_>>(),
+        })
+    }
+
+    fn play(&mut self, moves: Vec<Move>) -> TurnView {
+        let board_before = self.board.clone();
+        for mv in moves.iter() {
+            self.board.apply_move(mv);
+        }
+        let view = TurnView::new(self.to_move, moves);
+        self.history.push(Turn{ view: view.clone(), board_before });
+        self.to_move = self.to_move.other();
+        view
+    }
+
+    fn search(&self, limits: SearchLimits) -> Option<SearchInfo> {
+        Ai{ team: self.to_move }.search(self.board.clone(), capped(limits), None, |_| ())
+    }
+
+    /// Lets CPU players take their turns until a human is to move or the game ends.
+    fn play_cpu_turns(&mut self) -> Vec<TurnView> {
+        let mut played = Vec::new();
+        while self.players.get(self.to_move) == PlayerKind::Cpu && self.winner().is_none() {
+            match self.search(self.ai.limits()) {
+                Some(info) => played.push(self.play(info.moves)),
+                None => break,
+            }
+        }
+        played
+    }
+}
+
+/// `limits`, but never searching for longer than `MAX_SEARCH_TIME`
+fn capped(limits: SearchLimits) -> SearchLimits {
+    SearchLimits {
+        // A time limit alone would mean searching as deep as it allows, rather than the usual depth
+        depth: limits.depth.or(Some(DEFAULT_DEPTH).filter(|_| limits.time.is_none())),
+        time: Some(limits.time.map_or(MAX_SEARCH_TIME, |time| time.min(MAX_SEARCH_TIME))),
+    }
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+impl ApiError {
+    fn new(status: u16, message: String) -> ApiError {
+        ApiError{ status, message }
+    }
+}
+
+type ApiResult = Result<(u16, Value), ApiError>;
+
+fn parse_body<'a, T: Deserialize<'a> + Default>(request: &'a Request) -> Result<T, ApiError> {
+    if request.body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(T::default());
+    }
+    serde_json::from_slice(&request.body).map_err(|err| ApiError::new(400, format!("Bad request body: {}", err)))
+}
+
+fn search_view(info: &SearchInfo) -> Value {
+    json!({
+        "depth": info.depth,
+        "score": info.score,
+        "nodes": info.nodes,
+        "elapsed_ms": info.elapsed.as_millis() as u64,
+        "moves": info.moves,
+        "notation": notation::format_path(&info.moves),
+    })
+}
+
+#[derive(Default)]
+pub struct Api {
+    games: Mutex<HashMap<u64, Arc<Mutex<ApiGame>>>>,
+    next_id: Mutex<u64>,
+}
+impl Api {
+    pub fn new() -> Api {
+        Api::default()
+    }
+
+    pub fn handle(&self, request: &Request) -> (u16, Value) {
+        match self.route(request) {
+            Ok(response) => response,
+            Err(err) => (err.status, json!({ "error": err.message })),
+        }
+    }
+
+    fn route(&self, request: &Request) -> ApiResult {
+        let parts: Vec<&str> = request.path.split('/').filter(|part| !part.is_empty()).collect();
+        let method = request.method.as_str();
+        match parts.as_slice() {
+            ["games"] => match method {
+                "GET" => Ok((200, json!({ "games": self.game_ids() }))),
+                "POST" => self.create_game(parse_body(request)?),
+                _ => Err(ApiError::new(405, format!("{} not allowed on /games", method))),
+            },
+            ["games", id, rest @ ..] => {
+                let id = id.parse::<u64>().map_err(|_| ApiError::new(404, format!("No game '{}'", id)))?;
+                if let ([], "DELETE") = (rest, method) {
+                    return match self.games.lock().unwrap().remove(&id) {
+                        Some(_) => Ok((200, json!({ "deleted": id }))),
+                        None => Err(ApiError::new(404, format!("No game {}", id))),
+                    };
+                }
+
+                let game = self.games.lock().unwrap().get(&id).cloned()
+                    .ok_or_else(|| ApiError::new(404, format!("No game {}", id)))?;
+                let mut game = game.lock().unwrap();
+                match (rest, method) {
+                    ([], "GET") => Ok((200, game.view())),
+                    (["moves"], "GET") => Ok((200, legal_moves(&game))),
+                    (["moves"], "POST") => play_turn(&mut game, parse_body(request)?),
+                    (["undo"], "POST") => undo(&mut game),
+                    (["ai"], "POST") => ai_move(&mut game, parse_body(request)?),
+                    _ => Err(ApiError::new(405, format!("{} not allowed on {}", method, request.path))),
+                }
+            },
+            _ => Err(ApiError::new(404, format!("Nothing at {}", request.path))),
+        }
+    }
+
+    fn game_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.games.lock().unwrap().keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn create_game(&self, new_game: NewGame) -> ApiResult {
+        if let Some(variant) = &new_game.variant {
+            if !VARIANT_ALIASES.contains(&variant.to_lowercase().as_str()) {
+                return Err(ApiError::new(400, format!("Unsupported variant '{}', only '{}' is available", variant, VARIANT)));
+            }
+        }
+
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let mut game = ApiGame {
+            id,
+            players: new_game.players,
+            ai: new_game.ai,
+            board: Board::new().with_variant(Variant::American),
+            to_move: Variant::American.first_team(),
+            history: Vec::new(),
+        };
+        let played = game.play_cpu_turns();
+        let view = game.view();
+        self.games.lock().unwrap().insert(id, Arc::new(Mutex::new(game)));
+
+        Ok((201, json!({ "game": view, "played": played })))
+    }
+}
+
+fn legal_moves(game: &ApiGame) -> Value {
+    let turns: Vec<TurnView> = game.board.get_all_valid_turns(game.to_move)
+        .into_iter()
+        .map(|turn| TurnView::new(game.to_move, turn))
+        .collect();
+    json!({
+        "to_move": game.to_move,
+        "moves": game.board.get_all_valid_moves(game.to_move),
+        "turns": turns,
+    })
+}
+
+fn play_turn(game: &mut ApiGame, request: TurnRequest) -> ApiResult {
+    if game.winner().is_some() {
+        return Err(ApiError::new(409, "The game is over".to_string()));
+    }
+    if game.players.get(game.to_move) == PlayerKind::Cpu {
+        return Err(ApiError::new(409, format!("{:?} is played by the CPU", game.to_move)));
+    }
+
+    let moves = match (request.moves, request.notation) {
+        (Some(moves), None) => moves,
+        (None, Some(text)) => {
+            let squares = notation::parse_path(&text).map_err(|err| ApiError::new(400, err.to_string()))?;
+            squares.windows(2).map(|pair| Move{ from: pair[0], to: pair[1] }).collect()
+        },
+        _ => return Err(ApiError::new(400, "Give either \"moves\" or \"notation\"".to_string())),
+    };
+    if !game.board.get_all_valid_turns(game.to_move).contains(&moves) {
+        return Err(ApiError::new(400, format!("Illegal turn for {:?}: {}", game.to_move, notation::format_path(&moves))));
+    }
+
+    let mut played = vec![game.play(moves)];
+    played.extend(game.play_cpu_turns());
+    Ok((200, json!({ "game": game.view(), "played": played })))
+}
+
+fn undo(game: &mut ApiGame) -> ApiResult {
+    // Back to before the last human turn, leaving any CPU turns ahead of it
+    let players = game.players;
+    let idx = game.history.iter().rposition(|turn| players.get(turn.view.team) == PlayerKind::Human)
+        .ok_or_else(|| ApiError::new(409, "Nothing to undo".to_string()))?;
+    let mut undone = Vec::new();
+    for turn in game.history.drain(idx..).rev() {
+        game.board = turn.board_before;
+        game.to_move = turn.view.team;
+        undone.push(turn.view);
+    }
+    Ok((200, json!({ "game": game.view(), "undone": undone })))
+}
+
+fn ai_move(game: &mut ApiGame, request: AiRequest) -> ApiResult {
+    let limits = match (request.depth, request.time_ms) {
+        (None, None) => game.ai.limits(),
+        (depth, time_ms) => SearchLimits{ depth, time: time_ms.map(Duration::from_millis) },
+    };
+    let info = game.search(limits).ok_or_else(|| ApiError::new(409, "The game is over".to_string()))?;
+
+    let mut played = Vec::new();
+    if request.play {
+        played.push(game.play(info.moves.clone()));
+        played.extend(game.play_cpu_turns());
+    }
+    Ok((200, json!({ "search": search_view(&info), "game": game.view(), "played": played })))
+}
+
+pub fn run(addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Serving the JSON API on http://{}/games", listener.local_addr()?);
+
+    let api = Arc::new(Api::new());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let api = Arc::clone(&api);
+        thread::spawn(move || {
+            handle_connection(stream, &api).ok();
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, api: &Api) -> io::Result<()> {
+    let (request, _) = http::accept(&stream)?;
+    let (status, body) = api.handle(&request);
+    http::respond(&mut stream, status, "application/json", &body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: HashMap::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_human_against_cpu() {
+        let api = Api::new();
+        let (status, created) = api.handle(&request("POST", "/games", r#"{"players": {"light": "human", "dark": "cpu"}, "ai": {"depth": 2}}"#));
+        assert_eq!(status, 201);
+        // Dark moves first in American checkers, so the CPU already has
+        assert_eq!(created["played"].as_array().unwrap().len(), 1);
+        assert_eq!(created["game"]["variant"], "american");
+        assert_eq!(created["game"]["to_move"], "Light");
+        assert_eq!(created["game"]["board"]["pieces"].as_array().unwrap().len(), 24);
+
+        let (status, moves) = api.handle(&request("GET", "/games/1/moves", ""));
+        assert_eq!(status, 200);
+        assert_eq!(moves["moves"].as_array().unwrap().len(), 7);
+        assert!(moves["turns"].as_array().unwrap().iter().any(|turn| turn["notation"] == "22-18"));
+
+        // The CPU answers straight away
+        let (status, played) = api.handle(&request("POST", "/games/1/moves", r#"{"notation": "22-18"}"#));
+        assert_eq!(status, 200);
+        assert_eq!(played["played"].as_array().unwrap().len(), 2);
+        assert_eq!(played["game"]["to_move"], "Light");
+
+        // Undo takes back the CPU's reply too, but not its opening move
+        let (status, undone) = api.handle(&request("POST", "/games/1/undo", ""));
+        assert_eq!(status, 200);
+        assert_eq!(undone["undone"].as_array().unwrap().len(), 2);
+        assert_eq!(undone["game"]["history"].as_array().unwrap().len(), 1);
+        assert_eq!(api.handle(&request("POST", "/games/1/undo", "")).0, 409);
+    }
+
+    #[test]
+    fn test_ai_and_errors() {
+        let api = Api::new();
+        api.handle(&request("POST", "/games", r#"{"variant": "american", "players": {"light": "human", "dark": "human"}}"#));
+
+        let (status, result) = api.handle(&request("POST", "/games/1/ai", r#"{"time_ms": 50, "play": false}"#));
+        assert_eq!(status, 200);
+        assert!(result["search"]["depth"].as_u64().unwrap() >= 1);
+        assert_eq!(result["game"]["history"].as_array().unwrap().len(), 0);
+
+        let mv = json!({ "moves": [{ "from": { "x": 2, "y": 5 }, "to": { "x": 4, "y": 3 } }] });
+        assert_eq!(api.handle(&request("POST", "/games/1/moves", &mv.to_string())).0, 400);
+        assert_eq!(api.handle(&request("POST", "/games/1/moves", r#"{"notation": "22-18x"}"#)).0, 400);
+        assert_eq!(api.handle(&request("POST", "/games", r#"{"variant": "international"}"#)).0, 400);
+        assert_eq!(api.handle(&request("GET", "/games/7", "")).0, 404);
+        assert_eq!(api.handle(&request("PUT", "/games/1", "")).0, 405);
+
+        // However deep or long a client asks for, the search is cut short
+        let cap = Some(MAX_SEARCH_TIME);
+        assert_eq!(capped(SearchLimits{ depth: Some(64), time: None }), SearchLimits{ depth: Some(64), time: cap });
+        assert_eq!(capped(SearchLimits{ depth: None, time: Some(Duration::from_secs(3600)) }),
+                   SearchLimits{ depth: None, time: cap });
+        assert_eq!(capped(SearchLimits::default()), SearchLimits{ depth: Some(DEFAULT_DEPTH), time: cap });
+
+        assert_eq!(api.handle(&request("DELETE", "/games/1", "")).0, 200);
+        assert_eq!(api.handle(&request("GET", "/games", "")).1["games"], json!([]));
+    }
+}
@@ -0,0 +1,132 @@
+//! Just enough HTTP/1.1 for the board page and the JSON API: one request per connection, bodies sized by
+//! `Content-Length`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const MAX_BODY_LEN: usize = 1024 * 1024;
+/// Longest request or header line, counting the line ending
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+/// How long a client gets to send its request before the connection is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// `read_line` that gives up rather than buffer more than `MAX_LINE_LEN` bytes
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.take(MAX_LINE_LEN as u64 + 1).read_line(&mut line)? > MAX_LINE_LEN {
+        return Err(invalid("Request line too long"));
+    }
+    Ok(line)
+}
+
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    /// Only the JSON API reads request bodies
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    pub body: Vec<u8>,
+}
+impl Request {
+    pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+        let request_line = read_line(reader)?;
+        let mut headers = HashMap::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.trim_end().is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid("Too many request headers"));
+            }
+            if let Some(idx) = line.find(':') {
+                headers.insert(line[..idx].trim().to_lowercase(), line[idx + 1..].trim().to_string());
+            }
+        }
+
+        let len = headers.get("content-length").and_then(|len| len.parse::<usize>().ok()).unwrap_or(0);
+        if len > MAX_BODY_LEN {
+            return Err(invalid("Request body too long"));
+        }
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body)?;
+
+        let mut words = request_line.split_whitespace();
+        let method = words.next().unwrap_or("").to_string();
+        let path = words.next().unwrap_or("").split('?').next().unwrap().to_string();
+        Ok(Request{ method, path, headers, body })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+pub fn status_text(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+pub fn respond(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, status_text(status), content_type, body.len(), body)?;
+    stream.flush()
+}
+
+/// Reads one request off a fresh connection, keeping the reader for protocols that take over the socket afterwards.
+/// Only the request itself is timed: a WebSocket can sit idle for as long as the players think.
+pub fn accept(stream: &TcpStream) -> io::Result<(Request, BufReader<TcpStream>)> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = Request::read(&mut reader)?;
+    stream.set_read_timeout(None)?;
+    Ok((request, reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request() {
+        let mut input: &[u8] = b"POST /api/games?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}";
+        let request = Request::read(&mut input).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/games");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn test_request_limits() {
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert!(Request::read(&mut long_line.as_bytes()).is_err());
+
+        let long_header = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert!(Request::read(&mut long_header.as_bytes()).is_err());
+
+        let headers = |count| {
+            let lines: String = (0..count).map(|i| format!("X-{}: 1\r\n", i)).collect();
+            format!("GET / HTTP/1.1\r\n{}\r\n", lines)
+        };
+        assert!(Request::read(&mut headers(MAX_HEADERS).as_bytes()).is_ok());
+        assert!(Request::read(&mut headers(MAX_HEADERS + 1).as_bytes()).is_err());
+    }
+}
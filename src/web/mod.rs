@@ -0,0 +1,327 @@
+//! A small embedded HTTP server for playing from a browser.  `GET /` serves a static board page, and `/ws` upgrades to
+//! a WebSocket that speaks the `net::wire` line protocol, one line per text message, so browsers join the game just
+//! like TCP clients do.  The JSON API in `api` (built with the `serde` feature) is served separately.
+
+#[cfg(feature = "serde")]
+pub mod api;
+mod http;
+
+use crate::args::{Args, WebArgs};
+use crate::frontend::Preferences;
+use crate::net::{self, Connection, GameServer, LineReceiver, LineSender};
+
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const INDEX: &str = include_str!("index.html");
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Browsers only ever send short protocol lines, anything bigger is a misbehaving client.
+const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+pub fn run(args: Args, web_args: WebArgs) -> io::Result<()> {
+    let mut players = ["Human", "Human"];
+    if let Some(team) = web_args.cpu {
+        players[team as usize] = "CPU";
+    }
+    let server = net::serve_remote(args, Preferences{ players });
+
+    let listener = TcpListener::bind(&web_args.addr)?;
+    println!("Open http://{}/ in a browser to play", listener.local_addr()?);
+    serve(listener, server)
+}
+
+fn serve(listener: TcpListener, server: GameServer) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = server.clone();
+        thread::spawn(move || {
+            handle_request(stream, &server).ok();
+        });
+    }
+    Ok(())
+}
+
+fn handle_request(mut stream: TcpStream, server: &GameServer) -> io::Result<()> {
+    let (request, reader) = http::accept(&stream)?;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") | ("GET", "/index.html") => http::respond(&mut stream, 200, "text/html; charset=utf-8", INDEX),
+        ("GET", "/ws") => match request.header("sec-websocket-key") {
+            Some(key) if request.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) => {
+                write!(stream, "HTTP/1.1 101 {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                                Sec-WebSocket-Accept: {}\r\n\r\n", http::status_text(101), accept_key(key))?;
+                accept_websocket(stream, reader, server)
+            },
+            _ => http::respond(&mut stream, 400, "text/plain", "Expected a WebSocket upgrade"),
+        },
+        ("GET", _) => http::respond(&mut stream, 404, "text/plain", "Not found"),
+        _ => http::respond(&mut stream, 405, "text/plain", "Method not allowed"),
+    }
+}
+
+fn accept_websocket(stream: TcpStream, reader: BufReader<TcpStream>, server: &GameServer) -> io::Result<()> {
+    let writer = Arc::new(Mutex::new(stream));
+    let mut receiver = WebSocketReceiver{ reader, writer: Arc::clone(&writer) };
+    let hello = match receiver.recv_line()? {
+        Some(line) => net::wire::decode_hello(&line),
+        None => return Ok(()),
+    };
+    let hello = match hello {
+        Some(hello) => hello,
+        None => {
+            write_frame(&mut *writer.lock().unwrap(), OPCODE_CLOSE, &[])?;
+            return Ok(());
+        },
+    };
+
+    let connection = Connection {
+        sender: Box::new(WebSocketSender{ writer }),
+        receiver: Box::new(receiver),
+        hello,
+    };
+    // Once the game is over the connection is refused, and dropping it closes the socket
+    server.add_connection(connection);
+    Ok(())
+}
+
+/// `Sec-WebSocket-Accept` for a client's key, as required by the handshake.
+fn accept_key(key: &str) -> String {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    base64(&hasher.digest().bytes())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (idx, byte)| n | (*byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                result.push(ALPHABET[(n >> (18 - 6 * idx) & 0x3F) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        },
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head)?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        },
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        },
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "WebSocket message too long"));
+    }
+
+    let mut mask = [0u8; 4];
+    let masked = head[1] & 0x80 != 0;
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    if masked {
+        for (idx, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[idx % 4];
+        }
+    }
+
+    Ok(Frame{ fin: head[0] & 0x80 != 0, opcode: head[0] & 0x0F, payload })
+}
+
+struct WebSocketSender {
+    writer: Arc<Mutex<TcpStream>>,
+}
+impl LineSender for WebSocketSender {
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        write_frame(&mut *self.writer.lock().unwrap(), OPCODE_TEXT, line.as_bytes())
+    }
+
+    fn close(&mut self) {
+        let mut stream = self.writer.lock().unwrap();
+        write_frame(&mut *stream, OPCODE_CLOSE, &[]).ok();
+        stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+struct WebSocketReceiver {
+    reader: BufReader<TcpStream>,
+    /// Shared with the sender, to answer pings and closes
+    writer: Arc<Mutex<TcpStream>>,
+}
+impl LineReceiver for WebSocketReceiver {
+    fn recv_line(&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        loop {
+            let frame = match read_frame(&mut self.reader) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_CONTINUATION => {
+                    message.extend_from_slice(&frame.payload);
+                    // Each frame is limited, but so is a message split across many
+                    if message.len() as u64 > MAX_MESSAGE_LEN {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "WebSocket message too long"));
+                    }
+                    if frame.fin {
+                        return Ok(Some(String::from_utf8_lossy(&message).trim_end().to_string()));
+                    }
+                },
+                OPCODE_PING => write_frame(&mut *self.writer.lock().unwrap(), OPCODE_PONG, &frame.payload)?,
+                OPCODE_CLOSE => {
+                    write_frame(&mut *self.writer.lock().unwrap(), OPCODE_CLOSE, &[]).ok();
+                    return Ok(None);
+                },
+                _ => (), // Pongs and binary messages mean nothing to us
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    /// Frames a message the way a browser does, masked.
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(idx, byte)| byte ^ mask[idx % 4]));
+        frame
+    }
+
+    #[test]
+    fn test_handshake_key() {
+        // Example from RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut reader = io::Cursor::new(client_frame(OPCODE_TEXT, b"move 2,5:3,4"));
+        let frame = read_frame(&mut reader).unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OPCODE_TEXT);
+        assert_eq!(frame.payload, b"move 2,5:3,4");
+
+        let long = "x".repeat(300);
+        let mut written = Vec::new();
+        write_frame(&mut written, OPCODE_TEXT, long.as_bytes()).unwrap();
+        assert_eq!(&written[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(read_frame(&mut io::Cursor::new(written)).unwrap().payload, long.as_bytes());
+    }
+
+    #[test]
+    fn test_fragmented_message_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = listener.accept().unwrap().0;
+        let mut receiver = WebSocketReceiver {
+            writer: Arc::new(Mutex::new(stream.try_clone().unwrap())),
+            reader: BufReader::new(stream),
+        };
+
+        // Frames under the limit that add up to a message over it
+        let fragment = vec![b'x'; MAX_MESSAGE_LEN as usize / 2 + 1];
+        thread::spawn(move || {
+            for opcode in [OPCODE_TEXT, OPCODE_CONTINUATION, OPCODE_CONTINUATION] {
+                let mut frame = Vec::new();
+                write_frame(&mut frame, opcode, &fragment).unwrap();
+                frame[0] &= 0x7F; // Not the last
+                if client.write_all(&frame).is_err() {
+                    return;
+                }
+            }
+        });
+        assert_eq!(receiver.recv_line().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_observe_over_websocket() {
+        let args = Args::default();
+        let server = net::serve_remote(args, Preferences{ players: ["Human", "Human"] });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, server));
+
+        let mut page = TcpStream::connect(addr).unwrap();
+        write!(page, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        page.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("<html"));
+
+        let mut socket = TcpStream::connect(addr).unwrap();
+        write!(socket, "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+        socket.write_all(&client_frame(OPCODE_TEXT, b"hello dark")).unwrap();
+
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert!(status.starts_with("HTTP/1.1 101"));
+        while reader.read_line(&mut status).unwrap() > 2 {
+            status.clear();
+        }
+
+        let mut lines = Vec::new();
+        while !lines.iter().any(|line: &String| line.starts_with("board ")) {
+            let frame = read_frame(&mut reader).unwrap();
+            lines.push(String::from_utf8(frame.payload).unwrap());
+        }
+        assert!(lines.contains(&"log The Dark player joined".to_string()));
+    }
+}